@@ -0,0 +1,65 @@
+use bevy::math::IVec2;
+use spellhaven::world_generation::generation_options::GenerationOptionsResource;
+use spellhaven::world_generation::mesh_export::{export_region_mesh, MeshFormat};
+use spellhaven::world_generation::voxel_world::ChunkLod;
+use std::env;
+use std::time::Instant;
+
+/// Usage: `export_mesh [seed] [min] [max] [lod] [output] [--colliders]`, meshes every chunk
+/// touching the area between the `x,z` voxel positions `min` and `max` at the LOD whose voxels
+/// are `lod` voxels wide. The output is an `.obj` or `.glb` file.
+fn main() {
+    let with_collider = env::args().any(|arg| arg == "--colliders");
+    let mut args = env::args().skip(1).filter(|arg| arg != "--colliders");
+    let seed = args
+        .next()
+        .map_or(3, |seed| seed.parse().expect("Seed has to be a number"));
+    let min = parse_position(&args.next().unwrap_or_else(|| "-64,-64".into()));
+    let max = parse_position(&args.next().unwrap_or_else(|| "63,63".into()));
+    let chunk_lod = args.next().map_or(ChunkLod::Full, |lod| {
+        ChunkLod::from_multiplier(lod.parse().expect("Lod has to be a number"))
+            .expect("Lod has to be the width of a LOD voxel, like 1, 2, 4 or 8")
+    });
+    let output = args
+        .next()
+        .unwrap_or_else(|| format!("region_{}.glb", seed));
+    let format = MeshFormat::from_path(&output).expect("Output has to be an .obj or .glb file");
+
+    if with_collider && chunk_lod != ChunkLod::Full {
+        println!("Colliders only exist at full resolution, skipping them");
+    }
+
+    let generation_options = GenerationOptionsResource::from_seed(seed).0;
+    let instant = Instant::now();
+
+    export_region_mesh(
+        &generation_options,
+        min,
+        max,
+        chunk_lod,
+        with_collider,
+        format,
+        &output,
+    )
+    .expect("Failed to write the meshes");
+
+    println!(
+        "Wrote {} from {} to {} in {:.2?}",
+        output,
+        min,
+        max,
+        instant.elapsed()
+    );
+}
+
+fn parse_position(position: &str) -> IVec2 {
+    match position
+        .split(',')
+        .map(|value| value.trim().parse().expect("Positions have to be numbers"))
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        &[x, z] => IVec2::new(x, z),
+        _ => panic!("Positions have to be x,z"),
+    }
+}
@@ -0,0 +1,53 @@
+use bevy::math::{IVec2, IVec3};
+use spellhaven::world_generation::generation_options::GenerationOptionsResource;
+use spellhaven::world_generation::region_export::{export_region_vox, surface_box};
+use std::env;
+use std::time::Instant;
+
+/// Usage: `export_vox [seed] [min] [max] [output]`, writes the box between the `x,y,z` voxel
+/// positions `min` and `max`, inclusive on both ends, into a MagicaVoxel file. With `x,z`
+/// positions the box reaches from below to above the terrain.
+fn main() {
+    let mut args = env::args().skip(1);
+    let seed = args
+        .next()
+        .map_or(3, |seed| seed.parse().expect("Seed has to be a number"));
+    let min = parse_position(&args.next().unwrap_or_else(|| "-64,-64".into()));
+    let max = parse_position(&args.next().unwrap_or_else(|| "63,63".into()));
+    let output = args
+        .next()
+        .unwrap_or_else(|| format!("region_{}.vox", seed));
+
+    let generation_options = GenerationOptionsResource::from_seed(seed).0;
+    let instant = Instant::now();
+
+    let (min, max) = match (min.as_slice(), max.as_slice()) {
+        (&[min_x, min_z], &[max_x, max_z]) => surface_box(
+            &generation_options,
+            IVec2::new(min_x, min_z),
+            IVec2::new(max_x, max_z),
+        ),
+        (&[min_x, min_y, min_z], &[max_x, max_y, max_z]) => (
+            IVec3::new(min_x, min_y, min_z),
+            IVec3::new(max_x, max_y, max_z),
+        ),
+        _ => panic!("Positions have to be either x,z or x,y,z"),
+    };
+
+    export_region_vox(&generation_options, min, max, &output).expect("Failed to write the region");
+
+    println!(
+        "Wrote {} from {} to {} in {:.2?}",
+        output,
+        min,
+        max,
+        instant.elapsed()
+    );
+}
+
+fn parse_position(position: &str) -> Vec<i32> {
+    position
+        .split(',')
+        .map(|value| value.trim().parse().expect("Positions have to be numbers"))
+        .collect()
+}
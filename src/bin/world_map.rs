@@ -0,0 +1,33 @@
+use bevy::math::IVec2;
+use spellhaven::world_generation::generation_options::GenerationOptionsResource;
+use spellhaven::world_generation::world_map::export_map_png;
+use std::env;
+use std::time::Instant;
+
+/// Usage: `world_map [seed] [radius] [output]`, renders the countries within `radius` of the
+/// origin into a png.
+fn main() {
+    let mut args = env::args().skip(1);
+    let seed = args
+        .next()
+        .map_or(3, |seed| seed.parse().expect("Seed has to be a number"));
+    let radius: i32 = args.next().map_or(2, |radius| {
+        radius.parse().expect("Radius has to be a number")
+    });
+    let output = args
+        .next()
+        .unwrap_or_else(|| format!("world_map_{}.png", seed));
+
+    let generation_options = GenerationOptionsResource::from_seed(seed).0;
+    let instant = Instant::now();
+
+    export_map_png(
+        &generation_options,
+        IVec2::splat(-radius),
+        IVec2::splat(radius),
+        &output,
+    )
+    .expect("Failed to write the world map");
+
+    println!("Wrote {} in {:.2?}", output, instant.elapsed());
+}
@@ -1,4 +1,5 @@
 use crate::world_generation::chunk_loading::chunk_loader::ChunkLoader;
+use crate::world_generation::floating_origin::WorldPosition;
 use bevy::app::App;
 use bevy::prelude::{
     default, Camera3dBundle, Commands, Component, Name, Plugin, Query, Startup, Transform,
@@ -29,6 +30,7 @@ fn setup(mut commands: Commands) {
     commands.spawn((
         TransformBundle::from_transform(Transform::from_xyz(0., 0., 0.)),
         ChunkLoader::default(),
+        WorldPosition::default(),
         CameraPivotPoint,
         Name::new("BirdCameraPivot"),
     ));
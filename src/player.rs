@@ -2,7 +2,10 @@ use crate::debug_tools::debug_resource::SpellhavenDebug;
 // use crate::player;
 use crate::ui::ui::UiSpawnCallback;
 use crate::world_generation::chunk_generation::VOXEL_SIZE;
-use crate::world_generation::chunk_loading::chunk_loader::ChunkLoader;
+use crate::world_generation::chunk_loading::chunk_loader::{ChunkLoader, LoadShape};
+use crate::world_generation::floating_origin::{
+    FloatingOriginAnchor, OriginShifted, WorldPosition,
+};
 use bevy::core_pipeline::experimental::taa::{TemporalAntiAliasBundle, TemporalAntiAliasPlugin};
 use bevy::ecs::system::SystemId;
 use bevy::pbr::ScreenSpaceAmbientOcclusionBundle;
@@ -26,7 +29,16 @@ impl Plugin for PlayerPlugin {
         app.add_plugins(TemporalAntiAliasPlugin)
             .insert_resource(Msaa::Off)
             .add_systems(Startup, register_spawn_player_system)
-            .add_systems(Update, (movement, move_camera, move_body));
+            .add_systems(
+                Update,
+                (
+                    movement,
+                    move_camera,
+                    move_body,
+                    update_chunk_loader_view,
+                    shift_camera_focus,
+                ),
+            );
     }
 }
 
@@ -89,7 +101,16 @@ fn spawn_player(
             jumped: false,
             fly: true,
         },
-        ChunkLoader::default(),
+        ChunkLoader {
+            lookahead_time: 2.,
+            load_shape: LoadShape::ViewWeighted {
+                half_fov: PI / 3.,
+                outside_view_factor: 0.5,
+            },
+            ..default()
+        },
+        WorldPosition::default(),
+        FloatingOriginAnchor,
         Name::new("Player"),
     ));
 
@@ -173,6 +194,31 @@ fn move_camera(
     camera.target_focus += difference * 0.25;
 }
 
+fn shift_camera_focus(
+    mut origin_shifted_events: EventReader<OriginShifted>,
+    mut cameras: Query<&mut PanOrbitCamera>,
+) {
+    for origin_shifted in origin_shifted_events.read() {
+        for mut camera in &mut cameras {
+            camera.focus += origin_shifted.offset;
+            camera.target_focus += origin_shifted.offset;
+        }
+    }
+}
+
+fn update_chunk_loader_view(
+    camera: Query<&Transform, With<PlayerCamera>>,
+    mut chunk_loaders: Query<&mut ChunkLoader, With<Player>>,
+) {
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+
+    for mut chunk_loader in &mut chunk_loaders {
+        chunk_loader.view_direction = camera.forward().xz();
+    }
+}
+
 fn movement(
     keyboard_input: Res<ButtonInput<KeyCode>>,
     time: Res<Time>,
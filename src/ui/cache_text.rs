@@ -0,0 +1,36 @@
+use crate::world_generation::chunk_generation::CountryCacheStats;
+use crate::world_generation::generation_options::{
+    GenerationCacheStats, GenerationOptionsResource,
+};
+use bevy::prelude::{Component, Query, Res, Text, With};
+
+#[derive(Component)]
+pub struct CacheText;
+
+pub fn update_cache_ui(
+    mut texts: Query<&mut Text, With<CacheText>>,
+    generation_options: Res<GenerationOptionsResource>,
+    country_cache_stats: Res<CountryCacheStats>,
+) {
+    let caches = [
+        ("Countries", country_cache_stats.0),
+        ("Paths", generation_options.0.path_cache.stats()),
+        ("Structures", generation_options.0.structure_cache.stats()),
+        ("Columns", generation_options.0.column_cache.stats()),
+    ];
+
+    for mut text in &mut texts {
+        text.sections[0].value = caches
+            .iter()
+            .map(|(name, stats)| format_stats(name, stats))
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+}
+
+fn format_stats(name: &str, stats: &GenerationCacheStats) -> String {
+    format!(
+        "{}: {} cached, {} hits, {} misses, {} evicted",
+        name, stats.entries, stats.hits, stats.misses, stats.evictions
+    )
+}
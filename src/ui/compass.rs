@@ -0,0 +1,420 @@
+use crate::player::Player;
+use crate::ui::minimap::player_heading;
+use crate::world_generation::chunk_generation::VOXEL_SIZE;
+use crate::world_generation::chunk_loading::chunk_loader::ChunkLoader;
+use crate::world_generation::chunk_loading::country_cache::{Path, PathLine};
+use crate::world_generation::chunk_loading::road_decoration::{RoadProp, RoadPropKind};
+use crate::world_generation::floating_origin::WorldPosition;
+use crate::world_generation::generation_options::GenerationOptionsResource;
+use crate::world_generation::navigation::Navigation;
+use bevy::prelude::*;
+use std::f32::consts::{FRAC_PI_2, PI, SQRT_2};
+
+const COMPASS_WIDTH: f32 = 600.;
+/// Roads further away than this many voxels are not shown.
+const ROAD_SEARCH_RANGE: i32 = 4096;
+/// The route marker points at the route this many voxels ahead of the player.
+const ROUTE_LOOKAHEAD: f32 = 200.;
+/// Signposts and milestones closer than this many voxels are read out on the compass.
+const ROAD_SIGN_RANGE: f32 = 32.;
+/// Voxels the player moves before the compass looks for the nearest structures and roads again.
+const SEARCH_CELL_SIZE: i32 = 64;
+
+#[derive(Component, Copy, Clone)]
+pub enum CompassMarker {
+    /// Fixed direction, clockwise from north.
+    Direction(f32),
+    NearestCity,
+    NearestRoad,
+    /// Next waypoint of the [`Navigation`] route.
+    Route,
+    /// City on the signpost or milestone next to the player.
+    RoadSign,
+    NearestTreeHouse,
+}
+
+/// Compass targets that are too slow to find every frame. They are searched again once the player
+/// enters another search cell or more countries are done generating.
+#[derive(Default)]
+pub struct CompassSearch {
+    cell: Option<IVec2>,
+    generated_countries: usize,
+    nearest_tree_house: Option<Vec2>,
+    /// Road segments the closest road point can be on while the player stays in the cell.
+    road_segments: Vec<[IVec2; 2]>,
+    /// Signposts and milestones in range of some position of the cell.
+    road_signs: Vec<RoadProp>,
+}
+
+pub fn spawn_compass(commands: &mut Commands) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(15.),
+                left: Val::Percent(50.),
+                margin: UiRect::left(Val::Px(-COMPASS_WIDTH / 2.)),
+                width: Val::Px(COMPASS_WIDTH),
+                height: Val::Px(48.),
+                overflow: Overflow::clip(),
+                ..default()
+            },
+            background_color: Color::rgba(0., 0., 0., 0.4).into(),
+            ..default()
+        })
+        .with_children(|commands| {
+            let markers = [
+                (CompassMarker::Direction(0.), "N", Color::RED),
+                (CompassMarker::Direction(FRAC_PI_2), "E", Color::WHITE),
+                (CompassMarker::Direction(PI), "S", Color::WHITE),
+                (CompassMarker::Direction(-FRAC_PI_2), "W", Color::WHITE),
+                (CompassMarker::NearestCity, "City", Color::ORANGE),
+                (CompassMarker::NearestRoad, "Road", Color::BEIGE),
+                (CompassMarker::Route, "Route", Color::CYAN),
+                (CompassMarker::RoadSign, "Sign", Color::GOLD),
+                (
+                    CompassMarker::NearestTreeHouse,
+                    "Tree house",
+                    Color::LIME_GREEN,
+                ),
+            ];
+
+            for (marker, label, color) in markers {
+                commands.spawn((
+                    TextBundle {
+                        text: Text::from_section(
+                            label,
+                            TextStyle {
+                                font_size: 18.0,
+                                color,
+                                ..default()
+                            },
+                        ),
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            top: Val::Px(if matches!(marker, CompassMarker::Direction(_)) {
+                                2.
+                            } else {
+                                24.
+                            }),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    marker,
+                ));
+            }
+        });
+}
+
+pub fn update_compass(
+    player: Query<(&WorldPosition, &ChunkLoader), With<Player>>,
+    generation_options: Res<GenerationOptionsResource>,
+    navigation: Res<Navigation>,
+    mut search: Local<CompassSearch>,
+    mut markers: Query<(&CompassMarker, &mut Text, &mut Style, &Node)>,
+) {
+    let Ok((player_position, chunk_loader)) = player.get_single() else {
+        return;
+    };
+
+    let position = player_position.voxel_xz().as_vec2();
+    let heading = player_heading(chunk_loader);
+
+    let layout = &generation_options.0.country_layout;
+    let country = layout.country_at(position.as_ivec2());
+    let country_caches = generation_options.generated_country_caches(
+        (-1..=1).flat_map(|x| (-1..=1).map(move |z| country + IVec2::new(x, z))),
+    );
+
+    let nearest_city = country_caches
+        .iter()
+        .map(|country_cache| {
+            (
+                country_cache.structure_cache.city_location.as_vec2(),
+                layout.city_name(country_cache.country_pos),
+            )
+        })
+        .min_by(|(a, _), (b, _)| {
+            a.distance_squared(position)
+                .total_cmp(&b.distance_squared(position))
+        });
+    let waypoint = navigation
+        .route
+        .as_ref()
+        .and_then(|route| route.waypoint(position, ROUTE_LOOKAHEAD));
+
+    let cell = position
+        .as_ivec2()
+        .div_euclid(IVec2::splat(SEARCH_CELL_SIZE));
+    if search.cell != Some(cell) || search.generated_countries != country_caches.len() {
+        search.cell = Some(cell);
+        search.generated_countries = country_caches.len();
+        search.nearest_tree_house = generation_options
+            .0
+            .structure_generator("tree_house")
+            .zip(
+                country_caches
+                    .iter()
+                    .find(|country_cache| country_cache.country_pos == country),
+            )
+            .and_then(|(generator, country_cache)| {
+                country_cache.nearest_structure(
+                    generator,
+                    position.as_ivec2(),
+                    &generation_options.0,
+                )
+            })
+            .map(|instance| instance.center().as_vec2());
+        let merged = country_caches
+            .split_first()
+            .map(|(first, others)| first.merged(others.iter().copied()));
+        search.road_segments = merged
+            .as_ref()
+            .map(|merged| road_segments_near(merged.paths(), cell))
+            .unwrap_or_default();
+        search.road_signs = merged
+            .as_ref()
+            .map(|merged| road_signs_near(merged.road_props(), cell))
+            .unwrap_or_default();
+    }
+    let nearest_tree_house = search.nearest_tree_house;
+    let nearest_road = nearest_road(&search.road_segments, position);
+    let road_sign = nearest_road_sign(&search.road_signs, position).map(|prop| {
+        (
+            layout.capital(prop.target).as_vec2(),
+            layout.city_name(prop.target),
+        )
+    });
+
+    for (marker, mut text, mut style, node) in &mut markers {
+        let target = match marker {
+            CompassMarker::Direction(bearing) => Some(*bearing),
+            CompassMarker::NearestCity => nearest_city.as_ref().map(|(city, name)| {
+                text.sections[0].value =
+                    format!("{} {}", name, format_distance(city.distance(position)));
+                bearing(position, *city)
+            }),
+            CompassMarker::NearestRoad => nearest_road.map(|road| {
+                text.sections[0].value =
+                    format!("Road {}", format_distance(road.distance(position)));
+                bearing(position, road)
+            }),
+            CompassMarker::Route => waypoint.map(|(waypoint, distance_left)| {
+                text.sections[0].value = format!("Route {}", format_distance(distance_left));
+                bearing(position, waypoint)
+            }),
+            CompassMarker::NearestTreeHouse => nearest_tree_house.map(|tree_house| {
+                text.sections[0].value = format!(
+                    "Tree house {}",
+                    format_distance(tree_house.distance(position))
+                );
+                bearing(position, tree_house)
+            }),
+            CompassMarker::RoadSign => road_sign.as_ref().map(|(city, name)| {
+                text.sections[0].value =
+                    format!("To {} {}", name, format_distance(city.distance(position)));
+                bearing(position, *city)
+            }),
+        };
+
+        let relative = target.map(|target| wrap_angle(target - heading));
+        match relative {
+            Some(relative) if relative.abs() <= FRAC_PI_2 => {
+                style.display = Display::Flex;
+                style.left = Val::Px(
+                    COMPASS_WIDTH / 2. + relative / FRAC_PI_2 * COMPASS_WIDTH / 2.
+                        - node.size().x / 2.,
+                );
+            }
+            _ => style.display = Display::None,
+        }
+    }
+}
+
+/// Sample segments of `paths` that can hold the closest road point of any position in the search
+/// cell `cell`. Segments further from the cell center than the closest one plus the cell
+/// diagonal are never closest for a position in the cell.
+fn road_segments_near<'a>(paths: impl Iterator<Item = &'a Path>, cell: IVec2) -> Vec<[IVec2; 2]> {
+    let center = cell * SEARCH_CELL_SIZE + SEARCH_CELL_SIZE / 2;
+    let margin = IVec2::splat(ROAD_SEARCH_RANGE);
+
+    let segments = paths
+        .filter(|path| path.is_in_box(center, margin))
+        .flat_map(|path| &path.lines)
+        .filter(|line| line.is_in_box(center, margin))
+        .flat_map(|line| line.sample_points.windows(2))
+        .map(|points| {
+            let closest = PathLine::get_closest_point_to_line(points[0], points[1], center);
+            ([points[0], points[1]], closest.distance(center.as_vec2()))
+        })
+        .filter(|(_, distance)| *distance <= ROAD_SEARCH_RANGE as f32)
+        .collect::<Vec<_>>();
+
+    let closest = segments
+        .iter()
+        .map(|(_, distance)| *distance)
+        .fold(f32::INFINITY, f32::min);
+    let cell_diagonal = SEARCH_CELL_SIZE as f32 * SQRT_2;
+
+    segments
+        .into_iter()
+        .filter(|(_, distance)| *distance <= closest + cell_diagonal)
+        .map(|(segment, _)| segment)
+        .collect()
+}
+
+/// Closest point to `position` on any of the road `segments`.
+fn nearest_road(segments: &[[IVec2; 2]], position: Vec2) -> Option<Vec2> {
+    segments
+        .iter()
+        .map(|[start, end]| PathLine::get_closest_point_to_line(*start, *end, position.as_ivec2()))
+        .min_by(|a, b| {
+            a.distance_squared(position)
+                .total_cmp(&b.distance_squared(position))
+        })
+}
+
+/// Signposts and milestones of `road_props` in sign range of some position of the search cell
+/// `cell`.
+fn road_signs_near<'a>(
+    road_props: impl Iterator<Item = &'a RoadProp>,
+    cell: IVec2,
+) -> Vec<RoadProp> {
+    let center = (cell * SEARCH_CELL_SIZE).as_vec2() + SEARCH_CELL_SIZE as f32 / 2.;
+    let range = ROAD_SIGN_RANGE + SEARCH_CELL_SIZE as f32 * SQRT_2 / 2.;
+
+    road_props
+        .filter(|prop| prop.kind != RoadPropKind::Lantern)
+        .filter(|prop| prop.position.as_vec2().distance(center) <= range)
+        .copied()
+        .collect()
+}
+
+fn nearest_road_sign(road_signs: &[RoadProp], position: Vec2) -> Option<&RoadProp> {
+    road_signs
+        .iter()
+        .map(|prop| (prop, prop.position.as_vec2().distance(position)))
+        .filter(|(_, distance)| *distance <= ROAD_SIGN_RANGE)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(prop, _)| prop)
+}
+
+/// Clockwise angle between north and the direction from `from` to `to`.
+fn bearing(from: Vec2, to: Vec2) -> f32 {
+    let direction = to - from;
+    direction.x.atan2(-direction.y)
+}
+
+/// Wraps an angle into `-PI..=PI`.
+fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(2. * PI) - PI
+}
+
+fn format_distance(voxels: f32) -> String {
+    let meters = voxels * VOXEL_SIZE;
+    if meters >= 1000. {
+        format!("{:.1} km", meters / 1000.)
+    } else {
+        format!("{:.0} m", meters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(sample_points: Vec<IVec2>) -> Path {
+        let box_pos_start = sample_points.iter().copied().reduce(IVec2::min).unwrap();
+        let box_pos_end = sample_points.iter().copied().reduce(IVec2::max).unwrap();
+
+        Path {
+            lines: vec![PathLine {
+                start: sample_points[0],
+                end: *sample_points.last().unwrap(),
+                spline_one: Vec2::ZERO,
+                spline_two: Vec2::ZERO,
+                box_pos_start,
+                box_pos_end,
+                estimated_length: 0.,
+                heights: vec![0.; sample_points.len()],
+                sample_points,
+            }],
+            box_pos_start,
+            box_pos_end,
+            props: vec![],
+        }
+    }
+
+    #[test]
+    fn cell_road_signs_hold_every_sign_in_range() {
+        let props = (0..200)
+            .map(|index| RoadProp {
+                kind: if index % 3 == 0 {
+                    RoadPropKind::Lantern
+                } else {
+                    RoadPropKind::Signpost
+                },
+                position: IVec2::new(index * 7 % 400 - 200, index * 13 % 400 - 200),
+                height: 0.,
+                target: IVec2::new(index, 0),
+            })
+            .collect::<Vec<_>>();
+
+        for cell in [IVec2::new(0, 0), IVec2::new(-2, 1), IVec2::new(1, -3)] {
+            let road_signs = road_signs_near(props.iter(), cell);
+            assert!(road_signs.len() < props.len() / 2);
+
+            for x in (0..SEARCH_CELL_SIZE).step_by(3) {
+                for z in (0..SEARCH_CELL_SIZE).step_by(3) {
+                    let position = (cell * SEARCH_CELL_SIZE + IVec2::new(x, z)).as_vec2();
+                    let nearest = nearest_road_sign(&road_signs, position);
+                    let expected = props
+                        .iter()
+                        .filter(|prop| prop.kind != RoadPropKind::Lantern)
+                        .filter(|prop| {
+                            prop.position.as_vec2().distance(position) <= ROAD_SIGN_RANGE
+                        })
+                        .min_by(|a, b| {
+                            a.position
+                                .as_vec2()
+                                .distance(position)
+                                .total_cmp(&b.position.as_vec2().distance(position))
+                        });
+                    assert_eq!(
+                        nearest.map(|prop| prop.target),
+                        expected.map(|prop| prop.target)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cell_segments_hold_the_nearest_road_point() {
+        let paths = [
+            path((0..40).map(|x| IVec2::new(x * 16, 100 + x * x)).collect()),
+            path((0..40).map(|z| IVec2::new(-300 + z * z, z * 20)).collect()),
+        ];
+        let all_segments = paths
+            .iter()
+            .flat_map(|path| path.lines[0].sample_points.windows(2))
+            .map(|points| [points[0], points[1]])
+            .collect::<Vec<_>>();
+
+        for cell in [IVec2::new(0, 0), IVec2::new(3, -2), IVec2::new(-4, 5)] {
+            let segments = road_segments_near(paths.iter(), cell);
+            assert!(segments.len() < all_segments.len() / 4);
+
+            for x in (0..SEARCH_CELL_SIZE).step_by(7) {
+                for z in (0..SEARCH_CELL_SIZE).step_by(5) {
+                    let position = (cell * SEARCH_CELL_SIZE + IVec2::new(x, z)).as_vec2();
+                    assert_eq!(
+                        nearest_road(&segments, position),
+                        nearest_road(&all_segments, position)
+                    );
+                }
+            }
+        }
+    }
+}
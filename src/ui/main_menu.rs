@@ -1,8 +1,9 @@
 use crate::player::PlayerSpawnCallback;
-use crate::world_generation::generation_options::GenerationOptionsResource;
+use crate::world_generation::generation_options::disk_cache::DiskCache;
+use crate::world_generation::generation_options::{GenerationOptionsResource, GenerationSettings};
 use bevy::app::App;
 //use bevy::prelude::{info, Commands, Plugin, Res, ResMut, Resource, Update, With, World};
-use bevy::prelude::{info, Commands, Plugin, Res, ResMut, Resource, Update};
+use bevy::prelude::{info, warn, Commands, Plugin, Res, ResMut, Resource, Update};
 // use bevy::window::PrimaryWindow;
 //use bevy_inspector_egui::bevy_egui::{EguiContext, EguiContexts};
 use bevy_inspector_egui::bevy_egui::{EguiContexts};
@@ -67,16 +68,34 @@ fn spawn_main_menu(
 
             ui.text_edit_singleline(&mut menu_state.seed);
             if ui.button("Start").clicked() {
-                let mut hasher = DefaultHasher::new();
-                menu_state.seed.hash(&mut hasher);
-                let seed = hasher.finish();
+                let seed = hash_seed(&menu_state.seed);
 
                 info!("Seed to use: {}", seed);
-                *gen_options = GenerationOptionsResource::from_seed(seed);
+                *gen_options = GenerationOptionsResource::with_settings(
+                    seed,
+                    GenerationSettings {
+                        cache_root: DiskCache::default_root(),
+                        ..Default::default()
+                    },
+                );
 
                 menu_state.state = MainMenuStates::Hidden;
                 let _ = commands.run_system(player_spawn_callback.0);
             }
+            if ui.button("Clear cache").clicked() {
+                let seed = hash_seed(&menu_state.seed);
+
+                match DiskCache::new(seed, DiskCache::default_root()).invalidate() {
+                    Ok(()) => info!("Cleared cache of seed {}", seed),
+                    Err(error) => warn!("Failed to clear cache of seed {}: {}", seed, error),
+                }
+            }
         });
     });
 }
+
+fn hash_seed(seed: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    seed.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,259 @@
+use crate::player::Player;
+use crate::world_generation::chunk_generation::CacheTaskPool;
+use crate::world_generation::chunk_loading::chunk_loader::ChunkLoader;
+use crate::world_generation::chunk_loading::country_cache::CountryCache;
+use crate::world_generation::floating_origin::WorldPosition;
+use crate::world_generation::generation_options::GenerationOptionsResource;
+use crate::world_generation::voxel_world::ChunkLod;
+use crate::world_generation::world_map::{map_image, render_area};
+use bevy::prelude::*;
+use bevy::tasks::Task;
+use futures_lite::future;
+
+const MINIMAP_LOD: ChunkLod = ChunkLod::Sixteenth;
+/// Pixels of the rendered image, large enough to cover the frame at any rotation.
+const MINIMAP_PIXELS: usize = 160;
+/// Screen pixels per image pixel.
+const MINIMAP_SCALE: f32 = 2.;
+const MINIMAP_FRAME_SIZE: f32 = 192.;
+/// The image is rendered again once the player is this many image pixels away from its center.
+const MINIMAP_REDRAW_PIXELS: i32 = 12;
+
+/// Top down view around the player, rendered on the [`CacheTaskPool`]. `N` switches between
+/// north-up and rotating with the view.
+#[derive(Resource)]
+pub struct MinimapState {
+    pub rotating: bool,
+    image: Handle<Image>,
+    /// Voxel position at the center of the current image.
+    center: Option<IVec2>,
+    country: IVec2,
+    /// Whether the country cache of `country` was generated when the image was rendered.
+    with_roads: bool,
+}
+
+impl FromWorld for MinimapState {
+    fn from_world(world: &mut World) -> Self {
+        let image = map_image(vec![0; MINIMAP_PIXELS * MINIMAP_PIXELS * 4], MINIMAP_PIXELS);
+
+        Self {
+            rotating: false,
+            image: world.resource_mut::<Assets<Image>>().add(image),
+            center: None,
+            country: IVec2::ZERO,
+            with_roads: false,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct MinimapTask(Task<Vec<u8>>, IVec2, IVec2, bool);
+
+#[derive(Component)]
+pub struct MinimapRotor;
+
+#[derive(Component)]
+pub struct MinimapImage;
+
+#[derive(Component)]
+pub struct MinimapPlayerMarker;
+
+pub fn spawn_minimap(commands: &mut Commands, minimap_state: &MinimapState) {
+    let image_size = MINIMAP_PIXELS as f32 * MINIMAP_SCALE;
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(15.),
+                right: Val::Px(15.),
+                width: Val::Px(MINIMAP_FRAME_SIZE),
+                height: Val::Px(MINIMAP_FRAME_SIZE),
+                overflow: Overflow::clip(),
+                border: UiRect::all(Val::Px(2.)),
+                ..default()
+            },
+            border_color: Color::BLACK.into(),
+            background_color: Color::DARK_GRAY.into(),
+            ..default()
+        })
+        .with_children(|commands| {
+            commands
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            width: Val::Percent(100.),
+                            height: Val::Percent(100.),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    MinimapRotor,
+                ))
+                .with_children(|commands| {
+                    commands.spawn((
+                        ImageBundle {
+                            image: UiImage::new(minimap_state.image.clone()),
+                            style: Style {
+                                position_type: PositionType::Absolute,
+                                width: Val::Px(image_size),
+                                height: Val::Px(image_size),
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        MinimapImage,
+                    ));
+                });
+            commands.spawn((
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        left: Val::Px(MINIMAP_FRAME_SIZE / 2. - 3.),
+                        top: Val::Px(MINIMAP_FRAME_SIZE / 2. - 8.),
+                        width: Val::Px(6.),
+                        height: Val::Px(16.),
+                        ..default()
+                    },
+                    background_color: Color::YELLOW.into(),
+                    ..default()
+                },
+                MinimapPlayerMarker,
+            ));
+        });
+}
+
+/// Clockwise angle between north and the view direction of the player.
+pub fn player_heading(chunk_loader: &ChunkLoader) -> f32 {
+    let direction = chunk_loader.view_direction;
+    if direction == Vec2::ZERO {
+        return 0.;
+    }
+
+    direction.x.atan2(-direction.y)
+}
+
+pub fn toggle_minimap_rotation(
+    mut minimap_state: ResMut<MinimapState>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::KeyN) {
+        minimap_state.rotating = !minimap_state.rotating;
+    }
+}
+
+pub fn start_minimap_task(
+    mut commands: Commands,
+    minimap_state: Res<MinimapState>,
+    tasks: Query<(), With<MinimapTask>>,
+    player: Query<&WorldPosition, With<Player>>,
+    generation_options: Res<GenerationOptionsResource>,
+    cache_task_pool: Res<CacheTaskPool>,
+) {
+    let Ok(player_position) = player.get_single() else {
+        return;
+    };
+    if !tasks.is_empty() {
+        return;
+    }
+
+    let position = player_position.voxel_xz().as_ivec2();
+    let layout = &generation_options.0.country_layout;
+    let country = layout.country_at(position);
+    let with_roads = !generation_options
+        .generated_country_caches([country])
+        .is_empty();
+
+    let up_to_date = minimap_state.center.is_some_and(|center| {
+        (position - center).abs().max_element()
+            < MINIMAP_REDRAW_PIXELS * MINIMAP_LOD.multiplier_i32()
+            && country == minimap_state.country
+            && (minimap_state.with_roads || !with_roads)
+    });
+    if up_to_date {
+        return;
+    }
+
+    let half_extent = IVec2::splat(MINIMAP_PIXELS as i32 / 2 * MINIMAP_LOD.multiplier_i32());
+    let origin = position - half_extent;
+    let country_caches = generation_options
+        .generated_country_caches(layout.countries_in_area(origin, position + half_extent))
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let arc_generation_options = generation_options.0.clone();
+    let task = cache_task_pool.0.spawn(async move {
+        let country_cache = match country_caches.split_first() {
+            Some((first, rest)) => first.merged(rest),
+            None => CountryCache::without_paths(country, &arc_generation_options),
+        };
+
+        render_area(
+            &arc_generation_options,
+            &country_cache,
+            origin,
+            MINIMAP_LOD,
+            MINIMAP_PIXELS,
+        )
+    });
+
+    commands.spawn(MinimapTask(task, position, country, with_roads));
+}
+
+pub fn set_minimap_image(
+    mut commands: Commands,
+    mut minimap_state: ResMut<MinimapState>,
+    mut tasks: Query<(Entity, &mut MinimapTask)>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    for (entity, mut task) in &mut tasks {
+        let Some(pixels) = future::block_on(future::poll_once(&mut task.0)) else {
+            continue;
+        };
+
+        if let Some(image) = images.get_mut(&minimap_state.image) {
+            image.data = pixels;
+        }
+        minimap_state.center = Some(task.1);
+        minimap_state.country = task.2;
+        minimap_state.with_roads = task.3;
+        commands.entity(entity).despawn();
+    }
+}
+
+pub fn update_minimap(
+    minimap_state: Res<MinimapState>,
+    player: Query<(&WorldPosition, &ChunkLoader), With<Player>>,
+    mut rotors: Query<&mut Transform, (With<MinimapRotor>, Without<MinimapPlayerMarker>)>,
+    mut markers: Query<&mut Transform, (With<MinimapPlayerMarker>, Without<MinimapRotor>)>,
+    mut minimap_images: Query<&mut Style, With<MinimapImage>>,
+) {
+    let (Ok((player_position, chunk_loader)), Some(center)) =
+        (player.get_single(), minimap_state.center)
+    else {
+        return;
+    };
+
+    let heading = player_heading(chunk_loader);
+    let (map_rotation, marker_rotation) = if minimap_state.rotating {
+        (-heading, 0.)
+    } else {
+        (0., heading)
+    };
+
+    for mut transform in &mut rotors {
+        transform.rotation = Quat::from_rotation_z(map_rotation);
+    }
+    for mut transform in &mut markers {
+        transform.rotation = Quat::from_rotation_z(marker_rotation);
+    }
+
+    let player_pixel = MINIMAP_PIXELS as f32 / 2.
+        + (player_position.voxel_xz().as_vec2() - center.as_vec2()) / MINIMAP_LOD.multiplier_f32();
+    for mut style in &mut minimap_images {
+        style.left = Val::Px(MINIMAP_FRAME_SIZE / 2. - player_pixel.x * MINIMAP_SCALE);
+        style.top = Val::Px(MINIMAP_FRAME_SIZE / 2. - player_pixel.y * MINIMAP_SCALE);
+    }
+}
@@ -1,4 +1,8 @@
+mod cache_text;
+mod compass;
 mod fps_text;
 mod main_menu;
+mod minimap;
 mod task_text;
 pub mod ui;
+mod world_map_screen;
@@ -1,6 +1,13 @@
+use crate::ui::cache_text::{update_cache_ui, CacheText};
+use crate::ui::compass::{spawn_compass, update_compass};
 use crate::ui::fps_text::{update_fps_ui, FpsText};
 use crate::ui::main_menu::MainMenuPlugin;
+use crate::ui::minimap::{
+    set_minimap_image, spawn_minimap, start_minimap_task, toggle_minimap_rotation, update_minimap,
+    MinimapState,
+};
 use crate::ui::task_text::{update_task_ui, ChunkTaskText, CountryTaskText};
+use crate::ui::world_map_screen::{show_world_map, toggle_world_map, WorldMapScreen};
 use bevy::app::App;
 use bevy::diagnostic::FrameTimeDiagnosticsPlugin;
 use bevy::ecs::system::SystemId;
@@ -15,7 +22,20 @@ impl Plugin for GameUiPlugin {
             MainMenuPlugin::default(),
         ))
         .add_systems(Startup, register_spawn_ui_system)
-        .add_systems(Update, (update_fps_ui, update_task_ui));
+        .init_resource::<WorldMapScreen>()
+        .init_resource::<MinimapState>()
+        .add_systems(Update, (update_fps_ui, update_task_ui, update_cache_ui))
+        .add_systems(Update, (toggle_world_map, show_world_map).chain())
+        .add_systems(
+            Update,
+            (
+                toggle_minimap_rotation,
+                start_minimap_task,
+                set_minimap_image,
+                update_minimap,
+                update_compass,
+            ),
+        );
     }
 }
 
@@ -27,7 +47,10 @@ fn register_spawn_ui_system(world: &mut World) {
     world.insert_resource(UiSpawnCallback(id));
 }
 
-fn spawn_ui(mut commands: Commands) {
+fn spawn_ui(mut commands: Commands, minimap_state: Res<MinimapState>) {
+    spawn_minimap(&mut commands, &minimap_state);
+    spawn_compass(&mut commands);
+
     commands
         .spawn(NodeBundle {
             style: Style {
@@ -96,5 +119,24 @@ fn spawn_ui(mut commands: Commands) {
                 },
                 ChunkTaskText,
             ));
+            commands.spawn((
+                TextBundle {
+                    text: Text::from_section(
+                        "Caches!",
+                        TextStyle {
+                            font_size: 20.0,
+                            ..default()
+                        },
+                    ),
+                    style: Style {
+                        width: Val::Auto,
+                        height: Val::Auto,
+                        margin: UiRect::new(Val::Auto, Val::Auto, Val::Px(15.0), Val::Px(0.0)),
+                        ..default()
+                    },
+                    ..default()
+                },
+                CacheText,
+            ));
         });
 }
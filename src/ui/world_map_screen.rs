@@ -0,0 +1,209 @@
+use crate::player::Player;
+use crate::world_generation::chunk_loading::country_cache::COUNTRY_SIZE;
+use crate::world_generation::floating_origin::WorldPosition;
+use crate::world_generation::generation_options::GenerationOptionsResource;
+use crate::world_generation::navigation::Navigation;
+use crate::world_generation::world_map::{WorldMap, MAP_LOD, MAP_TILE_SIZE};
+use bevy::input::ButtonInput;
+use bevy::math::{DVec2, IVec2};
+use bevy::prelude::{KeyCode, Query, Res, ResMut, Resource, With};
+use bevy_inspector_egui::bevy_egui::EguiContexts;
+use bevy_inspector_egui::egui;
+use std::collections::HashMap;
+
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 8.;
+/// Country and city names are hidden below this zoom.
+const LABEL_ZOOM: f32 = 0.5;
+/// Clicks within this many points of a city select it as the navigation target.
+const CITY_CLICK_RADIUS: f32 = 12.;
+
+/// Zoomable and pannable view of the [`WorldMap`], toggled with `M`. Clicking a city navigates
+/// to it, right clicking clears the route.
+#[derive(Resource)]
+pub struct WorldMapScreen {
+    open: bool,
+    /// Voxel position shown at the center of the screen.
+    center: DVec2,
+    /// Screen points per map pixel.
+    zoom: f32,
+}
+
+impl Default for WorldMapScreen {
+    fn default() -> Self {
+        Self {
+            open: false,
+            center: DVec2::ZERO,
+            zoom: 1.,
+        }
+    }
+}
+
+pub fn toggle_world_map(
+    mut screen: ResMut<WorldMapScreen>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    player: Query<&WorldPosition, With<Player>>,
+    mut contexts: EguiContexts,
+) {
+    if !keyboard_input.just_pressed(KeyCode::KeyM) || contexts.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+
+    screen.open = !screen.open;
+    if let Ok(player_position) = player.get_single() {
+        screen.center = player_position.voxel_xz();
+    }
+}
+
+pub fn show_world_map(
+    mut screen: ResMut<WorldMapScreen>,
+    mut world_map: ResMut<WorldMap>,
+    generation_options: Res<GenerationOptionsResource>,
+    mut navigation: ResMut<Navigation>,
+    player: Query<&WorldPosition, With<Player>>,
+    mut contexts: EguiContexts,
+) {
+    if !screen.open {
+        return;
+    }
+
+    let textures = world_map
+        .tiles()
+        .map(|(country, handle)| (country, contexts.add_image(handle.clone_weak())))
+        .collect::<HashMap<_, _>>();
+
+    let mut open = screen.open;
+    egui::Window::new("World map")
+        .open(&mut open)
+        .default_size([800., 600.])
+        .show(contexts.ctx_mut(), |ui| {
+            let (response, painter) =
+                ui.allocate_painter(ui.available_size(), egui::Sense::click_and_drag());
+            let rect = response.rect;
+            let voxels_per_point = MAP_LOD.multiplier_f32() as f64 / screen.zoom as f64;
+
+            if response.dragged() {
+                let delta = response.drag_delta();
+                screen.center -= DVec2::new(delta.x as f64, delta.y as f64) * voxels_per_point;
+            }
+            if response.hovered() {
+                let scroll = ui.input(|input| input.raw_scroll_delta.y);
+                screen.zoom = (screen.zoom * (scroll / 200.).exp()).clamp(MIN_ZOOM, MAX_ZOOM);
+            }
+
+            let voxels_per_point = MAP_LOD.multiplier_f32() as f64 / screen.zoom as f64;
+            let center = screen.center;
+            let to_screen = |position: DVec2| {
+                let offset = (position - center) / voxels_per_point;
+                rect.center() + egui::vec2(offset.x as f32, offset.y as f32)
+            };
+            let to_country = |point: egui::Pos2| {
+                let offset = (point - rect.center()) * voxels_per_point as f32;
+                let position = center + DVec2::new(offset.x as f64, offset.y as f64);
+                (position / COUNTRY_SIZE as f64).floor().as_ivec2()
+            };
+
+            let min = to_country(rect.min);
+            let max = to_country(rect.max);
+            let tile_points = MAP_TILE_SIZE as f32 * screen.zoom;
+
+            for x in min.x..=max.x {
+                for z in min.y..=max.y {
+                    let country = IVec2::new(x, z);
+                    let tile_min = to_screen((country * COUNTRY_SIZE as i32).as_dvec2());
+                    let tile_rect =
+                        egui::Rect::from_min_size(tile_min, egui::vec2(tile_points, tile_points));
+
+                    match textures.get(&country) {
+                        Some(texture) => {
+                            painter.image(
+                                *texture,
+                                tile_rect,
+                                egui::Rect::from_min_max(egui::pos2(0., 0.), egui::pos2(1., 1.)),
+                                egui::Color32::WHITE,
+                            );
+                        }
+                        None => {
+                            world_map.request(country);
+                            painter.rect_filled(tile_rect, 0., egui::Color32::from_gray(30));
+                        }
+                    }
+                }
+            }
+
+            let layout = &generation_options.0.country_layout;
+
+            if let Some(route) = &navigation.route {
+                painter.add(egui::Shape::line(
+                    route
+                        .points
+                        .iter()
+                        .map(|point| to_screen(point.as_dvec2()))
+                        .collect(),
+                    egui::Stroke::new(3., egui::Color32::from_rgb(0, 220, 255)),
+                ));
+            }
+            if let Some(target) = navigation.target {
+                painter.circle_stroke(
+                    to_screen(layout.capital(target).as_dvec2()),
+                    CITY_CLICK_RADIUS,
+                    egui::Stroke::new(2., egui::Color32::from_rgb(0, 220, 255)),
+                );
+            }
+
+            if response.secondary_clicked() {
+                navigation.set_target(None);
+            } else if let Some(click) = response
+                .clicked()
+                .then(|| response.interact_pointer_pos())
+                .flatten()
+            {
+                let clicked_city = (min.y - 1..=max.y + 1)
+                    .flat_map(|z| (min.x - 1..=max.x + 1).map(move |x| IVec2::new(x, z)))
+                    .find(|country| {
+                        to_screen(layout.capital(*country).as_dvec2()).distance(click)
+                            <= CITY_CLICK_RADIUS
+                    });
+                if clicked_city.is_some() {
+                    navigation.set_target(clicked_city);
+                }
+            }
+
+            if screen.zoom >= LABEL_ZOOM {
+                for x in min.x - 1..=max.x + 1 {
+                    for z in min.y - 1..=max.y + 1 {
+                        let country = IVec2::new(x, z);
+                        let capital = to_screen(layout.capital(country).as_dvec2());
+                        if !rect.contains(capital) {
+                            continue;
+                        }
+
+                        painter.text(
+                            capital - egui::vec2(0., 12.),
+                            egui::Align2::CENTER_BOTTOM,
+                            layout.country_name(country),
+                            egui::FontId::proportional(18.),
+                            egui::Color32::WHITE,
+                        );
+                        painter.text(
+                            capital + egui::vec2(0., 6.),
+                            egui::Align2::CENTER_TOP,
+                            layout.city_name(country),
+                            egui::FontId::proportional(13.),
+                            egui::Color32::from_gray(230),
+                        );
+                    }
+                }
+            }
+
+            if let Ok(player_position) = player.get_single() {
+                painter.circle(
+                    to_screen(player_position.voxel_xz()),
+                    5.,
+                    egui::Color32::YELLOW,
+                    egui::Stroke::new(1.5, egui::Color32::BLACK),
+                );
+            }
+        });
+    screen.open = open;
+}
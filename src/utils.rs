@@ -12,3 +12,16 @@ pub fn div_floor<
         new
     }
 }
+
+/// Stable hash of a grid position, different salts give independent values for the same position.
+pub fn hash_position(seed: u64, position: bevy::math::IVec2, salt: u64) -> u64 {
+    let mut value = seed
+        ^ (position.x as u32 as u64).wrapping_mul(0x9e3779b97f4a7c15)
+        ^ (position.y as u32 as u64).wrapping_mul(0xc2b2ae3d27d4eb4f)
+        ^ salt.wrapping_mul(0x165667b19e3779f9);
+
+    // splitmix64 finalizer
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+    value ^ (value >> 31)
+}
@@ -1,7 +1,15 @@
+pub mod chunk_events;
 pub mod chunk_generation;
 pub mod chunk_loading;
+pub mod floating_origin;
 pub mod generation_options;
+pub mod mesh_export;
+pub mod naming;
+pub mod navigation;
+pub mod region_export;
+pub mod vox;
 pub mod voxel_world;
+pub mod world_map;
 
 use crate::world_generation::chunk_generation::ChunkGenerationPlugin;
 use bevy::app::App;
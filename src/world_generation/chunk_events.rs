@@ -0,0 +1,85 @@
+use crate::world_generation::chunk_generation::{CHUNK_SIZE, VOXEL_SIZE};
+use crate::world_generation::voxel_world::{ChunkLod, MAX_LOD};
+use bevy::prelude::{Entity, Event, IVec2, Vec3};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ChunkBounds {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl ChunkBounds {
+    /// Bounds of the whole column covered by a quad tree node, without any height limit.
+    pub fn column(parent_pos: IVec2, lod: ChunkLod, lod_position: IVec2) -> Self {
+        let chunk_pos = parent_pos * MAX_LOD.multiplier_i32() + lod_position * lod.multiplier_i32();
+        let chunk_world_size = IVec2::new(CHUNK_SIZE[0] as i32, CHUNK_SIZE[2] as i32).as_vec2()
+            * VOXEL_SIZE
+            * lod.multiplier_f32();
+        let min = (chunk_pos * IVec2::new(CHUNK_SIZE[0] as i32, CHUNK_SIZE[2] as i32)).as_vec2()
+            * VOXEL_SIZE;
+        let max = min + chunk_world_size;
+
+        Self {
+            min: Vec3::new(min.x, f32::MIN, min.y),
+            max: Vec3::new(max.x, f32::MAX, max.y),
+        }
+    }
+
+    pub fn chunk(parent_pos: IVec2, lod: ChunkLod, lod_position: IVec2, min_height: i32) -> Self {
+        let mut bounds = Self::column(parent_pos, lod, lod_position);
+        let voxel_height = VOXEL_SIZE * lod.multiplier_f32();
+
+        bounds.min.y = min_height as f32 * voxel_height;
+        bounds.max.y = (min_height + CHUNK_SIZE[1] as i32) as f32 * voxel_height;
+
+        bounds
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        point.cmpge(self.min).all() && point.cmplt(self.max).all()
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) / 2.
+    }
+}
+
+#[derive(Event, Clone, Debug)]
+pub struct ChunkMeshed {
+    pub entity: Entity,
+    pub chunk_parent: Entity,
+    pub parent_pos: IVec2,
+    pub lod: ChunkLod,
+    pub lod_position: IVec2,
+    pub chunk_height: i32,
+    pub bounds: ChunkBounds,
+}
+
+#[derive(Event, Clone, Debug)]
+pub struct ChunkColliderReady {
+    pub entity: Entity,
+    pub chunk_parent: Entity,
+    pub parent_pos: IVec2,
+    pub lod_position: IVec2,
+    pub chunk_height: i32,
+    pub bounds: ChunkBounds,
+}
+
+/// Sent once the area of a quad tree node is fully rendered at a new LOD and the
+/// chunks of the old LOD got despawned.
+#[derive(Event, Clone, Debug)]
+pub struct ChunkLodChanged {
+    pub chunk_parent: Entity,
+    pub parent_pos: IVec2,
+    pub node_lod: ChunkLod,
+    pub node_position: IVec2,
+    pub lod: ChunkLod,
+    pub bounds: ChunkBounds,
+}
+
+#[derive(Event, Clone, Debug)]
+pub struct ChunkUnloaded {
+    pub chunk_parent: Entity,
+    pub parent_pos: IVec2,
+    pub bounds: ChunkBounds,
+}
@@ -1,19 +1,27 @@
 use crate::debug_tools::debug_resource::SpellhavenDebug;
 use crate::player::Player;
-use crate::utils::div_floor;
+use crate::world_generation::chunk_events::{
+    ChunkBounds, ChunkColliderReady, ChunkLodChanged, ChunkMeshed, ChunkUnloaded,
+};
+use crate::world_generation::chunk_generation::mesh_generation::ChunkMeshes;
 use crate::world_generation::chunk_generation::voxel_generation::get_terrain_noise;
 use crate::world_generation::chunk_loading::chunk_loader::{
-    get_chunk_position, ChunkLoader, ChunkLoaderPlugin,
+    chunk_world_size, get_chunk_position, ChunkLoader, ChunkLoaderPlugin,
 };
 use crate::world_generation::chunk_loading::country_cache::{CountryCache, COUNTRY_SIZE};
 use crate::world_generation::chunk_loading::quad_tree_data::QuadTreeNode;
 use crate::world_generation::chunk_loading::quad_tree_data::QuadTreeNode::{Data, Node};
+use crate::world_generation::floating_origin::{
+    FloatingOrigin, FloatingOriginAnchor, FloatingOriginPlugin, WorldPosition,
+};
 use crate::world_generation::generation_options::{
-    GenerationCacheItem, GenerationOptionsResource, GenerationState,
+    GenerationCacheStats, GenerationOptionsResource, GenerationState,
 };
+use crate::world_generation::navigation::NavigationPlugin;
 use crate::world_generation::voxel_world::{
     ChunkGenerationResult, ChunkLod, QuadTreeVoxelWorld, VoxelWorld, MAX_LOD,
 };
+use crate::world_generation::world_map::WorldMapPlugin;
 use ::noise::NoiseFn;
 use bevy::prelude::*;
 use bevy::tasks::{Task, TaskPool, TaskPoolBuilder};
@@ -22,18 +30,40 @@ use futures_lite::future;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
+pub mod jigsaw;
 pub mod mesh_generation;
 mod noise;
+pub mod structure_model;
+pub mod structure_placement;
 pub mod voxel_generation;
 
 //pub const LEVEL_OF_DETAIL: i32 = 1;
 pub const CHUNK_SIZE: [usize; 3] = [64, 64, 64];
 pub const VOXEL_SIZE: f32 = 0.5;
+pub const COUNTRY_GENERATION_ATTEMPTS: u32 = 3;
+/// Roads change the terrain a few voxels past the border of their country.
+pub const ROAD_INFLUENCE_MARGIN: i32 = 32;
+/// Seconds before the first retry of a failed country, doubled for every further attempt.
+pub const COUNTRY_RETRY_DELAY: f32 = 2.;
+const GLASS_ALPHA: f32 = 0.35;
+/// Luminous power of a single light block, in lumens.
+const LIGHT_BLOCK_INTENSITY: f32 = 4_000.;
+/// Larger clusters of light blocks don't get any brighter than this many blocks.
+const MAX_LIGHT_CLUSTER_BLOCKS: usize = 4;
+const LIGHT_RANGE: f32 = 12.;
+/// Light blocks emit their color times this, enough to stay bright at night.
+const EMISSIVE_STRENGTH: f32 = 3.;
+/// Chunk colliders are only part of the physics world within this many meters of the
+/// [`FloatingOriginAnchor`], so shifting the origin only moves the colliders around it.
+const COLLIDER_RANGE: f32 = 64.;
+/// Colliders are removed again once they are this much further away than [`COLLIDER_RANGE`].
+const COLLIDER_RANGE_MARGIN: f32 = 32.;
 
 pub struct ChunkTaskData {
-    pub mesh: Mesh,
+    pub meshes: ChunkMeshes,
     pub transform: Transform,
     pub collider: Option<Collider>,
+    pub bounds: ChunkBounds,
 }
 
 #[derive(Copy, Clone, PartialEq)]
@@ -43,9 +73,14 @@ pub enum BlockType {
     Grass,
     Sand,
     Path,
+    RetainingWall,
     Snow,
     Gray(u8),
     Custom(u8, u8, u8),
+    /// See-through structure block, like window panes.
+    Glass(u8, u8, u8),
+    /// Structure block that glows in its own color, like lantern lights.
+    Light(u8, u8, u8),
     StructureDebug(u8, u8, u8),
 }
 
@@ -65,13 +100,29 @@ impl BlockType {
             BlockType::Custom(r, g, b) => {
                 [*r as f32 / 255., *g as f32 / 255., *b as f32 / 255., 1.]
             }
+            BlockType::Glass(r, g, b) => [
+                *r as f32 / 255.,
+                *g as f32 / 255.,
+                *b as f32 / 255.,
+                GLASS_ALPHA,
+            ],
+            BlockType::Light(r, g, b) => [*r as f32 / 255., *g as f32 / 255., *b as f32 / 255., 1.],
             BlockType::StructureDebug(r, g, b) => {
                 [*r as f32 / 255., *g as f32 / 255., *b as f32 / 255., 1.]
             }
             BlockType::Path => [100. / 255., 65. / 255., 50. / 255., 1.],
+            BlockType::RetainingWall => [120. / 255., 115. / 255., 105. / 255., 1.],
             BlockType::Snow => [5., 5., 5., 1.],
         }
     }
+
+    pub fn is_transparent(&self) -> bool {
+        matches!(self, BlockType::Glass(..))
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        matches!(self, BlockType::Light(..))
+    }
 }
 
 pub struct ChunkGenerationPlugin;
@@ -82,9 +133,13 @@ impl Resource for ChunkTaskPool {}
 pub struct CacheTaskPool(pub TaskPool);
 impl Resource for CacheTaskPool {}
 
+/// Stats of the country map in [`GenerationOptionsResource`].
+#[derive(Resource, Default)]
+pub struct CountryCacheStats(pub GenerationCacheStats);
+
 impl Plugin for ChunkGenerationPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(ChunkLoaderPlugin)
+        app.add_plugins((ChunkLoaderPlugin, FloatingOriginPlugin, WorldMapPlugin))
             //.add_systems(Startup, setup)
             .add_systems(
                 Update,
@@ -92,7 +147,11 @@ impl Plugin for ChunkGenerationPlugin {
                     set_generated_chunks,
                     start_chunk_tasks,
                     set_generated_caches,
+                    retry_failed_countries,
                     draw_path_gizmos,
+                    evict_column_cache,
+                    evict_country_caches,
+                    update_chunk_colliders,
                 ),
             )
             .add_systems(
@@ -101,6 +160,10 @@ impl Plugin for ChunkGenerationPlugin {
             )
             .add_systems(Update, upgrade_quad_trees.after(set_generated_chunks))
             .add_systems(Startup, setup_gizmo_settings)
+            .add_event::<ChunkMeshed>()
+            .add_event::<ChunkColliderReady>()
+            .add_event::<ChunkLodChanged>()
+            .add_event::<ChunkUnloaded>()
             .insert_resource(QuadTreeVoxelWorld::default())
             .insert_resource(ChunkTaskPool(
                 TaskPoolBuilder::new()
@@ -114,15 +177,18 @@ impl Plugin for ChunkGenerationPlugin {
                     .stack_size(3_000_000)
                     .build(),
             ))
-            .insert_resource(GenerationOptionsResource::default());
+            .insert_resource(GenerationOptionsResource::default())
+            .init_resource::<CountryCacheStats>();
+        app.add_plugins(NavigationPlugin);
     }
 }
 
 #[derive(Component)]
 pub struct ChunkGenerationTask(pub Task<ChunkGenerationResult>, pub Entity);
 
+/// Country position and the number of failed attempts before this one.
 #[derive(Component)]
-pub struct CacheGenerationTask(pub Task<CountryCache>);
+pub struct CacheGenerationTask(pub Task<Result<CountryCache, String>>, pub IVec2, pub u32);
 
 #[derive(Component)]
 pub struct ChunkTaskGenerator(pub IVec2, pub ChunkLod, pub IVec2, pub i32, pub Entity);
@@ -130,6 +196,11 @@ pub struct ChunkTaskGenerator(pub IVec2, pub ChunkLod, pub IVec2, pub i32, pub E
 #[derive(Component)]
 pub struct Chunk(pub [i32; 3]);
 
+/// Collider of a full resolution chunk, added to the physics world with a fixed rigid body while
+/// the chunk is within [`COLLIDER_RANGE`] of the anchor.
+#[derive(Component)]
+pub struct ChunkCollider(pub Collider);
+
 #[derive(Component, Reflect)]
 pub struct ChunkParent(pub [i32; 2]);
 
@@ -143,6 +214,8 @@ fn start_chunk_tasks(
     chunk_task_generators: Query<(Entity, &ChunkTaskGenerator)>,
     chunk_tasks: Query<(), With<ChunkGenerationTask>>,
     mut generation_options: ResMut<GenerationOptionsResource>,
+    chunk_loaders: Query<(&ChunkLoader, &WorldPosition)>,
+    mut country_cache_stats: ResMut<CountryCacheStats>,
 ) {
     let chunk_task_count = chunk_tasks.iter().count();
 
@@ -152,61 +225,93 @@ fn start_chunk_tasks(
 
     let mut current_added_tasks = 0usize;
 
-    let mut chunk_tasks_vec = chunk_task_generators.iter().collect::<Vec<_>>();
-    chunk_tasks_vec.sort_by(|a, b| a.1 .1.usize().cmp(&b.1 .1.usize()));
-
-    for (entity, chunk_task_generator) in chunk_tasks_vec {
+    let mut chunk_tasks_vec = chunk_task_generators
+        .iter()
+        .map(|(entity, chunk_task_generator)| {
+            let chunk_center = ChunkBounds::column(
+                chunk_task_generator.0,
+                chunk_task_generator.1,
+                chunk_task_generator.2,
+            )
+            .center();
+            let priority = chunk_loaders
+                .iter()
+                .map(|(chunk_loader, world_position)| {
+                    chunk_loader.priority(world_position.0, chunk_center.as_dvec3())
+                })
+                .fold(f32::INFINITY, f32::min);
+
+            (entity, chunk_task_generator, priority)
+        })
+        .collect::<Vec<_>>();
+    chunk_tasks_vec.sort_by(|a, b| {
+        a.1 .1
+            .usize()
+            .cmp(&b.1 .1.usize())
+            .then(a.2.total_cmp(&b.2))
+    });
+
+    for (entity, chunk_task_generator, _) in chunk_tasks_vec {
         let parent_pos = chunk_task_generator.0;
-        let country_pos = IVec2::new(
-            div_floor(
-                parent_pos.x,
-                COUNTRY_SIZE as i32 / (MAX_LOD.multiplier_i32() * CHUNK_SIZE[0] as i32),
-            ),
-            div_floor(
-                parent_pos.y,
-                COUNTRY_SIZE as i32 / (MAX_LOD.multiplier_i32() * CHUNK_SIZE[2] as i32),
-            ),
+        let chunk_lod = chunk_task_generator.1;
+        let lod_pos = chunk_task_generator.2;
+
+        let chunk_min = (parent_pos * MAX_LOD.multiplier_i32()
+            + lod_pos * chunk_lod.multiplier_i32())
+            * IVec2::new(CHUNK_SIZE[0] as i32, CHUNK_SIZE[2] as i32);
+        let chunk_max = chunk_min + chunk_lod.multiplier_i32() * CHUNK_SIZE[0] as i32;
+        let countries = generation_options.0.country_layout.countries_in_area(
+            chunk_min - ROAD_INFLUENCE_MARGIN,
+            chunk_max + ROAD_INFLUENCE_MARGIN,
         );
 
-        match generation_options.1.get(&country_pos) {
-            None => {
-                let arc_generation_options = generation_options.0.clone();
-                commands.spawn(CacheGenerationTask(cache_task_pool.0.spawn(async move {
-                    CountryCache::generate(country_pos, &arc_generation_options)
-                })));
+        for country_pos in &countries {
+            if !generation_options.1.contains_key(country_pos) {
+                country_cache_stats.0.misses += 1;
 
-                generation_options
-                    .1
-                    .insert(country_pos, GenerationState::Generating);
+                spawn_country_task(
+                    &mut commands,
+                    &cache_task_pool,
+                    &mut generation_options,
+                    *country_pos,
+                    0,
+                );
             }
-            Some(country_cache) => match country_cache {
-                GenerationState::Generating => {}
-                GenerationState::Some(country_cache) => {
-                    if let Some(mut entity) = commands.get_entity(entity) {
-                        current_added_tasks += 1;
-
-                        let generation_options = generation_options.0.clone();
-                        let chunk_lod = chunk_task_generator.1;
-                        let lod_pos = chunk_task_generator.2;
-                        let height = chunk_task_generator.3;
-                        let country_cache = country_cache.clone();
-                        let task = chunk_task_pool.0.spawn(async move {
-                            QuadTreeVoxelWorld::generate_chunk(
-                                parent_pos,
-                                chunk_lod,
-                                lod_pos,
-                                generation_options,
-                                height,
-                                &country_cache,
-                            )
-                        });
+        }
 
-                        entity
-                            .remove::<ChunkTaskGenerator>()
-                            .insert(ChunkGenerationTask(task, chunk_task_generator.4));
-                    }
-                }
-            },
+        let country_caches = countries
+            .iter()
+            .filter_map(|country_pos| match generation_options.1.get(country_pos) {
+                Some(GenerationState::Some(country_cache)) => Some(country_cache),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        if country_caches.len() < countries.len() {
+            continue;
+        }
+
+        if let Some(mut entity) = commands.get_entity(entity) {
+            current_added_tasks += 1;
+            country_cache_stats.0.hits += 1;
+
+            let country_cache = country_caches[0].merged(country_caches[1..].iter().copied());
+            let generation_options = generation_options.0.clone();
+            let height = chunk_task_generator.3;
+            let task = chunk_task_pool.0.spawn(async move {
+                QuadTreeVoxelWorld::generate_chunk(
+                    parent_pos,
+                    chunk_lod,
+                    lod_pos,
+                    generation_options,
+                    height,
+                    &country_cache,
+                )
+            });
+
+            entity
+                .remove::<ChunkTaskGenerator>()
+                .insert(ChunkGenerationTask(task, chunk_task_generator.4));
         }
 
         if chunk_task_count + current_added_tasks >= 5 {
@@ -219,7 +324,7 @@ fn start_generating_quadtree_chunks(
     mut commands: Commands,
     mut voxel_world: ResMut<QuadTreeVoxelWorld>,
     chunk_generators: Query<(Entity, &ChunkGenerator)>,
-    chunk_loaders: Query<(&ChunkLoader, &Transform)>,
+    chunk_loaders: Query<(&ChunkLoader, &WorldPosition)>,
 ) {
     for (entity, chunk_generator) in &chunk_generators {
         match voxel_world.get_chunk(chunk_generator.0) {
@@ -256,33 +361,17 @@ fn generate_quad_tree_chunk(
     current_lod: ChunkLod,
     current_lod_pos: [i32; 2],
     owner_chunk_pos: [i32; 2],
-    chunk_loaders: &Query<(&ChunkLoader, &Transform)>,
+    chunk_loaders: &Query<(&ChunkLoader, &WorldPosition)>,
     commands: &mut Commands,
     despawn_entities: Vec<Entity>,
 ) -> QuadTreeNode<HashMap<i32, Entity>> {
-    let mut divide = false;
-
-    if current_lod != ChunkLod::Full {
-        for (chunk_loader, transform) in chunk_loaders {
-            let loader_chunk_position = get_chunk_position(transform.translation, current_lod);
-            let current_chunk_pos = [
-                owner_chunk_pos[0] * current_lod.inverse_multiplier_i32() + current_lod_pos[0],
-                owner_chunk_pos[1] * current_lod.inverse_multiplier_i32() + current_lod_pos[1],
-            ];
-            let position_difference = [
-                loader_chunk_position[0] - current_chunk_pos[0],
-                loader_chunk_position[1] - current_chunk_pos[1],
-            ];
-            let current_range = chunk_loader.lod_range[MAX_LOD.usize() - current_lod.usize()];
-            if position_difference[0].abs() <= current_range
-                && position_difference[1].abs() <= current_range
-            {
-                divide = true;
-            }
-        }
-    }
-
-    if divide {
+    if should_divide(
+        current_lod,
+        current_lod_pos,
+        owner_chunk_pos,
+        chunk_loaders,
+        false,
+    ) {
         return Node(
             Box::new(generate_quad_tree_chunk(
                 owner,
@@ -351,11 +440,45 @@ fn generate_quad_tree_chunk(
     Data(map, despawn_entities)
 }
 
+fn should_divide(
+    current_lod: ChunkLod,
+    current_lod_pos: [i32; 2],
+    owner_chunk_pos: [i32; 2],
+    chunk_loaders: &Query<(&ChunkLoader, &WorldPosition)>,
+    is_split: bool,
+) -> bool {
+    if current_lod == ChunkLod::Full {
+        return false;
+    }
+
+    let current_chunk_pos = [
+        owner_chunk_pos[0] * current_lod.inverse_multiplier_i32() + current_lod_pos[0],
+        owner_chunk_pos[1] * current_lod.inverse_multiplier_i32() + current_lod_pos[1],
+    ];
+
+    for (chunk_loader, world_position) in chunk_loaders {
+        for position in
+            chunk_loader.lookahead_positions(world_position.0, chunk_world_size(current_lod))
+        {
+            let loader_chunk_position = get_chunk_position(position, current_lod);
+            let position_difference = IVec2::new(
+                current_chunk_pos[0] - loader_chunk_position[0],
+                current_chunk_pos[1] - loader_chunk_position[1],
+            );
+            if chunk_loader.should_split(position_difference, current_lod, is_split) {
+                return true;
+            }
+        }
+    }
+
+    false
+}
+
 pub(crate) fn upgrade_quad_trees(
     mut commands: Commands,
     mut voxel_world: ResMut<QuadTreeVoxelWorld>,
     chunks: Query<(Entity, &ChunkParent)>,
-    chunk_loaders: Query<(&ChunkLoader, &Transform)>,
+    chunk_loaders: Query<(&ChunkLoader, &WorldPosition)>,
     generated_chunks: Query<Entity, With<Chunk>>,
 ) {
     for chunk in &chunks {
@@ -387,37 +510,19 @@ fn upgrade_tree_recursion(
     current_lod: ChunkLod,
     current_lod_pos: [i32; 2],
     owner_chunk_pos: [i32; 2],
-    chunk_loaders: &Query<(&ChunkLoader, &Transform)>,
+    chunk_loaders: &Query<(&ChunkLoader, &WorldPosition)>,
     commands: &mut Commands,
     generated_chunks: &Query<Entity, With<Chunk>>,
 ) -> QuadTreeNode<HashMap<i32, Entity>> {
     match current_node {
         Data(children, entities) => {
-            let mut divide = false;
-
-            if current_lod != ChunkLod::Full {
-                for (chunk_loader, transform) in chunk_loaders {
-                    let loader_chunk_position =
-                        get_chunk_position(transform.translation, current_lod);
-                    let current_chunk_pos = [
-                        owner_chunk_pos[0] * current_lod.inverse_multiplier_i32()
-                            + current_lod_pos[0],
-                        owner_chunk_pos[1] * current_lod.inverse_multiplier_i32()
-                            + current_lod_pos[1],
-                    ];
-                    let position_difference = [
-                        loader_chunk_position[0] - current_chunk_pos[0],
-                        loader_chunk_position[1] - current_chunk_pos[1],
-                    ];
-                    let current_range =
-                        chunk_loader.lod_range[MAX_LOD.usize() - current_lod.usize()];
-                    if position_difference[0].abs() <= current_range
-                        && position_difference[1].abs() <= current_range
-                    {
-                        divide = true;
-                    }
-                }
-            }
+            let divide = should_divide(
+                current_lod,
+                current_lod_pos,
+                owner_chunk_pos,
+                chunk_loaders,
+                false,
+            );
 
             if !divide {
                 return Data(children.clone(), entities.clone());
@@ -471,31 +576,13 @@ fn upgrade_tree_recursion(
             )
         }
         Node(a, b, c, d, current_mutex, current_entities) => {
-            let mut divide = false;
-
-            if current_lod != ChunkLod::Full {
-                for (chunk_loader, transform) in chunk_loaders {
-                    let loader_chunk_position =
-                        get_chunk_position(transform.translation, current_lod);
-                    let current_chunk_pos = [
-                        owner_chunk_pos[0] * current_lod.inverse_multiplier_i32()
-                            + current_lod_pos[0],
-                        owner_chunk_pos[1] * current_lod.inverse_multiplier_i32()
-                            + current_lod_pos[1],
-                    ];
-                    let position_difference = [
-                        loader_chunk_position[0] - current_chunk_pos[0],
-                        loader_chunk_position[1] - current_chunk_pos[1],
-                    ];
-                    let current_range =
-                        chunk_loader.lod_range[MAX_LOD.usize() - current_lod.usize()];
-                    if position_difference[0].abs() <= current_range
-                        && position_difference[1].abs() <= current_range
-                    {
-                        divide = true;
-                    }
-                }
-            }
+            let divide = should_divide(
+                current_lod,
+                current_lod_pos,
+                owner_chunk_pos,
+                chunk_loaders,
+                true,
+            );
 
             if divide {
                 return Node(
@@ -579,8 +666,8 @@ fn check_entities_for_deletion(
         if generated_chunks.get(entity).is_ok() {
             new_entities.push(entity);
         } else {
-            if let Some(mut entity) = commands.get_entity(entity) {
-                entity.despawn();
+            if let Some(entity) = commands.get_entity(entity) {
+                entity.despawn_recursive();
             } else {
                 new_entities.push(entity);
             }
@@ -614,6 +701,10 @@ fn set_generated_chunks(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut voxel_world: ResMut<QuadTreeVoxelWorld>,
+    mut chunk_meshed_events: EventWriter<ChunkMeshed>,
+    mut chunk_collider_events: EventWriter<ChunkColliderReady>,
+    mut chunk_lod_events: EventWriter<ChunkLodChanged>,
+    floating_origin: Res<FloatingOrigin>,
 ) {
     for (entity, mut task) in &mut chunks {
         if let Some(chunk_task_data_option) = future::block_on(future::poll_once(&mut task.0)) {
@@ -657,21 +748,53 @@ fn set_generated_chunks(
                                     }
                                 } else {
                                     if let Data(_, despawn_entities) = node {
+                                        if !despawn_entities.is_empty() {
+                                            chunk_lod_events.send(ChunkLodChanged {
+                                                chunk_parent: task.1,
+                                                parent_pos: chunk_task_data_option.parent_pos,
+                                                node_lod: chunk_task_data_option.lod,
+                                                node_position: chunk_task_data_option.lod_position,
+                                                lod: chunk_task_data_option.lod,
+                                                bounds: ChunkBounds::column(
+                                                    chunk_task_data_option.parent_pos,
+                                                    chunk_task_data_option.lod,
+                                                    chunk_task_data_option.lod_position,
+                                                ),
+                                            });
+                                        }
+
                                         for despawn_entity in despawn_entities.clone() {
-                                            if let Some(mut entity) =
+                                            if let Some(entity) =
                                                 commands.get_entity(despawn_entity.clone())
                                             {
-                                                entity.despawn();
+                                                entity.despawn_recursive();
                                             }
                                         }
 
                                         despawn_entities.clear();
                                     }
 
+                                    let parent_pos = chunk_task_data_option.parent_pos;
                                     tree.add_to_parent(
                                         tree_depth,
                                         chunk_task_data_option.lod_position.to_array(),
                                         &mut commands,
+                                        &mut |node_depth, node_position| {
+                                            let node_lod = ChunkLod::from_tree_depth(node_depth);
+                                            let node_position = IVec2::from_array(node_position);
+                                            chunk_lod_events.send(ChunkLodChanged {
+                                                chunk_parent: task.1,
+                                                parent_pos,
+                                                node_lod,
+                                                node_position,
+                                                lod: node_lod.previous(),
+                                                bounds: ChunkBounds::column(
+                                                    parent_pos,
+                                                    node_lod,
+                                                    node_position,
+                                                ),
+                                            });
+                                        },
                                     );
                                 }
                             }
@@ -682,13 +805,13 @@ fn set_generated_chunks(
 
             if let Some(mut current_entity) = commands.get_entity(entity) {
                 if let Some(chunk_task_data) = chunk_task_data_option.task_data {
+                    let bounds = chunk_task_data.bounds;
+                    let mut transform = chunk_task_data.transform;
+                    transform.translation =
+                        floating_origin.to_render(transform.translation.as_dvec3());
+                    let chunk_meshes = chunk_task_data.meshes;
                     current_entity.remove::<ChunkGenerationTask>().insert((
-                        PbrBundle {
-                            mesh: meshes.add(chunk_task_data.mesh),
-                            material: materials.add(Color::WHITE),
-                            transform: chunk_task_data.transform,
-                            ..default()
-                        },
+                        SpatialBundle::from_transform(transform),
                         Chunk([
                             chunk_task_data_option.parent_pos[0],
                             chunk_task_data_option.chunk_height,
@@ -696,10 +819,66 @@ fn set_generated_chunks(
                         ]),
                         //SpawnAnimation::default()
                     ));
+                    if let Some(mesh) = chunk_meshes.opaque {
+                        current_entity.insert((meshes.add(mesh), materials.add(Color::WHITE)));
+                    }
+                    current_entity.with_children(|commands| {
+                        if let Some(mesh) = chunk_meshes.transparent {
+                            commands.spawn(PbrBundle {
+                                mesh: meshes.add(mesh),
+                                material: materials.add(StandardMaterial {
+                                    alpha_mode: AlphaMode::Blend,
+                                    ..default()
+                                }),
+                                ..default()
+                            });
+                        }
+                        for (color, mesh) in chunk_meshes.emissive {
+                            commands.spawn(PbrBundle {
+                                mesh: meshes.add(mesh),
+                                material: materials.add(StandardMaterial {
+                                    emissive: color * EMISSIVE_STRENGTH,
+                                    ..default()
+                                }),
+                                ..default()
+                            });
+                        }
+                        for light in chunk_meshes.lights {
+                            commands.spawn(PointLightBundle {
+                                point_light: PointLight {
+                                    color: light.color,
+                                    intensity: LIGHT_BLOCK_INTENSITY
+                                        * light.blocks.min(MAX_LIGHT_CLUSTER_BLOCKS) as f32,
+                                    range: LIGHT_RANGE,
+                                    ..default()
+                                },
+                                transform: Transform::from_translation(light.position),
+                                ..default()
+                            });
+                        }
+                    });
+
+                    chunk_meshed_events.send(ChunkMeshed {
+                        entity,
+                        chunk_parent: task.1,
+                        parent_pos: chunk_task_data_option.parent_pos,
+                        lod: chunk_task_data_option.lod,
+                        lod_position: chunk_task_data_option.lod_position,
+                        chunk_height: chunk_task_data_option.chunk_height,
+                        bounds,
+                    });
 
                     if chunk_task_data_option.lod == ChunkLod::Full {
-                        current_entity
-                            .insert((RigidBody::Fixed, chunk_task_data.collider.unwrap()));
+                        current_entity.insert(ChunkCollider(chunk_task_data.collider.unwrap()));
+
+                        chunk_collider_events.send(ChunkColliderReady {
+                            entity,
+                            chunk_parent: task.1,
+                            parent_pos: chunk_task_data_option.parent_pos,
+                            lod_position: chunk_task_data_option.lod_position,
+                            chunk_height: chunk_task_data_option.chunk_height,
+                            bounds,
+                        });
                     }
                 } else {
                     current_entity.despawn();
@@ -709,22 +888,211 @@ fn set_generated_chunks(
     }
 }
 
+/// Countries that failed [`COUNTRY_GENERATION_ATTEMPTS`] times are generated without roads.
+fn spawn_country_task(
+    commands: &mut Commands,
+    cache_task_pool: &CacheTaskPool,
+    generation_options: &mut GenerationOptionsResource,
+    country_pos: IVec2,
+    attempts: u32,
+) {
+    let arc_generation_options = generation_options.0.clone();
+    commands.spawn(CacheGenerationTask(
+        cache_task_pool.0.spawn(async move {
+            if attempts >= COUNTRY_GENERATION_ATTEMPTS {
+                Ok(CountryCache::without_paths(
+                    country_pos,
+                    &arc_generation_options,
+                ))
+            } else {
+                arc_generation_options
+                    .country_cache
+                    .try_get_cache_entry(country_pos, &arc_generation_options)
+                    .map(|country_cache| country_cache.as_ref().clone())
+            }
+        }),
+        country_pos,
+        attempts,
+    ));
+
+    generation_options
+        .1
+        .insert(country_pos, GenerationState::Generating);
+}
+
 fn set_generated_caches(
     mut commands: Commands,
+    cache_task_pool: Res<CacheTaskPool>,
     mut chunks: Query<(Entity, &mut CacheGenerationTask)>,
     mut generation_options: ResMut<GenerationOptionsResource>,
+    time: Res<Time>,
 ) {
     for (entity, mut task) in &mut chunks {
         if let Some(chunk_task_data_option) = future::block_on(future::poll_once(&mut task.0)) {
-            generation_options.1.insert(
-                chunk_task_data_option.country_pos,
-                GenerationState::Some(chunk_task_data_option),
-            );
+            let country_pos = task.1;
+            let attempts = task.2 + 1;
+
+            let generation_state = match chunk_task_data_option {
+                Ok(country_cache) => GenerationState::Some(country_cache),
+                Err(error) if attempts >= COUNTRY_GENERATION_ATTEMPTS => {
+                    warn!(
+                        "Country {} failed {} times, generating it without roads: {}",
+                        country_pos, attempts, error
+                    );
+                    commands.entity(entity).despawn();
+                    spawn_country_task(
+                        &mut commands,
+                        &cache_task_pool,
+                        &mut generation_options,
+                        country_pos,
+                        attempts,
+                    );
+                    continue;
+                }
+                Err(error) => {
+                    let retry_delay = COUNTRY_RETRY_DELAY * 2f32.powi(attempts as i32 - 1);
+                    warn!(
+                        "Country {} failed to generate, retrying in {}s: {}",
+                        country_pos, retry_delay, error
+                    );
+                    GenerationState::Failed {
+                        error,
+                        attempts,
+                        retry_at: time.elapsed_seconds() + retry_delay,
+                    }
+                }
+            };
+
+            generation_options.1.insert(country_pos, generation_state);
             commands.entity(entity).despawn();
         }
     }
 }
 
+fn retry_failed_countries(
+    mut commands: Commands,
+    cache_task_pool: Res<CacheTaskPool>,
+    mut generation_options: ResMut<GenerationOptionsResource>,
+    time: Res<Time>,
+) {
+    let retries = generation_options
+        .1
+        .iter()
+        .filter_map(|(country_pos, country_cache)| match country_cache {
+            GenerationState::Failed {
+                attempts, retry_at, ..
+            } if *retry_at <= time.elapsed_seconds() => Some((*country_pos, *attempts)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    for (country_pos, attempts) in retries {
+        spawn_country_task(
+            &mut commands,
+            &cache_task_pool,
+            &mut generation_options,
+            country_pos,
+            attempts,
+        );
+    }
+}
+
+fn evict_column_cache(
+    generation_options: Res<GenerationOptionsResource>,
+    mut chunk_unloaded_events: EventReader<ChunkUnloaded>,
+    mut chunk_lod_events: EventReader<ChunkLodChanged>,
+) {
+    let is_in_node =
+        |column: IVec2, parent_pos: IVec2, node_lod: ChunkLod, node_position: IVec2| {
+            let start =
+                parent_pos * MAX_LOD.multiplier_i32() + node_position * node_lod.multiplier_i32();
+            column.cmpge(start).all() && column.cmplt(start + node_lod.multiplier_i32()).all()
+        };
+
+    for chunk_unloaded in chunk_unloaded_events.read() {
+        generation_options.0.column_cache.retain(|(column, _)| {
+            !is_in_node(*column, chunk_unloaded.parent_pos, MAX_LOD, IVec2::ZERO)
+        });
+    }
+
+    // Columns of the other LODs are not rendered anymore once the node changed its LOD.
+    for chunk_lod_changed in chunk_lod_events.read() {
+        generation_options.0.column_cache.retain(|(column, lod)| {
+            *lod == chunk_lod_changed.lod
+                || !is_in_node(
+                    *column,
+                    chunk_lod_changed.parent_pos,
+                    chunk_lod_changed.node_lod,
+                    chunk_lod_changed.node_position,
+                )
+        });
+    }
+}
+
+/// Drops generated countries that are out of range of every chunk loader. Running chunk tasks keep
+/// their own clone of the [`CountryCache`].
+fn evict_country_caches(
+    mut generation_options: ResMut<GenerationOptionsResource>,
+    mut country_cache_stats: ResMut<CountryCacheStats>,
+    chunk_loaders: Query<(&ChunkLoader, &WorldPosition)>,
+) {
+    let loader_countries = chunk_loaders
+        .iter()
+        .map(|(chunk_loader, world_position)| {
+            let country_pos = generation_options
+                .0
+                .country_layout
+                .country_at((world_position.0.xz() / VOXEL_SIZE as f64).as_ivec2());
+            let country_range =
+                chunk_loader.unload_range * MAX_LOD.multiplier_i32() * CHUNK_SIZE[0] as i32
+                    / COUNTRY_SIZE as i32
+                    + 2;
+            (country_pos, country_range)
+        })
+        .collect::<Vec<_>>();
+
+    let country_count = generation_options.1.len();
+    generation_options
+        .1
+        .retain(|country_pos, country_cache| match country_cache {
+            GenerationState::Generating => true,
+            GenerationState::Some(_) | GenerationState::Failed { .. } => loader_countries
+                .iter()
+                .any(|(loader_country, country_range)| {
+                    (*country_pos - *loader_country).abs().max_element() <= *country_range
+                }),
+        });
+
+    country_cache_stats.0.evictions += (country_count - generation_options.1.len()) as u64;
+    country_cache_stats.0.entries = generation_options.1.len();
+}
+
+/// Chunks are in render space and their parents stay at zero, so their translation is where their
+/// minimum corner is rendered.
+fn update_chunk_colliders(
+    mut commands: Commands,
+    anchors: Query<&Transform, With<FloatingOriginAnchor>>,
+    chunks: Query<(Entity, &Transform, &ChunkCollider, Has<RigidBody>)>,
+) {
+    let Some(anchor) = anchors.iter().next() else {
+        return;
+    };
+
+    let half_size = chunk_world_size(ChunkLod::Full) / 2.;
+    for (entity, transform, collider, is_active) in &chunks {
+        let offset = transform.translation.xz() + half_size - anchor.translation.xz();
+        let distance = offset.abs().max_element() - half_size;
+
+        if !is_active && distance < COLLIDER_RANGE {
+            commands
+                .entity(entity)
+                .insert((RigidBody::Fixed, collider.0.clone()));
+        } else if is_active && distance > COLLIDER_RANGE + COLLIDER_RANGE_MARGIN {
+            commands.entity(entity).remove::<(RigidBody, Collider)>();
+        }
+    }
+}
+
 fn setup_gizmo_settings(mut config: ResMut<GizmoConfigStore>) {
     let (config, ..) = config.config_mut::<DefaultGizmoConfigGroup>();
     config.depth_bias = -1.;
@@ -734,8 +1102,9 @@ fn setup_gizmo_settings(mut config: ResMut<GizmoConfigStore>) {
 fn draw_path_gizmos(
     mut gizmos: Gizmos,
     generation_options: Res<GenerationOptionsResource>,
-    players: Query<&Transform, With<Player>>,
+    players: Query<&WorldPosition, With<Player>>,
     debug_resource: Res<SpellhavenDebug>,
+    floating_origin: Res<FloatingOrigin>,
 ) {
     if !debug_resource.show_path_debug {
         return;
@@ -744,21 +1113,16 @@ fn draw_path_gizmos(
     let terrain_noise = get_terrain_noise(ChunkLod::Full, &generation_options.0);
 
     for player in &players {
-        let player_country_pos = (player.translation * VOXEL_SIZE / COUNTRY_SIZE as f32)
-            .floor()
-            .as_ivec3();
-        let player_voxel_pos = (player.translation / VOXEL_SIZE).as_ivec3().xz();
-        match generation_options.1.get(&player_country_pos.xz()) {
+        let player_voxel_pos = (player.0 / VOXEL_SIZE as f64).as_ivec3().xz();
+        let player_country_pos = generation_options
+            .0
+            .country_layout
+            .country_at(player_voxel_pos);
+        match generation_options.1.get(&player_country_pos) {
             None => {}
             Some(country_cache) => match country_cache {
                 GenerationState::Some(country_cache) => {
-                    for path in country_cache
-                        .this_path_cache
-                        .paths
-                        .iter()
-                        .chain(&country_cache.bottom_path_cache.paths)
-                        .chain(&country_cache.left_path_cache.paths)
-                    {
+                    for path in country_cache.paths() {
                         if path.is_in_box(
                             player_voxel_pos,
                             IVec2::ONE * debug_resource.path_show_range,
@@ -776,71 +1140,81 @@ fn draw_path_gizmos(
                                         Color::GREEN
                                     };
                                     gizmos.line(
-                                        Vec3::from((
-                                            path_line.start.as_vec2(),
-                                            terrain_noise.get(path_line.start.as_dvec2().to_array())
-                                                as f32,
-                                        ))
-                                        .xzy()
-                                            * VOXEL_SIZE,
-                                        Vec3::from((
-                                            path_line.end.as_vec2(),
-                                            terrain_noise.get(path_line.end.as_dvec2().to_array())
-                                                as f32,
-                                        ))
-                                        .xzy()
-                                            * VOXEL_SIZE,
-                                        color,
-                                    );
-                                    if is_in_path {
-                                        gizmos.circle(
+                                        floating_origin.voxel_to_render(
                                             Vec3::from((
-                                                path_line.spline_one,
+                                                path_line.start.as_vec2(),
                                                 terrain_noise
-                                                    .get(path_line.spline_one.as_dvec2().to_array())
+                                                    .get(path_line.start.as_dvec2().to_array())
                                                     as f32,
                                             ))
-                                            .xzy()
-                                                * VOXEL_SIZE,
+                                            .xzy(),
+                                        ),
+                                        floating_origin.voxel_to_render(
+                                            Vec3::from((
+                                                path_line.end.as_vec2(),
+                                                terrain_noise
+                                                    .get(path_line.end.as_dvec2().to_array())
+                                                    as f32,
+                                            ))
+                                            .xzy(),
+                                        ),
+                                        color,
+                                    );
+                                    if is_in_path {
+                                        gizmos.circle(
+                                            floating_origin.voxel_to_render(
+                                                Vec3::from((
+                                                    path_line.spline_one,
+                                                    terrain_noise.get(
+                                                        path_line.spline_one.as_dvec2().to_array(),
+                                                    )
+                                                        as f32,
+                                                ))
+                                                .xzy(),
+                                            ),
                                             Direction3d::Y,
                                             debug_resource.path_circle_radius,
                                             Color::GREEN,
                                         );
                                         gizmos.circle(
-                                            Vec3::from((
-                                                path_line.spline_two,
-                                                terrain_noise
-                                                    .get(path_line.spline_two.as_dvec2().to_array())
-                                                    as f32,
-                                            ))
-                                            .xzy()
-                                                * VOXEL_SIZE,
+                                            floating_origin.voxel_to_render(
+                                                Vec3::from((
+                                                    path_line.spline_two,
+                                                    terrain_noise.get(
+                                                        path_line.spline_two.as_dvec2().to_array(),
+                                                    )
+                                                        as f32,
+                                                ))
+                                                .xzy(),
+                                            ),
                                             Direction3d::Y,
                                             debug_resource.path_circle_radius,
                                             Color::RED,
                                         );
                                         gizmos.circle(
-                                            Vec3::from((
-                                                path_line.start.as_vec2(),
-                                                terrain_noise
-                                                    .get(path_line.start.as_dvec2().to_array())
-                                                    as f32,
-                                            ))
-                                            .xzy()
-                                                * VOXEL_SIZE,
+                                            floating_origin.voxel_to_render(
+                                                Vec3::from((
+                                                    path_line.start.as_vec2(),
+                                                    terrain_noise
+                                                        .get(path_line.start.as_dvec2().to_array())
+                                                        as f32,
+                                                ))
+                                                .xzy(),
+                                            ),
                                             Direction3d::Y,
                                             debug_resource.path_circle_radius,
                                             Color::GREEN,
                                         );
                                         gizmos.circle(
-                                            Vec3::from((
-                                                path_line.end.as_vec2(),
-                                                terrain_noise
-                                                    .get(path_line.end.as_dvec2().to_array())
-                                                    as f32,
-                                            ))
-                                            .xzy()
-                                                * VOXEL_SIZE,
+                                            floating_origin.voxel_to_render(
+                                                Vec3::from((
+                                                    path_line.end.as_vec2(),
+                                                    terrain_noise
+                                                        .get(path_line.end.as_dvec2().to_array())
+                                                        as f32,
+                                                ))
+                                                .xzy(),
+                                            ),
                                             Direction3d::Y,
                                             debug_resource.path_circle_radius,
                                             Color::RED,
@@ -850,20 +1224,23 @@ fn draw_path_gizmos(
                                             let start = path_line.sample_points[i - 1];
                                             let end = path_line.sample_points[i];
                                             gizmos.line(
-                                                Vec3::from((
-                                                    start.as_vec2(),
-                                                    terrain_noise.get(start.as_dvec2().to_array())
-                                                        as f32,
-                                                ))
-                                                .xzy()
-                                                    * VOXEL_SIZE,
-                                                Vec3::from((
-                                                    end.as_vec2(),
-                                                    terrain_noise.get(end.as_dvec2().to_array())
-                                                        as f32,
-                                                ))
-                                                .xzy()
-                                                    * VOXEL_SIZE,
+                                                floating_origin.voxel_to_render(
+                                                    Vec3::from((
+                                                        start.as_vec2(),
+                                                        terrain_noise
+                                                            .get(start.as_dvec2().to_array())
+                                                            as f32,
+                                                    ))
+                                                    .xzy(),
+                                                ),
+                                                floating_origin.voxel_to_render(
+                                                    Vec3::from((
+                                                        end.as_vec2(),
+                                                        terrain_noise.get(end.as_dvec2().to_array())
+                                                            as f32,
+                                                    ))
+                                                    .xzy(),
+                                                ),
                                                 Color::RED,
                                             );
                                         }
@@ -872,45 +1249,54 @@ fn draw_path_gizmos(
                                             .closest_point_on_path(player_voxel_pos, IVec2::ONE * 5)
                                         {
                                             gizmos.circle(
-                                                Vec3::from((
-                                                    player_pos_on_path,
-                                                    terrain_noise.get(
-                                                        player_pos_on_path.as_dvec2().to_array(),
-                                                    )
-                                                        as f32,
-                                                ))
-                                                .xzy()
-                                                    * VOXEL_SIZE,
+                                                floating_origin.voxel_to_render(
+                                                    Vec3::from((
+                                                        player_pos_on_path,
+                                                        terrain_noise.get(
+                                                            player_pos_on_path
+                                                                .as_dvec2()
+                                                                .to_array(),
+                                                        )
+                                                            as f32,
+                                                    ))
+                                                    .xzy(),
+                                                ),
                                                 Direction3d::Y,
                                                 debug_resource.path_circle_radius,
                                                 Color::BLUE,
                                             );
                                             gizmos.circle(
-                                                Vec3::from((
-                                                    player_pos_on_path.as_ivec2().as_vec2()
-                                                        + VOXEL_SIZE,
-                                                    terrain_noise.get(
-                                                        player_pos_on_path.as_dvec2().to_array(),
-                                                    )
-                                                        as f32,
-                                                ))
-                                                .xzy()
-                                                    * VOXEL_SIZE,
+                                                floating_origin.voxel_to_render(
+                                                    Vec3::from((
+                                                        player_pos_on_path.as_ivec2().as_vec2()
+                                                            + VOXEL_SIZE,
+                                                        terrain_noise.get(
+                                                            player_pos_on_path
+                                                                .as_dvec2()
+                                                                .to_array(),
+                                                        )
+                                                            as f32,
+                                                    ))
+                                                    .xzy(),
+                                                ),
                                                 Direction3d::Y,
                                                 debug_resource.path_circle_radius,
                                                 Color::CYAN,
                                             );
 
                                             gizmos.circle(
-                                                Vec3::from((
-                                                    player_voxel_pos.as_vec2() + VOXEL_SIZE,
-                                                    terrain_noise.get(
-                                                        player_pos_on_path.as_dvec2().to_array(),
-                                                    )
-                                                        as f32,
-                                                ))
-                                                .xzy()
-                                                    * VOXEL_SIZE,
+                                                floating_origin.voxel_to_render(
+                                                    Vec3::from((
+                                                        player_voxel_pos.as_vec2() + VOXEL_SIZE,
+                                                        terrain_noise.get(
+                                                            player_pos_on_path
+                                                                .as_dvec2()
+                                                                .to_array(),
+                                                        )
+                                                            as f32,
+                                                    ))
+                                                    .xzy(),
+                                                ),
                                                 Direction3d::Y,
                                                 debug_resource.path_circle_radius,
                                                 Color::AQUAMARINE,
@@ -0,0 +1,423 @@
+use crate::world_generation::chunk_generation::structure_placement::StructureInstance;
+use crate::world_generation::chunk_generation::voxel_generation::StructureGenerator;
+use bevy::math::{IVec2, IVec3, Vec3Swizzles};
+use rand::prelude::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+
+/// Opening of a jigsaw piece other pieces can be joined to.
+#[derive(Copy, Clone, Debug)]
+pub struct Connector {
+    /// Model position of the block just inside the opening, as `[x, up, z]`.
+    pub position: IVec3,
+    /// Direction the opening faces, out of the model.
+    pub facing: IVec3,
+    /// Only connectors of the same kind are joined.
+    pub kind: &'static str,
+}
+
+/// Makes a [`StructureGenerator`] build layouts out of its variants, joined at their
+/// [`Connector`]s. Variants with a weight of zero are only used as the start piece.
+#[derive(Copy, Clone, Debug)]
+pub struct JigsawRules {
+    /// Variant every layout starts with, placed on the ground like a normal structure.
+    pub start: usize,
+    pub max_pieces: usize,
+    /// Distance every piece has to stay within from the center of the start piece.
+    pub max_radius: i32,
+}
+
+/// Box taken up by a placed piece, in full resolution voxels.
+#[derive(Copy, Clone, Debug)]
+pub struct PieceBounds {
+    pub min: IVec3,
+    pub max: IVec3,
+}
+
+impl PieceBounds {
+    fn overlaps(&self, other: &PieceBounds) -> bool {
+        self.min.cmplt(other.max).all() && other.min.cmplt(self.max).all()
+    }
+
+    fn contains(&self, other: &PieceBounds) -> bool {
+        self.min.cmple(other.min).all() && self.max.cmpge(other.max).all()
+    }
+}
+
+impl StructureGenerator {
+    /// Farthest a piece of a layout reaches past the footprint of its start piece.
+    pub fn jigsaw_reach(&self) -> i32 {
+        self.jigsaw.map_or(0, |rules| rules.max_radius)
+    }
+
+    pub fn piece_bounds(&self, instance: &StructureInstance) -> PieceBounds {
+        let min = IVec3::new(
+            instance.position.x,
+            instance.base_height as i32,
+            instance.position.y,
+        );
+        PieceBounds {
+            min,
+            max: min
+                + IVec3::new(
+                    instance.size.x,
+                    self.variants[instance.variant].model_size[1],
+                    instance.size.y,
+                ),
+        }
+    }
+
+    /// Grows a layout from `start`, which already stands on the ground. Open connectors are
+    /// filled one after another with a random fitting piece that doesn't overlap the earlier ones
+    /// or `occupied` and passes `fits`, until `max_pieces` is reached or nothing fits anymore.
+    /// There's no layout at all if `start` overlaps `occupied`.
+    pub fn assemble(
+        &self,
+        start: StructureInstance,
+        occupied: &[PieceBounds],
+        fits: impl Fn(&StructureInstance) -> bool,
+    ) -> Vec<StructureInstance> {
+        let Some(rules) = self.jigsaw else {
+            return vec![start];
+        };
+        let start_bounds = self.piece_bounds(&start);
+        if occupied.iter().any(|other| other.overlaps(&start_bounds)) {
+            return vec![];
+        }
+
+        let mut rand = StdRng::seed_from_u64(
+            (self
+                .noise
+                .get_noise(start.position.x as f32, start.position.y as f32)
+                .to_bits() as u64)
+                << 32
+                ^ (start.base_height as i32 as u32 as u64),
+        );
+
+        let center = IVec3::new(start.center().x, start.base_height as i32, start.center().y);
+        let limits = PieceBounds {
+            min: center - rules.max_radius,
+            max: center + rules.max_radius,
+        };
+
+        let mut pieces = vec![start];
+        let mut bounds = vec![start_bounds];
+        let mut open = self.open_connectors(&start, None).collect::<VecDeque<_>>();
+
+        while let Some((position, facing, kind)) = open.pop_front() {
+            if pieces.len() >= rules.max_pieces {
+                break;
+            }
+
+            let target = position + facing;
+            let mut candidates = vec![];
+            for (variant, piece) in self.variants.iter().enumerate() {
+                if piece.weight == 0 {
+                    continue;
+                }
+
+                for rotation in 0..if self.random_rotation { 4 } else { 1 } {
+                    for mirrored in [false, true] {
+                        if mirrored && !self.random_mirroring {
+                            continue;
+                        }
+
+                        let size = if rotation % 2 == 1 {
+                            IVec2::new(piece.model_size[2], piece.model_size[0])
+                        } else {
+                            IVec2::new(piece.model_size[0], piece.model_size[2])
+                        };
+                        let unplaced = StructureInstance {
+                            position: IVec2::ZERO,
+                            size,
+                            variant,
+                            rotation,
+                            mirrored,
+                            base_height: 0.,
+                            ..start
+                        };
+
+                        for (connector_index, connector) in piece.connectors.iter().enumerate() {
+                            if connector.kind != kind {
+                                continue;
+                            }
+                            let (local, local_facing) = unplaced.local_connector(connector);
+                            if local_facing != -facing {
+                                continue;
+                            }
+
+                            let candidate = StructureInstance {
+                                position: target.xz() - local.xz(),
+                                base_height: (target.y - local.y) as f32,
+                                ..unplaced
+                            };
+                            let candidate_bounds = self.piece_bounds(&candidate);
+                            if !limits.contains(&candidate_bounds)
+                                || bounds
+                                    .iter()
+                                    .chain(occupied)
+                                    .any(|other| other.overlaps(&candidate_bounds))
+                                || !fits(&candidate)
+                            {
+                                continue;
+                            }
+
+                            candidates.push((candidate, connector_index, piece.weight));
+                        }
+                    }
+                }
+            }
+
+            let total_weight = candidates.iter().map(|(_, _, weight)| weight).sum::<u32>();
+            if total_weight == 0 {
+                continue;
+            }
+            let mut picked_weight = rand.gen_range(0..total_weight);
+            let Some((piece, connector_index, _)) =
+                candidates.into_iter().find(|(_, _, weight)| {
+                    let is_picked = picked_weight < *weight;
+                    picked_weight = picked_weight.saturating_sub(*weight);
+                    is_picked
+                })
+            else {
+                continue;
+            };
+
+            open.extend(self.open_connectors(&piece, Some(connector_index)));
+            bounds.push(self.piece_bounds(&piece));
+            pieces.push(piece);
+        }
+
+        pieces
+    }
+
+    /// World positions and facings of the connectors of `instance`, except `used`.
+    fn open_connectors<'a>(
+        &'a self,
+        instance: &'a StructureInstance,
+        used: Option<usize>,
+    ) -> impl Iterator<Item = (IVec3, IVec3, &'static str)> + 'a {
+        self.variants[instance.variant]
+            .connectors
+            .iter()
+            .enumerate()
+            .filter(move |(index, _)| Some(*index) != used)
+            .map(|(_, connector)| {
+                let (local, facing) = instance.local_connector(connector);
+                let offset = IVec3::new(
+                    instance.position.x,
+                    instance.base_height as i32,
+                    instance.position.y,
+                );
+                (offset + local, facing, connector.kind)
+            })
+    }
+}
+
+impl StructureInstance {
+    /// Position of `connector` relative to the footprint corner and the base, and the direction it
+    /// faces, both turned and mirrored like the model.
+    fn local_connector(&self, connector: &Connector) -> (IVec3, IVec3) {
+        let local = self.local_position(connector.position.xz());
+        let facing = self.local_position(connector.position.xz() + connector.facing.xz()) - local;
+
+        (
+            IVec3::new(local.x, connector.position.y, local.y),
+            IVec3::new(facing.x, connector.facing.y, facing.y),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_generation::chunk_generation::structure_model::StructureModel;
+    use crate::world_generation::chunk_generation::structure_placement::{
+        FoundationMode, PlacementRules,
+    };
+    use crate::world_generation::chunk_generation::voxel_generation::StructureVariant;
+    use crate::world_generation::chunk_generation::BlockType;
+    use bracket_noise::prelude::FastNoise;
+    use std::sync::Arc;
+
+    fn variant(size: [i32; 3], weight: u32, connectors: Vec<Connector>) -> StructureVariant {
+        StructureVariant {
+            model: Arc::new(StructureModel::new(vec![
+                vec![
+                    vec![
+                        BlockType::Stone;
+                        size[2] as usize
+                    ];
+                    size[1] as usize
+                ];
+                size[0] as usize
+            ])),
+            model_size: size,
+            weight,
+            connectors,
+        }
+    }
+
+    fn connector(position: [i32; 3], facing: IVec3) -> Connector {
+        Connector {
+            position: IVec3::from(position),
+            facing,
+            kind: "tunnel",
+        }
+    }
+
+    fn generator(max_pieces: usize) -> StructureGenerator {
+        StructureGenerator {
+            name: "test",
+            variants: vec![
+                // A room with exits on all sides and a straight tunnel.
+                variant(
+                    [5, 3, 5],
+                    0,
+                    vec![
+                        connector([0, 1, 2], IVec3::NEG_X),
+                        connector([4, 1, 2], IVec3::X),
+                        connector([2, 1, 0], IVec3::NEG_Z),
+                        connector([2, 1, 4], IVec3::Z),
+                    ],
+                ),
+                variant(
+                    [7, 3, 3],
+                    1,
+                    vec![
+                        connector([0, 1, 1], IVec3::NEG_X),
+                        connector([6, 1, 1], IVec3::X),
+                    ],
+                ),
+            ],
+            palettes: vec![],
+            random_rotation: true,
+            random_mirroring: true,
+            noise: FastNoise::new(),
+            generation_size: [100, 100],
+            grid_offset: [0, 0],
+            generate_debug_blocks: false,
+            debug_rgb_multiplier: [0., 0., 0.],
+            placement: PlacementRules {
+                priority: 0,
+                exclusion_radius: 0,
+                road_clearance: 0,
+            },
+            foundation: FoundationMode::Buried,
+            blend_radius: 0,
+            jigsaw: Some(JigsawRules {
+                start: 0,
+                max_pieces,
+                max_radius: 40,
+            }),
+        }
+    }
+
+    fn start() -> StructureInstance {
+        StructureInstance {
+            generator: 0,
+            position: IVec2::new(10, -20),
+            size: IVec2::new(5, 5),
+            variant: 0,
+            rotation: 0,
+            mirrored: false,
+            palette: 0,
+            base_height: 50.,
+            foundation: FoundationMode::Buried,
+            blend_radius: 0,
+        }
+    }
+
+    #[test]
+    fn local_positions_undo_model_positions() {
+        for rotation in 0..4 {
+            for mirrored in [false, true] {
+                let instance = StructureInstance {
+                    size: if rotation % 2 == 1 {
+                        IVec2::new(3, 7)
+                    } else {
+                        IVec2::new(7, 3)
+                    },
+                    rotation,
+                    mirrored,
+                    ..start()
+                };
+                for x in 0..7 {
+                    for z in 0..3 {
+                        let model = IVec2::new(x, z);
+                        assert_eq!(
+                            instance.model_position(instance.local_position(model)),
+                            model
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pieces_join_at_connectors_without_overlapping() {
+        let generator = generator(12);
+        let pieces = generator.assemble(start(), &[], |_| true);
+
+        // Every side of the room gets a tunnel, which can be continued at its far end.
+        assert!(pieces.len() >= 5);
+        assert!(pieces.len() <= 12);
+        assert!(pieces[1..].iter().all(|piece| piece.variant == 1));
+        assert!(pieces.iter().all(|piece| piece.base_height == 50.));
+
+        let bounds = pieces
+            .iter()
+            .map(|piece| generator.piece_bounds(piece))
+            .collect::<Vec<_>>();
+        for (index, piece) in bounds.iter().enumerate() {
+            assert!(bounds[index + 1..]
+                .iter()
+                .all(|other| !piece.overlaps(other)));
+        }
+
+        let connected = |a: &StructureInstance, b: &StructureInstance| {
+            generator
+                .open_connectors(a, None)
+                .any(|(position, facing, _)| {
+                    generator
+                        .open_connectors(b, None)
+                        .any(|(other, other_facing, _)| {
+                            other == position + facing && other_facing == -facing
+                        })
+                })
+        };
+        for piece in &pieces[1..] {
+            assert!(pieces.iter().any(|other| connected(piece, other)));
+        }
+
+        let again = generator.assemble(start(), &[], |_| true);
+        assert_eq!(
+            again.iter().map(|piece| piece.position).collect::<Vec<_>>(),
+            pieces
+                .iter()
+                .map(|piece| piece.position)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn occupied_starts_have_no_layout() {
+        let generator = generator(12);
+        let other = generator.piece_bounds(&StructureInstance {
+            position: IVec2::new(12, -18),
+            ..start()
+        });
+
+        assert!(generator.assemble(start(), &[other], |_| true).is_empty());
+    }
+
+    #[test]
+    fn rejected_pieces_are_left_out() {
+        let generator = generator(12);
+        let pieces = generator.assemble(start(), &[], |piece| piece.position.x >= 10);
+
+        assert!(pieces.len() > 1);
+        assert!(pieces.iter().all(|piece| piece.position.x >= 10));
+    }
+}
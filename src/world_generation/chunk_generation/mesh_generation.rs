@@ -5,6 +5,42 @@ use bevy::render::mesh::{Indices, PrimitiveTopology};
 use bevy::render::render_asset::RenderAssetUsages;
 use rand::Rng;
 
+/// Light blocks closer than this many LOD blocks share one point light.
+const LIGHT_CLUSTER_SIZE: usize = 4;
+/// Coarser chunks are too far away for their point lights to matter.
+const MAX_LIGHT_LOD: ChunkLod = ChunkLod::Half;
+
+/// Meshes of a chunk, split by the material they need.
+///
+/// Glass and light blocks are as solid as opaque ones, so the faces of all three meshes go into
+/// the collider.
+pub struct ChunkMeshes {
+    pub opaque: Option<Mesh>,
+    /// Faces of see-through blocks, rendered with alpha blending.
+    pub transparent: Option<Mesh>,
+    /// Faces of glowing blocks, one mesh per block color for materials emitting that color.
+    pub emissive: Vec<(Color, Mesh)>,
+    /// Point lights for the light blocks, empty above [`MAX_LIGHT_LOD`].
+    pub lights: Vec<ChunkLight>,
+}
+
+/// Point light standing in for a cluster of light blocks.
+pub struct ChunkLight {
+    /// Center of the cluster, in the same space as the mesh positions.
+    pub position: Vec3,
+    pub color: Color,
+    /// Number of light blocks in the cluster.
+    pub blocks: usize,
+}
+
+#[derive(Default)]
+struct MeshData {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    triangles: Vec<[u32; 3]>,
+    colors: Vec<[f32; 4]>,
+}
+
 pub fn generate_mesh(
     generation_result: (
         [[[BlockType; CHUNK_SIZE[2] + 2]; CHUNK_SIZE[1] + 2]; CHUNK_SIZE[0] + 2],
@@ -12,13 +48,13 @@ pub fn generate_mesh(
         bool,
     ),
     chunk_lod: ChunkLod,
-) -> (Option<(Mesh, Vec<Vec3>, Vec<[u32; 3]>)>, bool) {
-    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::all());
-
-    let mut positions: Vec<[f32; 3]> = Vec::new();
-    let mut normals: Vec<[f32; 3]> = Vec::new();
-    let mut triangles: Vec<[u32; 3]> = Vec::new();
-    let mut colors: Vec<[f32; 4]> = Vec::new();
+) -> (Option<(ChunkMeshes, Vec<Vec3>, Vec<[u32; 3]>)>, bool) {
+    let mut opaque = MeshData::default();
+    let mut transparent = MeshData::default();
+    // Light blocks glow in their own color, so their faces are split by block.
+    let mut emissive: Vec<(BlockType, MeshData)> = vec![];
+    // Cluster, sum of the block positions, sum of the block colors and number of blocks.
+    let mut light_clusters: Vec<([usize; 3], Vec3, Vec3, usize)> = vec![];
 
     let mut rng = rand::thread_rng();
 
@@ -27,63 +63,94 @@ pub fn generate_mesh(
     for x in 1..CHUNK_SIZE[0] + 1 {
         for y in 1..CHUNK_SIZE[1] + 1 {
             for z in 1..CHUNK_SIZE[2] + 1 {
-                if blocks[x][y][z] == BlockType::Air || all_neighbours([x, y, z], &blocks) {
+                let block = blocks[x][y][z];
+                if block == BlockType::Air || all_neighbours([x, y, z], &blocks) {
                     continue;
                 }
 
                 let x_pos = x as f32;
                 let y_pos = y as f32;
                 let z_pos = z as f32;
-                let mut color = blocks[x][y][z].get_color();
+                let mut color = block.get_color();
                 color[0] = color[0] * rng.gen_range(0.9..1.);
                 color[1] = color[1] * rng.gen_range(0.9..1.);
                 color[2] = color[2] * rng.gen_range(0.9..1.);
 
-                if blocks[x][y + 1][z] == BlockType::Air {
-                    let positions_count = positions.len() as u32;
+                let mesh_data = if block.is_emissive() {
+                    let cluster = [x, y, z].map(|position| position / LIGHT_CLUSTER_SIZE);
+                    let block_color = Vec4::from_array(block.get_color()).truncate();
+                    let position = Vec3::new(x_pos, y_pos, z_pos);
+                    match light_clusters
+                        .iter_mut()
+                        .find(|(other, ..)| *other == cluster)
+                    {
+                        Some((_, positions, colors, count)) => {
+                            *positions += position;
+                            *colors += block_color;
+                            *count += 1;
+                        }
+                        None => light_clusters.push((cluster, position, block_color, 1)),
+                    }
+
+                    let index = match emissive.iter().position(|(other, _)| *other == block) {
+                        Some(index) => index,
+                        None => {
+                            emissive.push((block, MeshData::default()));
+                            emissive.len() - 1
+                        }
+                    };
+                    &mut emissive[index].1
+                } else if block.is_transparent() {
+                    &mut transparent
+                } else {
+                    &mut opaque
+                };
+
+                if shows_face(block, blocks[x][y + 1][z]) {
+                    let positions_count = mesh_data.positions.len() as u32;
 
                     let aos = [
                         calculate_ambient_occlusion(
-                            blocks[x - 1][y + 1][z] != BlockType::Air,
-                            blocks[x][y + 1][z - 1] != BlockType::Air,
-                            blocks[x - 1][y + 1][z - 1] != BlockType::Air,
+                            occludes(blocks[x - 1][y + 1][z]),
+                            occludes(blocks[x][y + 1][z - 1]),
+                            occludes(blocks[x - 1][y + 1][z - 1]),
                         ),
                         calculate_ambient_occlusion(
-                            blocks[x + 1][y + 1][z] != BlockType::Air,
-                            blocks[x][y + 1][z - 1] != BlockType::Air,
-                            blocks[x + 1][y + 1][z - 1] != BlockType::Air,
+                            occludes(blocks[x + 1][y + 1][z]),
+                            occludes(blocks[x][y + 1][z - 1]),
+                            occludes(blocks[x + 1][y + 1][z - 1]),
                         ),
                         calculate_ambient_occlusion(
-                            blocks[x + 1][y + 1][z] != BlockType::Air,
-                            blocks[x][y + 1][z + 1] != BlockType::Air,
-                            blocks[x + 1][y + 1][z + 1] != BlockType::Air,
+                            occludes(blocks[x + 1][y + 1][z]),
+                            occludes(blocks[x][y + 1][z + 1]),
+                            occludes(blocks[x + 1][y + 1][z + 1]),
                         ),
                         calculate_ambient_occlusion(
-                            blocks[x - 1][y + 1][z] != BlockType::Air,
-                            blocks[x][y + 1][z + 1] != BlockType::Air,
-                            blocks[x - 1][y + 1][z + 1] != BlockType::Air,
+                            occludes(blocks[x - 1][y + 1][z]),
+                            occludes(blocks[x][y + 1][z + 1]),
+                            occludes(blocks[x - 1][y + 1][z + 1]),
                         ),
                     ];
 
-                    add_colors(&mut colors, color, &aos);
+                    add_colors(&mut mesh_data.colors, color, &aos);
 
                     let rotate_quad = (aos[1] + aos[3]) < (aos[0] + aos[2]);
 
-                    positions.extend_from_slice(&[
+                    mesh_data.positions.extend_from_slice(&[
                         [x_pos - 0.5, y_pos + 0.5, z_pos - 0.5],
                         [x_pos + 0.5, y_pos + 0.5, z_pos - 0.5],
                         [x_pos + 0.5, y_pos + 0.5, z_pos + 0.5],
                         [x_pos - 0.5, y_pos + 0.5, z_pos + 0.5],
                     ]);
 
-                    normals.extend_from_slice(&[
+                    mesh_data.normals.extend_from_slice(&[
                         [0., 1., 0.],
                         [0., 1., 0.],
                         [0., 1., 0.],
                         [0., 1., 0.],
                     ]);
 
-                    triangles.extend_from_slice(&[
+                    mesh_data.triangles.extend_from_slice(&[
                         [
                             positions_count + 0,
                             positions_count + (if rotate_quad { 2 } else { 3 }),
@@ -97,51 +164,51 @@ pub fn generate_mesh(
                     ]);
                 }
 
-                if blocks[x][y - 1][z] == BlockType::Air {
-                    let positions_count = positions.len() as u32;
+                if shows_face(block, blocks[x][y - 1][z]) {
+                    let positions_count = mesh_data.positions.len() as u32;
 
                     let aos = [
                         calculate_ambient_occlusion(
-                            blocks[x - 1][y - 1][z] != BlockType::Air,
-                            blocks[x][y - 1][z - 1] != BlockType::Air,
-                            blocks[x - 1][y - 1][z - 1] != BlockType::Air,
+                            occludes(blocks[x - 1][y - 1][z]),
+                            occludes(blocks[x][y - 1][z - 1]),
+                            occludes(blocks[x - 1][y - 1][z - 1]),
                         ),
                         calculate_ambient_occlusion(
-                            blocks[x + 1][y - 1][z] != BlockType::Air,
-                            blocks[x][y - 1][z - 1] != BlockType::Air,
-                            blocks[x + 1][y - 1][z - 1] != BlockType::Air,
+                            occludes(blocks[x + 1][y - 1][z]),
+                            occludes(blocks[x][y - 1][z - 1]),
+                            occludes(blocks[x + 1][y - 1][z - 1]),
                         ),
                         calculate_ambient_occlusion(
-                            blocks[x + 1][y - 1][z] != BlockType::Air,
-                            blocks[x][y - 1][z + 1] != BlockType::Air,
-                            blocks[x + 1][y - 1][z + 1] != BlockType::Air,
+                            occludes(blocks[x + 1][y - 1][z]),
+                            occludes(blocks[x][y - 1][z + 1]),
+                            occludes(blocks[x + 1][y - 1][z + 1]),
                         ),
                         calculate_ambient_occlusion(
-                            blocks[x - 1][y - 1][z] != BlockType::Air,
-                            blocks[x][y - 1][z + 1] != BlockType::Air,
-                            blocks[x - 1][y - 1][z + 1] != BlockType::Air,
+                            occludes(blocks[x - 1][y - 1][z]),
+                            occludes(blocks[x][y - 1][z + 1]),
+                            occludes(blocks[x - 1][y - 1][z + 1]),
                         ),
                     ];
 
-                    add_colors(&mut colors, color, &aos);
+                    add_colors(&mut mesh_data.colors, color, &aos);
 
                     let rotate_quad = (aos[1] + aos[3]) < (aos[0] + aos[2]);
 
-                    positions.extend_from_slice(&[
+                    mesh_data.positions.extend_from_slice(&[
                         [x_pos - 0.5, y_pos - 0.5, z_pos - 0.5],
                         [x_pos + 0.5, y_pos - 0.5, z_pos - 0.5],
                         [x_pos + 0.5, y_pos - 0.5, z_pos + 0.5],
                         [x_pos - 0.5, y_pos - 0.5, z_pos + 0.5],
                     ]);
 
-                    normals.extend_from_slice(&[
+                    mesh_data.normals.extend_from_slice(&[
                         [0., -1., 0.],
                         [0., -1., 0.],
                         [0., -1., 0.],
                         [0., -1., 0.],
                     ]);
 
-                    triangles.extend_from_slice(&[
+                    mesh_data.triangles.extend_from_slice(&[
                         [
                             positions_count + 0,
                             positions_count + 1,
@@ -155,51 +222,51 @@ pub fn generate_mesh(
                     ]);
                 }
 
-                if blocks[x + 1][y][z] == BlockType::Air {
-                    let positions_count = positions.len() as u32;
+                if shows_face(block, blocks[x + 1][y][z]) {
+                    let positions_count = mesh_data.positions.len() as u32;
 
                     let aos = [
                         calculate_ambient_occlusion(
-                            blocks[x + 1][y - 1][z] != BlockType::Air,
-                            blocks[x + 1][y][z - 1] != BlockType::Air,
-                            blocks[x + 1][y - 1][z - 1] != BlockType::Air,
+                            occludes(blocks[x + 1][y - 1][z]),
+                            occludes(blocks[x + 1][y][z - 1]),
+                            occludes(blocks[x + 1][y - 1][z - 1]),
                         ),
                         calculate_ambient_occlusion(
-                            blocks[x + 1][y - 1][z] != BlockType::Air,
-                            blocks[x + 1][y][z + 1] != BlockType::Air,
-                            blocks[x + 1][y - 1][z + 1] != BlockType::Air,
+                            occludes(blocks[x + 1][y - 1][z]),
+                            occludes(blocks[x + 1][y][z + 1]),
+                            occludes(blocks[x + 1][y - 1][z + 1]),
                         ),
                         calculate_ambient_occlusion(
-                            blocks[x + 1][y + 1][z] != BlockType::Air,
-                            blocks[x + 1][y][z + 1] != BlockType::Air,
-                            blocks[x + 1][y + 1][z + 1] != BlockType::Air,
+                            occludes(blocks[x + 1][y + 1][z]),
+                            occludes(blocks[x + 1][y][z + 1]),
+                            occludes(blocks[x + 1][y + 1][z + 1]),
                         ),
                         calculate_ambient_occlusion(
-                            blocks[x + 1][y + 1][z] != BlockType::Air,
-                            blocks[x + 1][y][z - 1] != BlockType::Air,
-                            blocks[x + 1][y + 1][z - 1] != BlockType::Air,
+                            occludes(blocks[x + 1][y + 1][z]),
+                            occludes(blocks[x + 1][y][z - 1]),
+                            occludes(blocks[x + 1][y + 1][z - 1]),
                         ),
                     ];
 
-                    add_colors(&mut colors, color, &aos);
+                    add_colors(&mut mesh_data.colors, color, &aos);
 
                     let rotate_quad = (aos[1] + aos[3]) < (aos[0] + aos[2]);
 
-                    positions.extend_from_slice(&[
+                    mesh_data.positions.extend_from_slice(&[
                         [x_pos + 0.5, y_pos - 0.5, z_pos - 0.5],
                         [x_pos + 0.5, y_pos - 0.5, z_pos + 0.5],
                         [x_pos + 0.5, y_pos + 0.5, z_pos + 0.5],
                         [x_pos + 0.5, y_pos + 0.5, z_pos - 0.5],
                     ]);
 
-                    normals.extend_from_slice(&[
+                    mesh_data.normals.extend_from_slice(&[
                         [1., 0., 0.],
                         [1., 0., 0.],
                         [1., 0., 0.],
                         [1., 0., 0.],
                     ]);
 
-                    triangles.extend_from_slice(&[
+                    mesh_data.triangles.extend_from_slice(&[
                         [
                             positions_count + 0,
                             positions_count + (if rotate_quad { 2 } else { 3 }),
@@ -213,51 +280,51 @@ pub fn generate_mesh(
                     ]);
                 }
 
-                if blocks[x - 1][y][z] == BlockType::Air {
-                    let positions_count = positions.len() as u32;
+                if shows_face(block, blocks[x - 1][y][z]) {
+                    let positions_count = mesh_data.positions.len() as u32;
 
                     let aos = [
                         calculate_ambient_occlusion(
-                            blocks[x - 1][y - 1][z] != BlockType::Air,
-                            blocks[x - 1][y][z - 1] != BlockType::Air,
-                            blocks[x - 1][y - 1][z - 1] != BlockType::Air,
+                            occludes(blocks[x - 1][y - 1][z]),
+                            occludes(blocks[x - 1][y][z - 1]),
+                            occludes(blocks[x - 1][y - 1][z - 1]),
                         ),
                         calculate_ambient_occlusion(
-                            blocks[x - 1][y - 1][z] != BlockType::Air,
-                            blocks[x - 1][y][z + 1] != BlockType::Air,
-                            blocks[x - 1][y - 1][z + 1] != BlockType::Air,
+                            occludes(blocks[x - 1][y - 1][z]),
+                            occludes(blocks[x - 1][y][z + 1]),
+                            occludes(blocks[x - 1][y - 1][z + 1]),
                         ),
                         calculate_ambient_occlusion(
-                            blocks[x - 1][y + 1][z] != BlockType::Air,
-                            blocks[x - 1][y][z + 1] != BlockType::Air,
-                            blocks[x - 1][y + 1][z + 1] != BlockType::Air,
+                            occludes(blocks[x - 1][y + 1][z]),
+                            occludes(blocks[x - 1][y][z + 1]),
+                            occludes(blocks[x - 1][y + 1][z + 1]),
                         ),
                         calculate_ambient_occlusion(
-                            blocks[x - 1][y + 1][z] != BlockType::Air,
-                            blocks[x - 1][y][z - 1] != BlockType::Air,
-                            blocks[x - 1][y + 1][z - 1] != BlockType::Air,
+                            occludes(blocks[x - 1][y + 1][z]),
+                            occludes(blocks[x - 1][y][z - 1]),
+                            occludes(blocks[x - 1][y + 1][z - 1]),
                         ),
                     ];
 
-                    add_colors(&mut colors, color, &aos);
+                    add_colors(&mut mesh_data.colors, color, &aos);
 
                     let rotate_quad = (aos[1] + aos[3]) < (aos[0] + aos[2]);
 
-                    positions.extend_from_slice(&[
+                    mesh_data.positions.extend_from_slice(&[
                         [x_pos - 0.5, y_pos - 0.5, z_pos - 0.5],
                         [x_pos - 0.5, y_pos - 0.5, z_pos + 0.5],
                         [x_pos - 0.5, y_pos + 0.5, z_pos + 0.5],
                         [x_pos - 0.5, y_pos + 0.5, z_pos - 0.5],
                     ]);
 
-                    normals.extend_from_slice(&[
+                    mesh_data.normals.extend_from_slice(&[
                         [-1., 0., 0.],
                         [-1., 0., 0.],
                         [-1., 0., 0.],
                         [-1., 0., 0.],
                     ]);
 
-                    triangles.extend_from_slice(&[
+                    mesh_data.triangles.extend_from_slice(&[
                         [
                             positions_count + 0,
                             positions_count + 1,
@@ -271,51 +338,51 @@ pub fn generate_mesh(
                     ]);
                 }
 
-                if blocks[x][y][z + 1] == BlockType::Air {
-                    let positions_count = positions.len() as u32;
+                if shows_face(block, blocks[x][y][z + 1]) {
+                    let positions_count = mesh_data.positions.len() as u32;
 
                     let aos = [
                         calculate_ambient_occlusion(
-                            blocks[x - 1][y][z + 1] != BlockType::Air,
-                            blocks[x][y - 1][z + 1] != BlockType::Air,
-                            blocks[x - 1][y - 1][z + 1] != BlockType::Air,
+                            occludes(blocks[x - 1][y][z + 1]),
+                            occludes(blocks[x][y - 1][z + 1]),
+                            occludes(blocks[x - 1][y - 1][z + 1]),
                         ),
                         calculate_ambient_occlusion(
-                            blocks[x - 1][y][z + 1] != BlockType::Air,
-                            blocks[x][y + 1][z + 1] != BlockType::Air,
-                            blocks[x - 1][y + 1][z + 1] != BlockType::Air,
+                            occludes(blocks[x - 1][y][z + 1]),
+                            occludes(blocks[x][y + 1][z + 1]),
+                            occludes(blocks[x - 1][y + 1][z + 1]),
                         ),
                         calculate_ambient_occlusion(
-                            blocks[x + 1][y][z + 1] != BlockType::Air,
-                            blocks[x][y + 1][z + 1] != BlockType::Air,
-                            blocks[x + 1][y + 1][z + 1] != BlockType::Air,
+                            occludes(blocks[x + 1][y][z + 1]),
+                            occludes(blocks[x][y + 1][z + 1]),
+                            occludes(blocks[x + 1][y + 1][z + 1]),
                         ),
                         calculate_ambient_occlusion(
-                            blocks[x + 1][y][z + 1] != BlockType::Air,
-                            blocks[x][y - 1][z + 1] != BlockType::Air,
-                            blocks[x + 1][y - 1][z + 1] != BlockType::Air,
+                            occludes(blocks[x + 1][y][z + 1]),
+                            occludes(blocks[x][y - 1][z + 1]),
+                            occludes(blocks[x + 1][y - 1][z + 1]),
                         ),
                     ];
 
-                    add_colors(&mut colors, color, &aos);
+                    add_colors(&mut mesh_data.colors, color, &aos);
 
                     let rotate_quad = (aos[1] + aos[3]) < (aos[0] + aos[2]);
 
-                    positions.extend_from_slice(&[
+                    mesh_data.positions.extend_from_slice(&[
                         [x_pos - 0.5, y_pos - 0.5, z_pos + 0.5],
                         [x_pos - 0.5, y_pos + 0.5, z_pos + 0.5],
                         [x_pos + 0.5, y_pos + 0.5, z_pos + 0.5],
                         [x_pos + 0.5, y_pos - 0.5, z_pos + 0.5],
                     ]);
 
-                    normals.extend_from_slice(&[
+                    mesh_data.normals.extend_from_slice(&[
                         [0., 0., 1.],
                         [0., 0., 1.],
                         [0., 0., 1.],
                         [0., 0., 1.],
                     ]);
 
-                    triangles.extend_from_slice(&[
+                    mesh_data.triangles.extend_from_slice(&[
                         [
                             positions_count + 0,
                             positions_count + (if rotate_quad { 2 } else { 3 }),
@@ -329,51 +396,51 @@ pub fn generate_mesh(
                     ]);
                 }
 
-                if blocks[x][y][z - 1] == BlockType::Air {
-                    let positions_count = positions.len() as u32;
+                if shows_face(block, blocks[x][y][z - 1]) {
+                    let positions_count = mesh_data.positions.len() as u32;
 
                     let aos = [
                         calculate_ambient_occlusion(
-                            blocks[x - 1][y][z - 1] != BlockType::Air,
-                            blocks[x][y - 1][z - 1] != BlockType::Air,
-                            blocks[x - 1][y - 1][z - 1] != BlockType::Air,
+                            occludes(blocks[x - 1][y][z - 1]),
+                            occludes(blocks[x][y - 1][z - 1]),
+                            occludes(blocks[x - 1][y - 1][z - 1]),
                         ),
                         calculate_ambient_occlusion(
-                            blocks[x - 1][y][z - 1] != BlockType::Air,
-                            blocks[x][y + 1][z - 1] != BlockType::Air,
-                            blocks[x - 1][y + 1][z - 1] != BlockType::Air,
+                            occludes(blocks[x - 1][y][z - 1]),
+                            occludes(blocks[x][y + 1][z - 1]),
+                            occludes(blocks[x - 1][y + 1][z - 1]),
                         ),
                         calculate_ambient_occlusion(
-                            blocks[x + 1][y][z - 1] != BlockType::Air,
-                            blocks[x][y + 1][z - 1] != BlockType::Air,
-                            blocks[x + 1][y + 1][z - 1] != BlockType::Air,
+                            occludes(blocks[x + 1][y][z - 1]),
+                            occludes(blocks[x][y + 1][z - 1]),
+                            occludes(blocks[x + 1][y + 1][z - 1]),
                         ),
                         calculate_ambient_occlusion(
-                            blocks[x + 1][y][z - 1] != BlockType::Air,
-                            blocks[x][y - 1][z - 1] != BlockType::Air,
-                            blocks[x + 1][y - 1][z - 1] != BlockType::Air,
+                            occludes(blocks[x + 1][y][z - 1]),
+                            occludes(blocks[x][y - 1][z - 1]),
+                            occludes(blocks[x + 1][y - 1][z - 1]),
                         ),
                     ];
 
-                    add_colors(&mut colors, color, &aos);
+                    add_colors(&mut mesh_data.colors, color, &aos);
 
                     let rotate_quad = (aos[1] + aos[3]) < (aos[0] + aos[2]);
 
-                    positions.extend_from_slice(&[
+                    mesh_data.positions.extend_from_slice(&[
                         [x_pos - 0.5, y_pos - 0.5, z_pos - 0.5],
                         [x_pos - 0.5, y_pos + 0.5, z_pos - 0.5],
                         [x_pos + 0.5, y_pos + 0.5, z_pos - 0.5],
                         [x_pos + 0.5, y_pos - 0.5, z_pos - 0.5],
                     ]);
 
-                    normals.extend_from_slice(&[
+                    mesh_data.normals.extend_from_slice(&[
                         [0., 0., -1.],
                         [0., 0., -1.],
                         [0., 0., -1.],
                         [0., 0., -1.],
                     ]);
 
-                    triangles.extend_from_slice(&[
+                    mesh_data.triangles.extend_from_slice(&[
                         [
                             positions_count + 0,
                             positions_count + 1,
@@ -390,75 +457,140 @@ pub fn generate_mesh(
         }
     }
 
-    if triangles.is_empty() {
+    if opaque.triangles.is_empty()
+        && transparent.triangles.is_empty()
+        && emissive
+            .iter()
+            .all(|(_, mesh_data)| mesh_data.triangles.is_empty())
+    {
         return (None, generate_more);
     }
 
-    for position in positions.iter_mut() {
-        position[0] = (position[0] - 0.5) * VOXEL_SIZE * chunk_lod.multiplier_f32() + 0.5;
-        position[1] =
-            (position[1] + min_height as f32 - 0.5) * VOXEL_SIZE * chunk_lod.multiplier_f32() + 0.5;
-        position[2] = (position[2] - 0.5) * VOXEL_SIZE * chunk_lod.multiplier_f32() + 0.5;
-    }
-
-    let mut mesh_triangles: Vec<u32> = Vec::new();
-
-    for triangle in &triangles {
-        mesh_triangles.push(triangle[0]);
-        mesh_triangles.push(triangle[1]);
-        mesh_triangles.push(triangle[2]);
-    }
-
-    let collider_positions = if chunk_lod == ChunkLod::Full {
-        positions
-            .clone()
+    let lights = if chunk_lod.usize() <= MAX_LIGHT_LOD.usize() {
+        light_clusters
             .iter()
-            .map(|position| Vec3::new(position[0], position[1], position[2]))
+            .map(|(_, positions, colors, count)| {
+                let position = *positions / *count as f32;
+                let color = *colors / *count as f32;
+                ChunkLight {
+                    position: Vec3::from_array(to_chunk_space(
+                        position.to_array(),
+                        min_height,
+                        chunk_lod,
+                    )),
+                    color: Color::rgb(color.x, color.y, color.z),
+                    blocks: *count,
+                }
+            })
             .collect()
     } else {
-        Vec::new()
+        vec![]
     };
 
-    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    let mut collider_positions = Vec::new();
+    let mut collider_triangles = Vec::new();
+    let mut to_mesh = |mut mesh_data: MeshData| {
+        for position in mesh_data.positions.iter_mut() {
+            *position = to_chunk_space(*position, min_height, chunk_lod);
+        }
+
+        // Glass and lights are walked on and bumped into like every other block.
+        if chunk_lod == ChunkLod::Full {
+            let offset = collider_positions.len() as u32;
+            collider_triangles.extend(
+                mesh_data
+                    .triangles
+                    .iter()
+                    .map(|triangle| triangle.map(|index| index + offset)),
+            );
+            collider_positions.extend(
+                mesh_data
+                    .positions
+                    .iter()
+                    .map(|position| Vec3::new(position[0], position[1], position[2])),
+            );
+        }
 
-    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
-    mesh.insert_indices(Indices::U32(mesh_triangles));
+        mesh_data.into_mesh()
+    };
 
     (
         Some((
-            mesh,
-            collider_positions,
-            if chunk_lod == ChunkLod::Full {
-                triangles
-            } else {
-                Vec::new()
+            ChunkMeshes {
+                opaque: to_mesh(opaque),
+                transparent: to_mesh(transparent),
+                emissive: emissive
+                    .into_iter()
+                    .filter_map(|(block, mesh_data)| {
+                        let [r, g, b, _] = block.get_color();
+                        Some((Color::rgb(r, g, b), to_mesh(mesh_data)?))
+                    })
+                    .collect(),
+                lights,
             },
+            collider_positions,
+            collider_triangles,
         )),
         generate_more,
     )
 }
 
+/// Moves a position in LOD blocks of the padded block array into the space of the chunk entity.
+fn to_chunk_space(position: [f32; 3], min_height: i32, chunk_lod: ChunkLod) -> [f32; 3] {
+    [
+        (position[0] - 0.5) * VOXEL_SIZE * chunk_lod.multiplier_f32() + 0.5,
+        (position[1] + min_height as f32 - 0.5) * VOXEL_SIZE * chunk_lod.multiplier_f32() + 0.5,
+        (position[2] - 0.5) * VOXEL_SIZE * chunk_lod.multiplier_f32() + 0.5,
+    ]
+}
+
+impl MeshData {
+    fn into_mesh(self) -> Option<Mesh> {
+        if self.triangles.is_empty() {
+            return None;
+        }
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::all());
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, self.colors);
+        mesh.insert_indices(Indices::U32(self.triangles.into_iter().flatten().collect()));
+
+        Some(mesh)
+    }
+}
+
+/// Faces between two see-through blocks and faces hidden behind solid blocks are skipped.
+fn shows_face(block: BlockType, neighbour: BlockType) -> bool {
+    neighbour == BlockType::Air || (neighbour.is_transparent() && !block.is_transparent())
+}
+
+/// Whether `block` darkens the corners of the faces next to it.
+fn occludes(block: BlockType) -> bool {
+    block != BlockType::Air && !block.is_transparent()
+}
+
 fn all_neighbours(
     pos: [usize; 3],
     blocks: &[[[BlockType; CHUNK_SIZE[2] + 2]; CHUNK_SIZE[1] + 2]; CHUNK_SIZE[0] + 2],
 ) -> bool {
-    if blocks[pos[0]][pos[1] + 1][pos[2]] == BlockType::Air {
+    let block = blocks[pos[0]][pos[1]][pos[2]];
+    if shows_face(block, blocks[pos[0]][pos[1] + 1][pos[2]]) {
         return false;
     }
-    if blocks[pos[0]][pos[1] - 1][pos[2]] == BlockType::Air {
+    if shows_face(block, blocks[pos[0]][pos[1] - 1][pos[2]]) {
         return false;
     }
-    if blocks[pos[0] + 1][pos[1]][pos[2]] == BlockType::Air {
+    if shows_face(block, blocks[pos[0] + 1][pos[1]][pos[2]]) {
         return false;
     }
-    if blocks[pos[0] - 1][pos[1]][pos[2]] == BlockType::Air {
+    if shows_face(block, blocks[pos[0] - 1][pos[1]][pos[2]]) {
         return false;
     }
-    if blocks[pos[0]][pos[1]][pos[2] + 1] == BlockType::Air {
+    if shows_face(block, blocks[pos[0]][pos[1]][pos[2] + 1]) {
         return false;
     }
-    if blocks[pos[0]][pos[1]][pos[2] - 1] == BlockType::Air {
+    if shows_face(block, blocks[pos[0]][pos[1]][pos[2] - 1]) {
         return false;
     }
     return true;
@@ -491,3 +623,110 @@ fn add_colors(
         ]);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Blocks = [[[BlockType; CHUNK_SIZE[2] + 2]; CHUNK_SIZE[1] + 2]; CHUNK_SIZE[0] + 2];
+
+    /// Meshes `place`d blocks on a thread with enough stack for a few block arrays.
+    fn mesh(
+        chunk_lod: ChunkLod,
+        place: fn(&mut Blocks),
+    ) -> (ChunkMeshes, Vec<Vec3>, Vec<[u32; 3]>) {
+        std::thread::Builder::new()
+            .stack_size(32 * 1024 * 1024)
+            .spawn(move || {
+                let mut blocks =
+                    [[[BlockType::Air; CHUNK_SIZE[2] + 2]; CHUNK_SIZE[1] + 2]; CHUNK_SIZE[0] + 2];
+                place(&mut blocks);
+                generate_mesh((blocks, 0, false), chunk_lod).0.unwrap()
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    }
+
+    fn vertices(mesh: &Option<Mesh>) -> usize {
+        mesh.as_ref().map_or(0, Mesh::count_vertices)
+    }
+
+    fn emissive_vertices(meshes: &ChunkMeshes) -> usize {
+        meshes
+            .emissive
+            .iter()
+            .map(|(_, mesh)| mesh.count_vertices())
+            .sum()
+    }
+
+    #[test]
+    fn faces_are_split_by_material() {
+        let (meshes, collider_positions, collider_triangles) = mesh(ChunkLod::Full, |blocks| {
+            blocks[2][2][2] = BlockType::Stone;
+            blocks[6][2][2] = BlockType::Glass(200, 220, 255);
+            blocks[10][2][2] = BlockType::Light(255, 204, 102);
+            blocks[11][2][2] = BlockType::Light(255, 204, 102);
+        });
+
+        // Six faces of four vertices per block, the two lights share a face.
+        assert_eq!(vertices(&meshes.opaque), 24);
+        assert_eq!(vertices(&meshes.transparent), 24);
+        assert_eq!(emissive_vertices(&meshes), 40);
+        assert_eq!(collider_positions.len(), 88);
+        assert_eq!(collider_triangles.len(), 44);
+
+        assert_eq!(meshes.emissive.len(), 1);
+        assert_eq!(meshes.emissive[0].0, Color::rgb(1., 0.8, 0.4));
+        assert_eq!(meshes.lights.len(), 1);
+        assert_eq!(meshes.lights[0].blocks, 2);
+        assert_eq!(meshes.lights[0].color, meshes.emissive[0].0);
+        // Halfway between the two blocks.
+        assert_eq!(meshes.lights[0].position, Vec3::new(5.5, 1.25, 1.25));
+    }
+
+    #[test]
+    fn glass_only_hides_faces_of_other_glass() {
+        let (meshes, ..) = mesh(ChunkLod::Full, |blocks| {
+            blocks[2][2][2] = BlockType::Stone;
+            blocks[3][2][2] = BlockType::Glass(200, 220, 255);
+            blocks[4][2][2] = BlockType::Glass(200, 220, 255);
+        });
+
+        // The stone face behind the glass stays, the faces between the panes don't.
+        assert_eq!(vertices(&meshes.opaque), 24);
+        assert_eq!(vertices(&meshes.transparent), 36);
+        assert!(meshes.lights.is_empty());
+    }
+
+    #[test]
+    fn coarse_chunks_have_no_point_lights() {
+        let (meshes, collider_positions, _) = mesh(ChunkLod::Quarter, |blocks| {
+            blocks[10][2][2] = BlockType::Light(255, 204, 102);
+        });
+
+        assert_eq!(emissive_vertices(&meshes), 24);
+        assert!(meshes.lights.is_empty());
+        assert!(collider_positions.is_empty());
+    }
+
+    #[test]
+    fn lights_glow_in_their_own_color() {
+        let (meshes, ..) = mesh(ChunkLod::Full, |blocks| {
+            blocks[10][2][2] = BlockType::Light(255, 0, 0);
+            blocks[11][2][2] = BlockType::Light(0, 0, 255);
+            blocks[20][2][2] = BlockType::Light(255, 0, 0);
+        });
+
+        // The faces between the two touching lights are hidden.
+        let colors = meshes
+            .emissive
+            .iter()
+            .map(|(color, mesh)| (*color, mesh.count_vertices()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            colors,
+            vec![(Color::rgb(1., 0., 0.), 44), (Color::rgb(0., 0., 1.), 20)]
+        );
+    }
+}
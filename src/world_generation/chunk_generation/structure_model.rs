@@ -0,0 +1,147 @@
+use crate::world_generation::chunk_generation::BlockType;
+use crate::world_generation::voxel_world::{ChunkLod, MAX_LOD};
+
+/// Part of a downsampled cell that has to be solid for the cell to be solid. Lower than half so
+/// trunks and poles survive a few levels.
+const MIN_COVERAGE: f32 = 0.25;
+
+/// Blocks of a structure at every LOD, indexed `[x][up][z]` like the loaded models.
+pub struct StructureModel {
+    /// One level per [`ChunkLod`] starting at [`ChunkLod::Full`], missing once nothing is left.
+    levels: Vec<Vec<Vec<Vec<BlockType>>>>,
+}
+
+impl StructureModel {
+    pub fn new(blocks: Vec<Vec<Vec<BlockType>>>) -> Self {
+        let mut levels = vec![];
+        let mut multiplier = 2;
+        while multiplier <= MAX_LOD.multiplier_i32() {
+            let level = downsample(&blocks, multiplier as usize);
+            if level
+                .iter()
+                .flatten()
+                .flatten()
+                .all(|block| *block == BlockType::Air)
+            {
+                break;
+            }
+            levels.push(level);
+            multiplier *= 2;
+        }
+        levels.insert(0, blocks);
+
+        Self { levels }
+    }
+
+    /// The full resolution blocks.
+    pub fn blocks(&self) -> &Vec<Vec<Vec<BlockType>>> {
+        &self.levels[0]
+    }
+
+    /// Blocks of the model with every block `chunk_lod` voxels wide, `None` if it's too small to
+    /// show up at that LOD.
+    pub fn level(&self, chunk_lod: ChunkLod) -> Option<&Vec<Vec<Vec<BlockType>>>> {
+        self.levels.get(chunk_lod.usize() - 1)
+    }
+}
+
+/// Merges every `multiplier`³ cell into one block, the most common one in the cell if enough of
+/// it is solid.
+fn downsample(blocks: &[Vec<Vec<BlockType>>], multiplier: usize) -> Vec<Vec<Vec<BlockType>>> {
+    let size = [
+        blocks.len(),
+        blocks.first().map_or(0, |x_blocks| x_blocks.len()),
+        blocks
+            .first()
+            .and_then(|x_blocks| x_blocks.first())
+            .map_or(0, |y_blocks| y_blocks.len()),
+    ];
+    let level_size = size.map(|size| size.div_ceil(multiplier));
+    let min_solid = (multiplier.pow(3) as f32 * MIN_COVERAGE).ceil() as usize;
+
+    let mut level = vec![vec![vec![BlockType::Air; level_size[2]]; level_size[1]]; level_size[0]];
+    let mut counts: Vec<(BlockType, usize)> = vec![];
+    for (cell_x, x_cells) in level.iter_mut().enumerate() {
+        for (cell_y, y_cells) in x_cells.iter_mut().enumerate() {
+            for (cell_z, cell) in y_cells.iter_mut().enumerate() {
+                counts.clear();
+                for x_blocks in blocks.iter().skip(cell_x * multiplier).take(multiplier) {
+                    for y_blocks in x_blocks.iter().skip(cell_y * multiplier).take(multiplier) {
+                        for block in y_blocks.iter().skip(cell_z * multiplier).take(multiplier) {
+                            if *block == BlockType::Air {
+                                continue;
+                            }
+                            match counts.iter_mut().find(|(counted, _)| counted == block) {
+                                Some((_, count)) => *count += 1,
+                                None => counts.push((*block, 1)),
+                            }
+                        }
+                    }
+                }
+
+                if counts.iter().map(|(_, count)| count).sum::<usize>() < min_solid {
+                    continue;
+                }
+                // Ties go to the block found first, so the result only depends on the model.
+                let mut most_common = 0;
+                for (block, count) in &counts {
+                    if *count > most_common {
+                        *cell = *block;
+                        most_common = *count;
+                    }
+                }
+            }
+        }
+    }
+
+    level
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(height: usize, block: BlockType) -> StructureModel {
+        let mut blocks = vec![vec![vec![BlockType::Air; 4]; height]; 4];
+        for y_blocks in &mut blocks[0] {
+            y_blocks[0] = block;
+            y_blocks[1] = block;
+        }
+        for y_blocks in &mut blocks[1] {
+            y_blocks[0] = block;
+            y_blocks[1] = block;
+        }
+        StructureModel::new(blocks)
+    }
+
+    #[test]
+    fn thin_parts_survive_coarse_levels() {
+        let model = column(16, BlockType::Stone);
+
+        let half = model.level(ChunkLod::Half).unwrap();
+        assert_eq!((half.len(), half[0].len(), half[0][0].len()), (2, 8, 2));
+        assert!(half[0]
+            .iter()
+            .all(|y_blocks| y_blocks[0] == BlockType::Stone));
+        assert!(half[1][0][1] == BlockType::Air);
+
+        // A two by two column is a quarter of a four voxel cell.
+        let quarter = model.level(ChunkLod::Quarter).unwrap();
+        assert!(quarter[0]
+            .iter()
+            .all(|y_blocks| y_blocks[0] == BlockType::Stone));
+
+        assert!(model.level(ChunkLod::Eighth).is_none());
+    }
+
+    #[test]
+    fn cells_take_the_most_common_block() {
+        let mut blocks = vec![vec![vec![BlockType::Stone; 2]; 2]; 2];
+        blocks[0][0][0] = BlockType::Snow;
+        blocks[1][1][1] = BlockType::Air;
+        let model = StructureModel::new(blocks);
+
+        assert!(model.level(ChunkLod::Half).unwrap()[0][0][0] == BlockType::Stone);
+        assert!(model.blocks()[0][0][0] == BlockType::Snow);
+    }
+}
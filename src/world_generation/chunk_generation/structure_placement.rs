@@ -0,0 +1,891 @@
+use crate::world_generation::chunk_generation::voxel_generation::{
+    get_min_distance_to_path, get_terrain_noise, StructureGenerator,
+};
+use crate::world_generation::chunk_generation::BlockType;
+use crate::world_generation::chunk_loading::country_cache::{CountryCache, Path, PathCache};
+use crate::world_generation::generation_options::GenerationOptions;
+use crate::world_generation::voxel_world::ChunkLod;
+use bevy::log::warn;
+use bevy::math::{IVec2, Vec3Swizzles};
+use noise::NoiseFn;
+use rand::prelude::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cmp::Reverse;
+use std::sync::Arc;
+
+/// Posts of [`FoundationMode::Stilts`] are placed every this many voxels along the footprint.
+const STILT_SPACING: i32 = 4;
+
+/// How a structure and the terrain below it are fitted together on uneven ground.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FoundationMode {
+    /// Stands at the average ground height, the terrain under it is leveled to that height.
+    Flatten,
+    /// Stands at the highest ground under it on posts, the terrain is left alone.
+    Stilts,
+    /// Stands at the highest ground under it, the terrain under it is raised to that height.
+    FillToGround,
+    /// Stands at the lowest ground under it and sinks into the slope, the terrain is left alone.
+    Sink,
+    /// Stands at the ground height at its center, the terrain is left alone. For trees, whose
+    /// trunk is in the middle of the model.
+    Rooted,
+    /// Its top is at the highest ground under it, so openings in the top reach the surface.
+    /// Everything inside the model replaces the terrain, air included. Used for mines and dungeons.
+    Buried,
+}
+
+/// One placed model of a [`StructureGenerator`].
+#[derive(Copy, Clone, Debug)]
+pub struct StructureInstance {
+    /// Index into [`GenerationOptions::structures`].
+    pub generator: usize,
+    /// Corner of the footprint with the lowest coordinates.
+    pub position: IVec2,
+    /// Size of the footprint, the model size with `rotation` applied.
+    pub size: IVec2,
+    /// Index into [`StructureGenerator::variants`].
+    pub variant: usize,
+    /// Clockwise quarter turns of the model around the vertical axis.
+    pub rotation: u8,
+    /// Whether the model is mirrored along its x axis before being turned.
+    pub mirrored: bool,
+    /// Index into [`StructureGenerator::palettes`].
+    pub palette: usize,
+    /// Height of the bottom of the model in full resolution voxels.
+    pub base_height: f32,
+    pub foundation: FoundationMode,
+    pub blend_radius: i32,
+}
+
+impl StructureInstance {
+    pub fn center(&self) -> IVec2 {
+        self.position + self.size / 2
+    }
+
+    /// Distance from `point` to the footprint, zero inside of it.
+    pub fn footprint_distance(&self, point: IVec2) -> f32 {
+        let outside = (self.position - point)
+            .max(point - (self.position + self.size - 1))
+            .max(IVec2::ZERO);
+        outside.as_vec2().length()
+    }
+
+    /// Distance between the footprints of `self` and `other`, zero if they overlap or touch.
+    pub fn footprint_gap(&self, other: &StructureInstance) -> f32 {
+        let gap = (other.position - (self.position + self.size))
+            .max(self.position - (other.position + other.size))
+            .max(IVec2::ZERO);
+        gap.as_vec2().length()
+    }
+
+    pub fn overlaps(&self, start: IVec2, end: IVec2, margin: i32) -> bool {
+        (self.position + self.size + margin).cmpgt(start).all()
+            && (self.position - margin).cmplt(end).all()
+    }
+
+    /// Ground height at `point` once the foundation is laid, both in full resolution voxels.
+    pub fn adjust_terrain(&self, point: IVec2, height: f32) -> f32 {
+        let target = match self.foundation {
+            FoundationMode::Flatten => self.base_height,
+            FoundationMode::FillToGround => height.max(self.base_height),
+            FoundationMode::Stilts
+            | FoundationMode::Sink
+            | FoundationMode::Rooted
+            | FoundationMode::Buried => return height,
+        };
+
+        let distance = self.footprint_distance(point);
+        if distance > self.blend_radius as f32 {
+            return height;
+        }
+
+        let weight = 1. - distance / (self.blend_radius + 1) as f32;
+        let weight = weight * weight * (3. - 2. * weight);
+        height + (target - height) * weight
+    }
+
+    /// Model column at the footprint position `local`.
+    pub fn model_position(&self, local: IVec2) -> IVec2 {
+        self.transform_to_model(local, self.size)
+    }
+
+    /// Column of the model level for `chunk_lod` drawn at the full resolution `point`, if the
+    /// footprint covers it at that LOD.
+    pub fn lod_model_position(&self, point: IVec2, chunk_lod: ChunkLod) -> Option<IVec2> {
+        let cell = lod_footprint_cell(point, self.position, self.size, chunk_lod)?;
+        Some(self.transform_to_model(
+            cell,
+            (self.size + chunk_lod.multiplier_i32() - 1) / chunk_lod.multiplier_i32(),
+        ))
+    }
+
+    /// Footprint position of the model column `model`, the opposite of
+    /// [`StructureInstance::model_position`].
+    pub fn local_position(&self, model: IVec2) -> IVec2 {
+        let mut model = model;
+        if self.mirrored {
+            let model_width = if self.rotation % 2 == 1 {
+                self.size.y
+            } else {
+                self.size.x
+            };
+            model.x = model_width - 1 - model.x;
+        }
+
+        match self.rotation % 4 {
+            0 => model,
+            1 => IVec2::new(self.size.x - 1 - model.y, model.x),
+            2 => self.size - 1 - model,
+            _ => IVec2::new(model.y, self.size.y - 1 - model.x),
+        }
+    }
+
+    /// Turns and mirrors the position `local` of a footprint that is `size` wide.
+    fn transform_to_model(&self, local: IVec2, size: IVec2) -> IVec2 {
+        let mut position = match self.rotation % 4 {
+            0 => local,
+            1 => IVec2::new(local.y, size.x - 1 - local.x),
+            2 => size - 1 - local,
+            _ => IVec2::new(size.y - 1 - local.y, local.x),
+        };
+
+        if self.mirrored {
+            let model_width = if self.rotation % 2 == 1 {
+                size.y
+            } else {
+                size.x
+            };
+            position.x = model_width - 1 - position.x;
+        }
+
+        position
+    }
+
+    /// Whether the model column at `local` gets a post down to the ground.
+    pub fn is_stilt(&self, local: IVec2) -> bool {
+        let is_post_line =
+            |coordinate: i32, size: i32| coordinate % STILT_SPACING == 0 || coordinate == size - 1;
+
+        self.foundation == FoundationMode::Stilts
+            && is_post_line(local.x, self.size.x)
+            && is_post_line(local.y, self.size.y)
+    }
+}
+
+/// Rules deciding where the structures of a generator may go. Roads and structures of higher
+/// ranked generators are kept clear.
+#[derive(Copy, Clone, Debug)]
+pub struct PlacementRules {
+    /// Structures of a higher priority suppress lower ones, generators listed earlier win ties.
+    pub priority: i32,
+    /// Distance lower ranked structures have to keep from the footprint.
+    pub exclusion_radius: i32,
+    /// Distance the footprint has to keep from the center of roads.
+    pub road_clearance: i32,
+}
+
+impl PlacementRules {
+    fn outranks(&self, generator: usize, other: &PlacementRules, other_generator: usize) -> bool {
+        (self.priority, Reverse(generator)) > (other.priority, Reverse(other_generator))
+    }
+
+    /// Whether `instance` keeps clear of the roads in `paths_list` and of `blockers`, the
+    /// outranking structures with their exclusion radius.
+    fn allows(
+        &self,
+        instance: &StructureInstance,
+        paths_list: &Vec<&Vec<Path>>,
+        blockers: &[(StructureInstance, i32)],
+    ) -> bool {
+        let half_size = instance.size.max_element() / 2;
+        let (path_distance, _, _, _) = get_min_distance_to_path(
+            instance.center(),
+            paths_list,
+            IVec2::splat(half_size + self.road_clearance),
+        );
+        if (path_distance as i32) < half_size + self.road_clearance {
+            return false;
+        }
+
+        !blockers.iter().any(|(blocker, exclusion_radius)| {
+            instance.footprint_gap(blocker) <= *exclusion_radius as f32
+        })
+    }
+}
+
+/// Maps colors of a model to other ones, colors that aren't listed are kept.
+#[derive(Clone, Default)]
+pub struct PaletteSwap(pub Vec<([u8; 3], [u8; 3])>);
+
+impl PaletteSwap {
+    /// Swaps every color used in `models` that `swap` returns a replacement for.
+    pub fn from_models(
+        models: &[&Vec<Vec<Vec<BlockType>>>],
+        swap: impl Fn([u8; 3]) -> Option<[u8; 3]>,
+    ) -> Self {
+        let mut colors = vec![];
+        for block in models
+            .iter()
+            .flat_map(|model| model.iter().flatten().flatten())
+        {
+            if let BlockType::Custom(r, g, b) = *block {
+                if !colors.iter().any(|(from, _)| *from == [r, g, b]) {
+                    if let Some(to) = swap([r, g, b]) {
+                        colors.push(([r, g, b], to));
+                    }
+                }
+            }
+        }
+
+        Self(colors)
+    }
+
+    pub fn apply(&self, block: BlockType) -> BlockType {
+        let BlockType::Custom(r, g, b) = block else {
+            return block;
+        };
+
+        self.0
+            .iter()
+            .find(|(from, _)| *from == [r, g, b])
+            .map_or(block, |(_, [r, g, b])| BlockType::Custom(*r, *g, *b))
+    }
+}
+
+/// Structures are placed and cached in square regions of this many grid cells.
+const REGION_CELLS: i32 = 16;
+
+/// Every structure of one generator whose grid cell lies in a region.
+pub struct StructureRegion {
+    pub instances: Vec<StructureInstance>,
+}
+
+impl StructureGenerator {
+    /// Region of the grid cell whose footprints can cover `position`.
+    fn region_at(&self, position: IVec2) -> IVec2 {
+        (position + IVec2::from(self.grid_offset))
+            .div_euclid(IVec2::from(self.generation_size))
+            .div_euclid(IVec2::splat(REGION_CELLS))
+    }
+
+    /// The model placed in grid cell `cell`, if any, before checking the [`PlacementRules`].
+    /// Its ground height is filled in by [`StructureGenerator::place_on_ground`].
+    fn instance_in_cell(&self, generator: usize, cell: IVec2) -> Option<StructureInstance> {
+        let structure_value = self.noise.get_noise(cell.x as f32, cell.y as f32) * 0.5 + 0.5;
+        if structure_value <= 0. {
+            return None;
+        }
+
+        let mut rand = StdRng::seed_from_u64((structure_value.abs() * 10000.) as u64);
+
+        let total_weight = self
+            .variants
+            .iter()
+            .map(|variant| variant.weight)
+            .sum::<u32>();
+        let mut picked_weight = rand.gen_range(0..total_weight.max(1));
+        let variant = self
+            .variants
+            .iter()
+            .position(|variant| {
+                let is_picked = picked_weight < variant.weight;
+                picked_weight = picked_weight.saturating_sub(variant.weight);
+                is_picked
+            })
+            .unwrap_or_default();
+        let variant = self.jigsaw.map_or(variant, |rules| rules.start);
+        // Generators without variants, or with a missing jigsaw start, have nothing to place.
+        let model_size = self.variants.get(variant)?.model_size;
+        let rotation = if self.random_rotation {
+            rand.gen_range(0..4)
+        } else {
+            0
+        };
+        let mirrored = self.random_mirroring && rand.gen_bool(0.5);
+        let palette = rand.gen_range(0..self.palettes.len().max(1));
+
+        let size = if rotation % 2 == 1 {
+            IVec2::new(model_size[2], model_size[0])
+        } else {
+            IVec2::new(model_size[0], model_size[2])
+        };
+
+        let random_x = rand.gen_range(0..=(self.generation_size[0] - size.x).max(0));
+        let random_z = rand.gen_range(0..=(self.generation_size[1] - size.y).max(0));
+        let position = cell * IVec2::from(self.generation_size) - IVec2::from(self.grid_offset)
+            + IVec2::new(random_x, random_z);
+
+        Some(StructureInstance {
+            generator,
+            position,
+            size,
+            variant,
+            rotation,
+            mirrored,
+            palette,
+            base_height: 0.,
+            foundation: self.foundation,
+            blend_radius: self.blend_radius,
+        })
+    }
+
+    fn place_on_ground<F: NoiseFn<f64, 2>>(
+        &self,
+        instance: StructureInstance,
+        terrain_noise: &F,
+    ) -> StructureInstance {
+        let ground_heights = ground_heights(&instance, terrain_noise);
+
+        let base_height = match self.foundation {
+            FoundationMode::Flatten => {
+                ground_heights.iter().sum::<f32>() / ground_heights.len() as f32
+            }
+            FoundationMode::Stilts | FoundationMode::FillToGround => {
+                ground_heights.into_iter().fold(f32::MIN, f32::max)
+            }
+            FoundationMode::Sink => ground_heights.into_iter().fold(f32::MAX, f32::min),
+            FoundationMode::Rooted => ground_heights[4],
+            FoundationMode::Buried => {
+                ground_heights.into_iter().fold(f32::MIN, f32::max)
+                    - (self.variants[instance.variant].model_size[1] - 1) as f32
+            }
+        };
+
+        StructureInstance {
+            base_height: base_height.floor(),
+            ..instance
+        }
+    }
+
+    /// Whether the top of `instance` stays below the terrain at its corners and center, which the
+    /// pieces of buried layouts have to.
+    fn is_below_ground<F: NoiseFn<f64, 2>>(
+        &self,
+        instance: &StructureInstance,
+        terrain_noise: &F,
+    ) -> bool {
+        let top = instance.base_height + (self.variants[instance.variant].model_size[1] - 1) as f32;
+        ground_heights(instance, terrain_noise)
+            .into_iter()
+            .all(|height| top < height.floor())
+    }
+}
+
+/// Terrain height at the corners and the center of the footprint of `instance`.
+fn ground_heights<F: NoiseFn<f64, 2>>(instance: &StructureInstance, terrain_noise: &F) -> [f32; 5] {
+    let StructureInstance { position, size, .. } = *instance;
+    [
+        position,
+        position + IVec2::new(size.x - 1, 0),
+        position + IVec2::new(0, size.y - 1),
+        position + size - 1,
+        position + size / 2,
+    ]
+    .map(|point| terrain_noise.get(point.as_dvec2().to_array()) as f32)
+}
+
+impl CountryCache {
+    /// Every structure whose footprint, grown by its blend radius, overlaps the area between
+    /// `start` and `end`. Structures smaller than `min_size` are skipped, at coarse LODs they
+    /// would be a single voxel at most.
+    pub fn structure_instances(
+        &self,
+        start: IVec2,
+        end: IVec2,
+        min_size: i32,
+        generation_options: &GenerationOptions,
+    ) -> Vec<StructureInstance> {
+        let mut instances = vec![];
+
+        for (generator, structure) in generation_options.structures.iter().enumerate() {
+            let largest_size = structure
+                .variants
+                .iter()
+                .map(|variant| variant.model_size[0].max(variant.model_size[2]))
+                .max()
+                .unwrap_or_default();
+            if largest_size < min_size {
+                continue;
+            }
+
+            instances.extend(self.generator_instances(
+                generator,
+                start,
+                end,
+                // Snapping to the LOD grid moves structures by less than a voxel of it.
+                structure.blend_radius.max(min_size),
+                generation_options,
+            ));
+        }
+
+        instances
+    }
+
+    /// Structure of `generator` whose center is closest to `position`, searching the region of
+    /// `position` and the ones around it.
+    pub fn nearest_structure(
+        &self,
+        generator: usize,
+        position: IVec2,
+        generation_options: &GenerationOptions,
+    ) -> Option<StructureInstance> {
+        let region = generation_options.structures[generator].region_at(position);
+
+        (-1..=1)
+            .flat_map(|x| (-1..=1).map(move |z| region + IVec2::new(x, z)))
+            .flat_map(|region| {
+                self.structure_region(generator, region, generation_options)
+                    .instances
+                    .clone()
+            })
+            .min_by_key(|instance| instance.center().distance_squared(position))
+    }
+
+    /// Structures of `generator` overlapping the area between `start` and `end` grown by `margin`.
+    fn generator_instances(
+        &self,
+        generator: usize,
+        start: IVec2,
+        end: IVec2,
+        margin: i32,
+        generation_options: &GenerationOptions,
+    ) -> Vec<StructureInstance> {
+        let structure = &generation_options.structures[generator];
+        // Layouts reach beyond the grid cell of their start piece.
+        let reach = margin + structure.jigsaw_reach();
+        let region_start = structure.region_at(start - reach);
+        let region_end = structure.region_at(end - 1 + reach);
+
+        let mut instances = vec![];
+        for region_x in region_start.x..=region_end.x {
+            for region_z in region_start.y..=region_end.y {
+                let region = self.structure_region(
+                    generator,
+                    IVec2::new(region_x, region_z),
+                    generation_options,
+                );
+
+                instances.extend(
+                    region
+                        .instances
+                        .iter()
+                        .filter(|instance| instance.overlaps(start, end, margin))
+                        .copied(),
+                );
+            }
+        }
+
+        instances
+    }
+
+    /// Structures of `generator` in `region`. They keep clear of the roads around the region
+    /// rather than the ones of `self`, which depend on the countries it got merged with. Regions
+    /// next to roads that failed to generate are placed without them and not cached.
+    fn structure_region(
+        &self,
+        generator: usize,
+        region: IVec2,
+        generation_options: &GenerationOptions,
+    ) -> Arc<StructureRegion> {
+        self.structure_cache
+            .instances
+            .try_get_or_generate((generator, region), || {
+                let structure = &generation_options.structures[generator];
+                let terrain_noise = get_terrain_noise(ChunkLod::Full, generation_options);
+
+                let first_cell = region * REGION_CELLS;
+                let region_start = first_cell * IVec2::from(structure.generation_size)
+                    - IVec2::from(structure.grid_offset);
+                let region_end =
+                    region_start + IVec2::from(structure.generation_size) * REGION_CELLS;
+
+                // The centers of the structures lie in the region, roads further away than their clearance
+                // and half their size don't matter.
+                let largest_size = structure
+                    .variants
+                    .iter()
+                    .map(|variant| variant.model_size[0].max(variant.model_size[2]))
+                    .max()
+                    .unwrap_or_default();
+                let road_margin = structure.placement.road_clearance + largest_size / 2;
+                let path_caches = PathCache::in_area(
+                    region_start - road_margin,
+                    region_end + road_margin,
+                    generation_options,
+                );
+                let paths_list = path_caches
+                    .iter()
+                    .flatten()
+                    .map(|path_cache| &path_cache.paths)
+                    .collect::<Vec<_>>();
+
+                // Outranking generators never depend on this one, so this can't recurse forever.
+                let blockers = generation_options
+                    .structures
+                    .iter()
+                    .enumerate()
+                    .filter(|(other, other_structure)| {
+                        other_structure
+                            .placement
+                            .outranks(*other, &structure.placement, generator)
+                    })
+                    .flat_map(|(other, other_structure)| {
+                        self.generator_instances(
+                            other,
+                            region_start - structure.jigsaw_reach(),
+                            region_end + structure.jigsaw_reach(),
+                            other_structure.placement.exclusion_radius,
+                            generation_options,
+                        )
+                        .into_iter()
+                        .map(move |blocker| (blocker, other_structure.placement.exclusion_radius))
+                    })
+                    .collect::<Vec<_>>();
+                let allows = |instance: &StructureInstance| {
+                    structure.placement.allows(instance, &paths_list, &blockers)
+                };
+                // Layouts stay inside of their region, so they can't overlap the ones of the
+                // regions around it.
+                let fits = |piece: &StructureInstance| {
+                    let bounds = structure.piece_bounds(piece);
+                    bounds.min.xz().cmpge(region_start).all()
+                        && bounds.max.xz().cmple(region_end).all()
+                        && (structure.foundation != FoundationMode::Buried
+                            || structure.is_below_ground(piece, &terrain_noise))
+                        && allows(piece)
+                };
+
+                let mut instances = vec![];
+                let mut occupied = vec![];
+                for cell in (0..REGION_CELLS)
+                    .flat_map(|x| (0..REGION_CELLS).map(move |z| first_cell + IVec2::new(x, z)))
+                {
+                    let Some(instance) = structure
+                        .instance_in_cell(generator, cell)
+                        .filter(|instance| allows(instance))
+                    else {
+                        continue;
+                    };
+
+                    let pieces = structure.assemble(
+                        structure.place_on_ground(instance, &terrain_noise),
+                        &occupied,
+                        fits,
+                    );
+                    if structure.jigsaw.is_some() {
+                        occupied.extend(pieces.iter().map(|piece| structure.piece_bounds(piece)));
+                    }
+                    instances.extend(pieces);
+                }
+
+                let structure_region = StructureRegion { instances };
+                match path_caches.into_iter().find_map(Result::err) {
+                    None => Ok(structure_region),
+                    Some(error) => Err((structure_region, error)),
+                }
+            })
+            .unwrap_or_else(|(structure_region, error)| {
+                warn!(
+                    "Placing structures in region {} without the roads that failed: {}",
+                    region, error
+                );
+                Arc::new(structure_region)
+            })
+    }
+}
+
+/// Converts a height in full resolution voxels to the height of the same terrain at `chunk_lod`.
+pub fn full_to_lod_height(height: f32, chunk_lod: ChunkLod) -> f32 {
+    (height - 1.) / chunk_lod.multiplier_f32() + 1.
+}
+
+pub fn lod_to_full_height(height: f32, chunk_lod: ChunkLod) -> f32 {
+    (height - 1.) * chunk_lod.multiplier_f32() + 1.
+}
+
+/// Block index at `chunk_lod` of the block containing the full resolution block at `full_y`.
+pub fn full_to_lod_block(full_y: i32, chunk_lod: ChunkLod) -> i32 {
+    (full_y - 1).div_euclid(chunk_lod.multiplier_i32()) + 1
+}
+
+/// Cell of a downsampled footprint starting at `position` and `size` wide, that the full
+/// resolution `point` falls into. At coarser LODs the footprint is moved onto the grid of the LOD,
+/// so every cell of the downsampled model lines up with one voxel.
+pub fn lod_footprint_cell(
+    point: IVec2,
+    position: IVec2,
+    size: IVec2,
+    chunk_lod: ChunkLod,
+) -> Option<IVec2> {
+    let multiplier = chunk_lod.multiplier_i32();
+    let cell =
+        point.div_euclid(IVec2::splat(multiplier)) - position.div_euclid(IVec2::splat(multiplier));
+    let cells = (size + multiplier - 1) / multiplier;
+    if cell.cmplt(IVec2::ZERO).any() || cell.cmpge(cells).any() {
+        return None;
+    }
+
+    Some(cell)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_generation::chunk_generation::structure_model::StructureModel;
+    use crate::world_generation::chunk_generation::voxel_generation::StructureVariant;
+    use bracket_noise::prelude::FastNoise;
+    use noise::Constant;
+
+    /// Terrain sloping down along x, 100 voxels high at x = 0.
+    struct Slope;
+
+    impl NoiseFn<f64, 2> for Slope {
+        fn get(&self, point: [f64; 2]) -> f64 {
+            100. - point[0]
+        }
+    }
+
+    /// Terrain curving down along x, 100 voxels high at x = 0.
+    struct Curve;
+
+    impl NoiseFn<f64, 2> for Curve {
+        fn get(&self, point: [f64; 2]) -> f64 {
+            100. - point[0] * point[0]
+        }
+    }
+
+    fn generator(foundation: FoundationMode) -> StructureGenerator {
+        StructureGenerator {
+            name: "test",
+            variants: vec![StructureVariant {
+                model: Arc::new(StructureModel::new(vec![
+                    vec![vec![BlockType::Stone; 5]; 10];
+                    5
+                ])),
+                model_size: [5, 10, 5],
+                weight: 1,
+                connectors: vec![],
+            }],
+            palettes: vec![],
+            random_rotation: false,
+            random_mirroring: false,
+            noise: FastNoise::new(),
+            generation_size: [100, 100],
+            grid_offset: [0, 0],
+            generate_debug_blocks: false,
+            debug_rgb_multiplier: [0., 0., 0.],
+            placement: PlacementRules {
+                priority: 0,
+                exclusion_radius: 0,
+                road_clearance: 0,
+            },
+            foundation,
+            blend_radius: 4,
+            jigsaw: None,
+        }
+    }
+
+    fn instance(foundation: FoundationMode) -> StructureInstance {
+        StructureInstance {
+            generator: 0,
+            position: IVec2::ZERO,
+            size: IVec2::new(5, 5),
+            variant: 0,
+            rotation: 0,
+            mirrored: false,
+            palette: 0,
+            base_height: 80.,
+            foundation,
+            blend_radius: 4,
+        }
+    }
+
+    #[test]
+    fn buried_pieces_have_to_stay_below_ground() {
+        let generator = generator(FoundationMode::Buried);
+        let piece = instance(FoundationMode::Buried);
+
+        // The top of the piece is at 89.
+        assert!(generator.is_below_ground(&piece, &Constant::new(100.)));
+        assert!(!generator.is_below_ground(&piece, &Constant::new(89.5)));
+        assert!(generator.is_below_ground(&piece, &Slope));
+        assert!(!generator.is_below_ground(
+            &StructureInstance {
+                position: IVec2::new(8, 0),
+                ..piece
+            },
+            &Slope
+        ));
+    }
+
+    #[test]
+    fn foundations_pick_their_base_height() {
+        // Ground heights are 100 and 84 at the corners and 96 at the center.
+        let base_height = |foundation| {
+            generator(foundation)
+                .place_on_ground(instance(foundation), &Curve)
+                .base_height
+        };
+
+        assert_eq!(base_height(FoundationMode::Flatten), 92.);
+        assert_eq!(base_height(FoundationMode::Stilts), 100.);
+        assert_eq!(base_height(FoundationMode::FillToGround), 100.);
+        assert_eq!(base_height(FoundationMode::Sink), 84.);
+        assert_eq!(base_height(FoundationMode::Rooted), 96.);
+        // The top of the ten voxel high model is at the highest ground.
+        assert_eq!(base_height(FoundationMode::Buried), 91.);
+    }
+
+    #[test]
+    fn flattened_terrain_blends_into_the_surroundings() {
+        let flatten = instance(FoundationMode::Flatten);
+
+        assert_eq!(flatten.adjust_terrain(IVec2::new(2, 2), 100.), 80.);
+        assert_eq!(flatten.adjust_terrain(IVec2::new(4, 0), 60.), 80.);
+        assert_eq!(flatten.adjust_terrain(IVec2::new(-5, 2), 100.), 100.);
+        assert_eq!(flatten.adjust_terrain(IVec2::new(9, 9), 60.), 60.);
+
+        let near = flatten.adjust_terrain(IVec2::new(-1, 2), 100.);
+        let far = flatten.adjust_terrain(IVec2::new(-3, 2), 100.);
+        assert!(80. < near && near < far && far < 100.);
+    }
+
+    #[test]
+    fn filled_terrain_only_rises() {
+        let fill = instance(FoundationMode::FillToGround);
+
+        assert_eq!(fill.adjust_terrain(IVec2::new(2, 2), 70.), 80.);
+        assert_eq!(fill.adjust_terrain(IVec2::new(2, 2), 90.), 90.);
+        assert_eq!(fill.adjust_terrain(IVec2::new(-5, 2), 70.), 70.);
+        assert!(fill.adjust_terrain(IVec2::new(-2, 2), 70.) > 70.);
+    }
+
+    #[test]
+    fn other_foundations_leave_the_terrain_alone() {
+        for foundation in [
+            FoundationMode::Stilts,
+            FoundationMode::Sink,
+            FoundationMode::Rooted,
+            FoundationMode::Buried,
+        ] {
+            let instance = instance(foundation);
+            assert_eq!(instance.adjust_terrain(IVec2::new(2, 2), 70.), 70.);
+            assert_eq!(instance.adjust_terrain(IVec2::new(-2, 2), 90.), 90.);
+        }
+    }
+
+    #[test]
+    fn stilts_stand_on_a_grid_and_the_edges() {
+        let stilts = StructureInstance {
+            size: IVec2::new(10, 6),
+            ..instance(FoundationMode::Stilts)
+        };
+
+        assert!(stilts.is_stilt(IVec2::new(0, 0)));
+        assert!(stilts.is_stilt(IVec2::new(4, 5)));
+        assert!(stilts.is_stilt(IVec2::new(9, 4)));
+        assert!(!stilts.is_stilt(IVec2::new(2, 0)));
+        assert!(!stilts.is_stilt(IVec2::new(4, 3)));
+        assert!(!instance(FoundationMode::Flatten).is_stilt(IVec2::ZERO));
+    }
+
+    #[test]
+    fn generators_without_variants_place_nothing() {
+        let mut generator = generator(FoundationMode::Flatten);
+        generator
+            .noise
+            .set_noise_type(bracket_noise::prelude::NoiseType::WhiteNoise);
+        let cells = || (0..64).map(|x| IVec2::new(x, x / 8));
+
+        assert!(cells().any(|cell| generator.instance_in_cell(0, cell).is_some()));
+
+        generator.variants.clear();
+        assert!(cells().all(|cell| generator.instance_in_cell(0, cell).is_none()));
+    }
+
+    /// Instance of a model 3 wide along x and 5 along z.
+    fn turned(rotation: u8, mirrored: bool) -> StructureInstance {
+        StructureInstance {
+            size: if rotation % 2 == 1 {
+                IVec2::new(5, 3)
+            } else {
+                IVec2::new(3, 5)
+            },
+            rotation,
+            mirrored,
+            ..instance(FoundationMode::Flatten)
+        }
+    }
+
+    #[test]
+    fn model_and_footprint_positions_are_inverse() {
+        for rotation in 0..4 {
+            for mirrored in [false, true] {
+                let instance = turned(rotation, mirrored);
+
+                let mut covered = vec![];
+                for x in 0..instance.size.x {
+                    for z in 0..instance.size.y {
+                        let local = IVec2::new(x, z);
+                        let model = instance.model_position(local);
+                        assert!(model.cmpge(IVec2::ZERO).all());
+                        assert!(model.cmplt(IVec2::new(3, 5)).all());
+                        assert_eq!(instance.local_position(model), local);
+                        covered.push(model);
+                    }
+                }
+
+                covered.sort_by_key(|model| (model.x, model.y));
+                covered.dedup();
+                assert_eq!(covered.len(), 15);
+            }
+        }
+    }
+
+    #[test]
+    fn models_turn_clockwise_and_mirror_along_x() {
+        // The model corner at the origin, seen from above with x to the right and z down.
+        let corner = |rotation, mirrored| turned(rotation, mirrored).local_position(IVec2::ZERO);
+
+        assert_eq!(corner(0, false), IVec2::new(0, 0));
+        assert_eq!(corner(1, false), IVec2::new(4, 0));
+        assert_eq!(corner(2, false), IVec2::new(2, 4));
+        assert_eq!(corner(3, false), IVec2::new(0, 2));
+        assert_eq!(corner(0, true), IVec2::new(2, 0));
+        assert_eq!(corner(1, true), IVec2::new(4, 2));
+
+        // Mirroring happens before turning, so it's along the model's own x axis.
+        let instance = turned(1, true);
+        assert_eq!(
+            instance.local_position(IVec2::new(0, 4)),
+            turned(1, false).local_position(IVec2::new(2, 4))
+        );
+    }
+
+    #[test]
+    fn coarse_levels_turn_like_the_full_model() {
+        for rotation in 0..4 {
+            for mirrored in [false, true] {
+                let instance = StructureInstance {
+                    size: IVec2::new(4, 4),
+                    ..turned(rotation, mirrored)
+                };
+
+                for x in 0..4 {
+                    for z in 0..4 {
+                        let full = instance.model_position(IVec2::new(x, z));
+                        assert_eq!(
+                            instance.lod_model_position(IVec2::new(x, z), ChunkLod::Half),
+                            Some(full / 2)
+                        );
+                    }
+                }
+                assert_eq!(
+                    instance.lod_model_position(IVec2::new(4, 0), ChunkLod::Half),
+                    None
+                );
+            }
+        }
+    }
+}
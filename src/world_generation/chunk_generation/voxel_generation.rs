@@ -1,10 +1,19 @@
 use crate::utils::div_floor;
+use crate::world_generation::chunk_generation::jigsaw::{Connector, JigsawRules};
 use crate::world_generation::chunk_generation::noise::fractal_open_simplex::FractalOpenSimplex;
 use crate::world_generation::chunk_generation::noise::roughness::Roughness;
+use crate::world_generation::chunk_generation::structure_model::StructureModel;
+use crate::world_generation::chunk_generation::structure_placement::{
+    full_to_lod_block, full_to_lod_height, lod_footprint_cell, lod_to_full_height, FoundationMode,
+    PaletteSwap, PlacementRules,
+};
 use crate::world_generation::chunk_generation::{BlockType, CHUNK_SIZE, VOXEL_SIZE};
-use crate::world_generation::chunk_loading::country_cache::{
-    CountryCache, Path, PathLine, COUNTRY_SIZE,
+use crate::world_generation::chunk_loading::country_cache::{CountryCache, Path, PathLine};
+use crate::world_generation::chunk_loading::country_layout::CountryPalette;
+use crate::world_generation::chunk_loading::road_decoration::{
+    RoadProp, RoadPropModel, RoadPropModels,
 };
+use crate::world_generation::chunk_loading::road_grading::RoadSurface;
 use crate::world_generation::generation_options::GenerationOptions;
 use crate::world_generation::voxel_world::ChunkLod;
 use bevy::math::IVec2;
@@ -28,16 +37,184 @@ use rand::{Rng, SeedableRng};
 use std::sync::Arc;
 
 pub struct StructureGenerator {
-    pub model: Arc<Vec<Vec<Vec<BlockType>>>>,
-    pub model_size: [i32; 3],
+    /// Used to look the generator up, for example to find the nearest tree house.
+    pub name: &'static str,
+    /// Models picked from by weight, generators without any place nothing.
+    pub variants: Vec<StructureVariant>,
+    /// Color swaps picked from at random, models keep their colors if this is empty.
+    pub palettes: Vec<PaletteSwap>,
+    /// Whether models get turned by a random multiple of 90 degrees.
+    pub random_rotation: bool,
+    /// Whether models get mirrored at random.
+    pub random_mirroring: bool,
     pub noise: FastNoise,
     pub generation_size: [i32; 2],
     pub grid_offset: [i32; 2],
     pub generate_debug_blocks: bool,
     pub debug_rgb_multiplier: [f32; 3],
+    pub placement: PlacementRules,
+    pub foundation: FoundationMode,
+    /// Distance around the footprint over which the terrain is blended into the foundation.
+    pub blend_radius: i32,
+    /// Builds layouts out of the variants instead of placing a single one.
+    pub jigsaw: Option<JigsawRules>,
     //pub height_offset: i32
 }
 
+/// One of the models a [`StructureGenerator`] picks from.
+#[derive(Clone)]
+pub struct StructureVariant {
+    pub model: Arc<StructureModel>,
+    pub model_size: [i32; 3],
+    /// Chance of getting picked, relative to the other variants.
+    pub weight: u32,
+    /// Where other pieces can be joined, only used by [`JigsawRules`].
+    pub connectors: Vec<Connector>,
+}
+
+/// Terrain of one chunk column, shared by all chunks stacked in it.
+pub struct ColumnData {
+    /// Terrain height after blending in the paths and structure foundations.
+    pub heights: [[f32; CHUNK_SIZE[0] + 2]; CHUNK_SIZE[0] + 2],
+    /// `Path` for the whole column, `RetainingWall` from the wall base up, `Grass` or `Snow` for
+    /// the top block, `Stone` otherwise.
+    pub surface_blocks: [[BlockType; CHUNK_SIZE[0] + 2]; CHUNK_SIZE[0] + 2],
+    /// Height of the graded road next to a retaining wall, the wall fills the column from there
+    /// up to the terrain. Unused in other columns.
+    pub wall_bases: [[f32; CHUNK_SIZE[0] + 2]; CHUNK_SIZE[0] + 2],
+    /// Colors of the country every column lies in.
+    pub palettes: [[CountryPalette; CHUNK_SIZE[0] + 2]; CHUNK_SIZE[0] + 2],
+    /// Lowest terrain height after blending in the paths and structure foundations.
+    pub min_height: f32,
+}
+
+impl ColumnData {
+    pub fn generate<F: NoiseFn<f64, 2>>(
+        position: IVec2,
+        chunk_lod: ChunkLod,
+        terrain_noise: &F,
+        country_cache: &CountryCache,
+        generation_options: &GenerationOptions,
+    ) -> Self {
+        let road_grading = &generation_options.road_grading;
+        let mut terrain_height = [[0f32; CHUNK_SIZE[0] + 2]; CHUNK_SIZE[0] + 2];
+        let mut terrain_steepness = [[0f32; CHUNK_SIZE[0]]; CHUNK_SIZE[0]];
+        get_noise_map(
+            position * IVec2::new(CHUNK_SIZE[0] as i32, CHUNK_SIZE[2] as i32),
+            chunk_lod.multiplier_i32(),
+            terrain_noise,
+            &mut terrain_height,
+        );
+        get_steepness_map(&mut terrain_steepness, &terrain_height);
+
+        let country_layout = &generation_options.country_layout;
+        let column_start = position * IVec2::new(CHUNK_SIZE[0] as i32, CHUNK_SIZE[2] as i32);
+        let mut column = Self {
+            heights: terrain_height,
+            surface_blocks: [[BlockType::Stone; CHUNK_SIZE[0] + 2]; CHUNK_SIZE[0] + 2],
+            wall_bases: [[0.; CHUNK_SIZE[0] + 2]; CHUNK_SIZE[0] + 2],
+            palettes: [[country_layout.palette(country_layout.country_at(column_start));
+                CHUNK_SIZE[0] + 2]; CHUNK_SIZE[0] + 2],
+            min_height: get_min_in_noise_map(&terrain_height),
+        };
+
+        let all_paths = get_all_paths(country_cache);
+
+        let column_end = column_start
+            + IVec2::new(CHUNK_SIZE[0] as i32 + 2, CHUNK_SIZE[2] as i32 + 2)
+                * chunk_lod.multiplier_i32();
+        let structure_instances = country_cache.structure_instances(
+            column_start,
+            column_end,
+            chunk_lod.multiplier_i32(),
+            generation_options,
+        );
+
+        for x in 0..CHUNK_SIZE[0] + 2 {
+            for z in 0..CHUNK_SIZE[2] + 2 {
+                let total_x =
+                    position.x * CHUNK_SIZE[0] as i32 + x as i32 * chunk_lod.multiplier_i32();
+                let total_z =
+                    position.y * CHUNK_SIZE[2] as i32 + z as i32 * chunk_lod.multiplier_i32();
+
+                let steepness = if x > 0 && z > 0 && x <= CHUNK_SIZE[0] && z <= CHUNK_SIZE[2] {
+                    terrain_steepness[x - 1][z - 1]
+                } else {
+                    0.
+                };
+
+                let mut noise_height = terrain_height[x][z];
+
+                let is_snow = noise_height * chunk_lod.multiplier_f32() > 3500. / VOXEL_SIZE;
+                let is_grass_steep = if is_snow {
+                    steepness < 1.2
+                } else {
+                    steepness < 0.8
+                };
+
+                let (path_distance, _, _, line) = get_min_distance_to_path(
+                    IVec2::new(total_x, total_z),
+                    &all_paths,
+                    IVec2::ONE * road_grading.reach().ceil() as i32,
+                );
+
+                // Roads and foundations are in full resolution voxels, the column in `chunk_lod`
+                // ones.
+                let mut full_height = lod_to_full_height(noise_height, chunk_lod);
+
+                let mut road_surface = RoadSurface::Terrain;
+                if let Some(line) = line {
+                    let road_height = line.height_at(IVec2::new(total_x, total_z).as_vec2());
+                    let (height, surface) =
+                        road_grading.blend(full_height, road_height, path_distance);
+
+                    full_height = height;
+                    road_surface = surface;
+                    if surface == RoadSurface::RetainingWall {
+                        column.wall_bases[x][z] = full_to_lod_height(road_height, chunk_lod);
+                    }
+                }
+
+                for instance in &structure_instances {
+                    full_height =
+                        instance.adjust_terrain(IVec2::new(total_x, total_z), full_height);
+                }
+
+                if road_surface != RoadSurface::Terrain || !structure_instances.is_empty() {
+                    noise_height = full_to_lod_height(full_height, chunk_lod);
+                }
+
+                column.heights[x][z] = noise_height;
+                column.palettes[x][z] =
+                    country_layout.palette(country_layout.country_at(IVec2::new(total_x, total_z)));
+                column.surface_blocks[x][z] = if road_surface == RoadSurface::Road {
+                    BlockType::Path
+                } else if road_surface == RoadSurface::RetainingWall {
+                    BlockType::RetainingWall
+                } else if !is_grass_steep {
+                    BlockType::Stone
+                } else if is_snow {
+                    BlockType::Snow
+                } else {
+                    BlockType::Grass
+                };
+            }
+        }
+
+        column.min_height = get_min_in_noise_map(&column.heights);
+        // Buried structures reach below the terrain, the chunks have to start below them.
+        for instance in &structure_instances {
+            if instance.foundation == FoundationMode::Buried {
+                column.min_height = column
+                    .min_height
+                    .min(full_to_lod_height(instance.base_height, chunk_lod));
+            }
+        }
+
+        column
+    }
+}
+
 pub fn generate_voxels(
     position: [i32; 3],
     generation_options: &GenerationOptions,
@@ -53,28 +230,45 @@ pub fn generate_voxels(
 
     let terrain_noise = get_terrain_noise(chunk_lod, generation_options);
 
-    let mut terrain_height = [[0f32; CHUNK_SIZE[0] + 2]; CHUNK_SIZE[0] + 2];
-    let mut terrain_steepness = [[0f32; CHUNK_SIZE[0]]; CHUNK_SIZE[0]];
-    get_noise_map(
-        IVec2::new(position[0], position[2])
-            * IVec2::new(CHUNK_SIZE[0] as i32, CHUNK_SIZE[2] as i32),
-        chunk_lod.multiplier_i32(),
-        &terrain_noise,
-        &mut terrain_height,
-    );
-    get_steepness_map(&mut terrain_steepness, &terrain_height);
+    let column_position = IVec2::new(position[0], position[2]);
+    let column =
+        generation_options
+            .column_cache
+            .get_or_generate((column_position, chunk_lod), || {
+                ColumnData::generate(
+                    column_position,
+                    chunk_lod,
+                    &terrain_noise,
+                    country_cache,
+                    generation_options,
+                )
+            });
 
-    let min_height = (get_min_in_noise_map(&terrain_height) as i32).max(2) - 2
-        + position[1] * CHUNK_SIZE[1] as i32
+    let min_height = (column.min_height as i32).max(2) - 2 + position[1] * CHUNK_SIZE[1] as i32
         - 10 / chunk_lod.multiplier_i32();
 
     let mut generate_more: bool = false;
 
-    let all_paths = vec![
-        &country_cache.this_path_cache.paths,
-        &country_cache.bottom_path_cache.paths,
-        &country_cache.left_path_cache.paths,
-    ];
+    let chunk_start = IVec2::new(
+        position[0] * CHUNK_SIZE[0] as i32,
+        position[2] * CHUNK_SIZE[2] as i32,
+    );
+    let chunk_end = chunk_start
+        + IVec2::new(CHUNK_SIZE[0] as i32 + 2, CHUNK_SIZE[2] as i32 + 2)
+            * chunk_lod.multiplier_i32();
+    // Props are moved onto the grid of the LOD, by less than one of its voxels.
+    let road_props = get_road_props_in_area(
+        country_cache.road_props(),
+        &generation_options.road_prop_models,
+        chunk_start - chunk_lod.multiplier_i32(),
+        chunk_end,
+    );
+    let structure_instances = country_cache.structure_instances(
+        chunk_start,
+        chunk_end,
+        chunk_lod.multiplier_i32(),
+        generation_options,
+    );
 
     for x in 0..CHUNK_SIZE[0] + 2 {
         for z in 0..CHUNK_SIZE[2] + 2 {
@@ -83,54 +277,10 @@ pub fn generate_voxels(
             let total_z =
                 position[2] * CHUNK_SIZE[2] as i32 + z as i32 * chunk_lod.multiplier_i32();
 
-            //let dryness = value_noise.get([total_x as f64, total_z as f64]);
-            //let mountain = mountain_noise.get([total_x as f64, total_z as f64]);
-
-            let steepness = if x > 0 && z > 0 && x <= CHUNK_SIZE[0] && z <= CHUNK_SIZE[2] {
-                terrain_steepness[x - 1][z - 1]
-            } else {
-                0.
-            };
-
-            let mut noise_height = terrain_height[x][z];
-
-            let is_snow = noise_height * chunk_lod.multiplier_f32() > 3500. / VOXEL_SIZE;
-            let is_grass_steep = if is_snow {
-                steepness < 1.2
-            } else {
-                steepness < 0.8
-            };
-
-            let (mut path_distance, closest_point_on_path, _, line) =
-                get_min_distance_to_path(IVec2::new(total_x, total_z), &all_paths, IVec2::ONE * 15);
-            let is_path = path_distance <= 8.75;
-
-            path_distance /= 10.;
-
-            if path_distance <= 1.65 {
-                let path_start_height =
-                    terrain_noise.get(line.unwrap().start.as_dvec2().to_array()) as f32;
-                let path_end_height =
-                    terrain_noise.get(line.unwrap().end.as_dvec2().to_array()) as f32;
-                let path_height = lerp(
-                    path_start_height,
-                    path_end_height,
-                    line.unwrap().get_progress_on_line(closest_point_on_path),
-                );
-
-                let closest_point_height =
-                    terrain_noise.get(closest_point_on_path.as_dvec2().to_array()) as f32;
-                let closest_point_height = lerp(closest_point_height, noise_height, 0.5);
-
-                let path_height = lerp(closest_point_height, path_height, 0.5);
-
-                noise_height = lerp(
-                    noise_height,
-                    path_height,
-                    (1.65 - path_distance.powi(2)).clamp(0., 1.),
-                )
-                .max(noise_height - 10.);
-            }
+            let noise_height = column.heights[x][z];
+            let surface_block = column.surface_blocks[x][z];
+            let wall_base = column.wall_bases[x][z].floor() as usize;
+            let palette = column.palettes[x][z];
 
             for y in min_height as usize
                 ..noise_height.min((CHUNK_SIZE[1] + 2 + min_height as usize) as f32) as usize
@@ -138,22 +288,99 @@ pub fn generate_voxels(
                 if y == CHUNK_SIZE[1] + 1 + min_height as usize {
                     generate_more = true;
                 }
-                blocks[x][y - min_height as usize][z] = if is_path {
-                    BlockType::Path
-                } else {
-                    if is_grass_steep && y + 1 == noise_height.floor() as usize {
-                        if is_snow {
-                            BlockType::Snow
-                        } else {
-                            BlockType::Grass
+                blocks[x][y - min_height as usize][z] = palette.apply(match surface_block {
+                    BlockType::Path => BlockType::Path,
+                    BlockType::RetainingWall if y >= wall_base => BlockType::RetainingWall,
+                    _ if y + 1 == noise_height.floor() as usize => surface_block,
+                    _ => BlockType::Stone,
+                });
+            }
+
+            for (prop, prop_model) in &road_props {
+                let size = IVec2::new(prop_model.model_size[0], prop_model.model_size[2]);
+                let (Some(level), Some(model_position)) = (
+                    prop_model.model.level(chunk_lod),
+                    lod_footprint_cell(
+                        IVec2::new(total_x, total_z),
+                        prop.position - size / 2,
+                        size,
+                        chunk_lod,
+                    ),
+                ) else {
+                    continue;
+                };
+
+                generate_more |= place_model_column(
+                    &mut blocks[x],
+                    z,
+                    &level[model_position.x as usize],
+                    model_position.y as usize,
+                    prop.height.floor() as i32,
+                    chunk_lod,
+                    min_height,
+                    |block| (block != BlockType::Air).then_some(block),
+                );
+            }
+
+            for instance in &structure_instances {
+                let structure = &generation_options.structures[instance.generator];
+                let palette = structure.palettes.get(instance.palette);
+                let recolor = |block| palette.map_or(block, |palette| palette.apply(block));
+                let model = &structure.variants[instance.variant].model;
+                let base_height = instance.base_height as i32;
+
+                let local = IVec2::new(total_x, total_z) - instance.position;
+                let in_footprint =
+                    local.cmpge(IVec2::ZERO).all() && local.cmplt(instance.size).all();
+                if in_footprint && instance.is_stilt(local) {
+                    let model_position = instance.model_position(local);
+                    let lowest_block = recolor(
+                        model.blocks()[model_position.x as usize][0][model_position.y as usize],
+                    );
+                    if lowest_block != BlockType::Air {
+                        let ground = noise_height.floor() as i32;
+                        for y in ground.max(min_height)
+                            ..full_to_lod_height(base_height as f32, chunk_lod).floor() as i32
+                        {
+                            if y - min_height >= (CHUNK_SIZE[1] + 2) as i32 {
+                                generate_more = true;
+                                break;
+                            }
+                            blocks[x][(y - min_height) as usize][z] = lowest_block;
                         }
-                    } else {
-                        BlockType::Stone
                     }
+                }
+
+                let (Some(level), Some(model_position)) = (
+                    model.level(chunk_lod),
+                    instance.lod_model_position(IVec2::new(total_x, total_z), chunk_lod),
+                ) else {
+                    continue;
                 };
+
+                generate_more |= place_model_column(
+                    &mut blocks[x],
+                    z,
+                    &level[model_position.x as usize],
+                    model_position.y as usize,
+                    base_height,
+                    chunk_lod,
+                    min_height,
+                    |block| {
+                        if block == BlockType::Air {
+                            (instance.foundation == FoundationMode::Buried).then_some(block)
+                        } else {
+                            Some(recolor(block))
+                        }
+                    },
+                );
             }
 
             for structure in &generation_options.structures {
+                if !structure.generate_debug_blocks {
+                    continue;
+                }
+
                 let structure_offset_x = div_floor(
                     total_x + structure.grid_offset[0],
                     structure.generation_size[0],
@@ -167,116 +394,23 @@ pub fn generate_voxels(
                     .get_noise(structure_offset_x as f32, structure_offset_z as f32)
                     * 0.5
                     + 0.5;
-                if structure.generate_debug_blocks {
-                    let top_terrain = (noise_height.min(CHUNK_SIZE[1] as f32 + min_height as f32)
-                        as i32
-                        - min_height.min(noise_height as i32))
-                    .max(1) as usize
-                        - 1;
-                    let current_color = match blocks[x][top_terrain][z] {
-                        BlockType::StructureDebug(r, g, b) => (r, g, b),
-                        _ => (0u8, 0u8, 0u8),
-                    };
-                    blocks[x][top_terrain][z] = BlockType::StructureDebug(
-                        ((structure_value) * structure.debug_rgb_multiplier[0] * 255.) as u8
-                            + current_color.0,
-                        ((structure_value) * structure.debug_rgb_multiplier[1] * 255.) as u8
-                            + current_color.1,
-                        ((structure_value) * structure.debug_rgb_multiplier[2] * 255.) as u8
-                            + current_color.2,
-                    )
-                }
-                let mut rand = StdRng::seed_from_u64((structure_value.abs() * 10000.) as u64);
-
-                if structure_value > 0. {
-                    let random_x =
-                        rand.gen_range(0..=structure.generation_size[0] - structure.model_size[0]);
-                    let random_z =
-                        rand.gen_range(0..=structure.generation_size[1] - structure.model_size[2]);
-
-                    let structure_x: i32 = (total_x + structure.grid_offset[0]
-                        - structure_offset_x * structure.generation_size[0])
-                        .abs()
-                        - random_x;
-                    let structure_z: i32 = (total_z + structure.grid_offset[1]
-                        - structure_offset_z * structure.generation_size[1])
-                        .abs()
-                        - random_z;
-
-                    if structure_x < 0
-                        || structure_z < 0
-                        || structure_x >= structure.model_size[0]
-                        || structure_z >= structure.model_size[2]
-                    {
-                        continue;
-                    }
-
-                    let structure_noise_height_x = structure_offset_x
-                        * structure.generation_size[0]
-                        + (structure.model_size[0] / 2)
-                        - structure.grid_offset[0]
-                        + random_x;
-                    let structure_noise_height_z = structure_offset_z
-                        * structure.generation_size[1]
-                        + (structure.model_size[2] / 2)
-                        - structure.grid_offset[1]
-                        + random_z;
-
-                    let structure_center: IVec2 =
-                        [structure_noise_height_x, structure_noise_height_z].into();
-
-                    let country_bounds_check =
-                        structure_center - country_cache.country_pos * COUNTRY_SIZE as i32;
-                    if country_bounds_check.x >= 0
-                        && country_bounds_check.y >= 0
-                        && country_bounds_check.x < COUNTRY_SIZE as i32 - 1
-                        && country_bounds_check.y < COUNTRY_SIZE as i32 - 1
-                    {
-                        let (a, _, _, _) = get_min_distance_to_path(
-                            structure_center,
-                            &all_paths,
-                            IVec2::new(structure.model_size[0] / 2, structure.model_size[2] / 2)
-                                + IVec2::ONE * 10,
-                        );
-
-                        if (a as i32) < structure.model_size[0] / 2 + structure.model_size[1] / 2 {
-                            continue;
-                        }
-                    }
-
-                    let noise_height = terrain_noise.get([
-                        structure_noise_height_x as f64,
-                        structure_noise_height_z as f64,
-                    ]);
-
-                    for (index, sub_structure) in
-                        structure.model[structure_x as usize].iter().enumerate()
-                    {
-                        if (index as i32
-                            + (noise_height * chunk_lod.multiplier_i32() as f64) as i32)
-                            % chunk_lod.multiplier_i32()
-                            != 0
-                        {
-                            continue;
-                        }
-                        let chunk_index = index / chunk_lod.multiplier_i32() as usize;
-                        if (noise_height as i32 - min_height + chunk_index as i32) < 0 {
-                            continue;
-                        }
-                        let structure_block = sub_structure[structure_z as usize];
-                        if structure_block == BlockType::Air {
-                            continue;
-                        }
-                        if noise_height as usize + chunk_index - min_height as usize
-                            >= CHUNK_SIZE[1] + 2
-                        {
-                            generate_more = true;
-                            break;
-                        }
-                        blocks[x][noise_height as usize + chunk_index - min_height as usize][z] =
-                            structure_block;
-                    }
-                }
+                let top_terrain = (noise_height.min(CHUNK_SIZE[1] as f32 + min_height as f32)
+                    as i32
+                    - min_height.min(noise_height as i32))
+                .max(1) as usize
+                    - 1;
+                let current_color = match blocks[x][top_terrain][z] {
+                    BlockType::StructureDebug(r, g, b) => (r, g, b),
+                    _ => (0u8, 0u8, 0u8),
+                };
+                blocks[x][top_terrain][z] = BlockType::StructureDebug(
+                    ((structure_value) * structure.debug_rgb_multiplier[0] * 255.) as u8
+                        + current_color.0,
+                    ((structure_value) * structure.debug_rgb_multiplier[1] * 255.) as u8
+                        + current_color.1,
+                    ((structure_value) * structure.debug_rgb_multiplier[2] * 255.) as u8
+                        + current_color.2,
+                )
             }
         }
     }
@@ -444,7 +578,7 @@ fn get_min_in_noise_map<const SIZE: usize, T: PartialOrd + Copy>(map: &[[T; SIZE
     min
 }
 
-fn get_min_distance_to_path<'a>(
+pub fn get_min_distance_to_path<'a>(
     pos: IVec2,
     paths_list: &'a Vec<&'a Vec<Path>>,
     margin: IVec2,
@@ -498,6 +632,61 @@ fn get_min_distance_to_path<'a>(
     )
 }
 
-fn lerp(a: f32, b: f32, f: f32) -> f32 {
-    a + f * (b - a)
+/// Writes the column `model_z` of `model_column`, a level of a model for `chunk_lod`, into
+/// `blocks`, passing every block through `map_block`, which returns `None` for blocks to leave
+/// out. The model stands on the LOD block containing the full resolution height `base_height`.
+/// Returns whether the model reaches above the chunk.
+fn place_model_column(
+    blocks: &mut [[BlockType; CHUNK_SIZE[2] + 2]; CHUNK_SIZE[1] + 2],
+    z: usize,
+    model_column: &[Vec<BlockType>],
+    model_z: usize,
+    base_height: i32,
+    chunk_lod: ChunkLod,
+    min_height: i32,
+    map_block: impl Fn(BlockType) -> Option<BlockType>,
+) -> bool {
+    let base_block = full_to_lod_block(base_height, chunk_lod);
+
+    for (index, sub_structure) in model_column.iter().enumerate() {
+        let Some(model_block) = map_block(sub_structure[model_z]) else {
+            continue;
+        };
+
+        let y = base_block + index as i32 - min_height;
+        if y < 0 {
+            continue;
+        }
+        if y as usize >= CHUNK_SIZE[1] + 2 {
+            return true;
+        }
+        blocks[y as usize][z] = model_block;
+    }
+
+    false
+}
+
+/// Props of `road_props` whose model overlaps the area between `start` and `end`.
+fn get_road_props_in_area<'a>(
+    road_props: impl Iterator<Item = &'a RoadProp>,
+    prop_models: &'a RoadPropModels,
+    start: IVec2,
+    end: IVec2,
+) -> Vec<(&'a RoadProp, &'a RoadPropModel)> {
+    road_props
+        .map(|prop| (prop, prop_models.get(prop.kind)))
+        .filter(|(prop, prop_model)| {
+            let half_size = IVec2::new(prop_model.model_size[0], prop_model.model_size[2]) / 2 + 1;
+            (prop.position + half_size).cmpge(start).all()
+                && (prop.position - half_size).cmplt(end).all()
+        })
+        .collect()
+}
+
+fn get_all_paths(country_cache: &CountryCache) -> Vec<&Vec<Path>> {
+    country_cache
+        .path_caches
+        .iter()
+        .map(|path_cache| &path_cache.paths)
+        .collect()
 }
@@ -1,3 +1,6 @@
 pub mod chunk_loader;
 pub mod country_cache;
+pub mod country_layout;
 pub mod quad_tree_data;
+pub mod road_decoration;
+pub mod road_grading;
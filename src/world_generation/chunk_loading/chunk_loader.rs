@@ -1,13 +1,21 @@
 use crate::animations::DespawnAnimation;
+use crate::world_generation::chunk_events::{ChunkBounds, ChunkUnloaded};
 use crate::world_generation::chunk_generation::{
     ChunkGenerationTask, ChunkGenerator, ChunkParent, CHUNK_SIZE, VOXEL_SIZE,
 };
+use crate::world_generation::floating_origin::WorldPosition;
 use crate::world_generation::voxel_world::{ChunkLod, QuadTreeVoxelWorld, VoxelWorld, MAX_LOD};
 use bevy::log::info;
+use bevy::math::DVec3;
 use bevy::prelude::{
-    App, Commands, Component, Entity, IntoSystemConfigs, Plugin, Query, ResMut, Transform, Update,
-    Vec3,
+    App, Commands, Component, Entity, EventWriter, IVec2, IntoSystemConfigs, Plugin, Query, Res,
+    ResMut, Time, Update, Vec2, Vec3, Vec3Swizzles,
 };
+use std::f32::consts::PI;
+
+/// Split chunks of a [`LoadShape::ViewWeighted`] loader stay split until they are this far outside
+/// the view cone, so looking around doesn't keep merging and splitting the chunks at its edge.
+const VIEW_HYSTERESIS: f32 = PI / 12.;
 
 pub struct ChunkLoaderPlugin;
 
@@ -17,15 +25,94 @@ impl Plugin for ChunkLoaderPlugin {
             Update,
             (load_chunks, unload_chunks)
                 .after(crate::world_generation::chunk_generation::upgrade_quad_trees),
+        )
+        .add_systems(
+            Update,
+            update_chunk_loader_velocity
+                .before(crate::world_generation::chunk_generation::upgrade_quad_trees),
         );
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LoadShape {
+    Square,
+    Circle,
+    /// Loads and unloads like [`LoadShape::Circle`], but only splits chunks outside the view cone
+    /// within `outside_view_factor` of the LOD range.
+    ViewWeighted {
+        half_fov: f32,
+        outside_view_factor: f32,
+    },
+}
+
+impl LoadShape {
+    pub fn contains(&self, offset: IVec2, range: i32) -> bool {
+        match self {
+            LoadShape::Square => offset.x.abs() <= range && offset.y.abs() <= range,
+            LoadShape::Circle | LoadShape::ViewWeighted { .. } => {
+                offset.as_vec2().length() <= range as f32 + 0.5
+            }
+        }
+    }
+
+    pub fn contains_in_view(&self, offset: IVec2, range: i32, view_direction: Vec2) -> bool {
+        match self {
+            LoadShape::ViewWeighted {
+                half_fov,
+                outside_view_factor,
+            } => {
+                let distance = offset.as_vec2().length();
+                if distance <= 1.5 || Self::is_in_view(offset, view_direction, *half_fov) {
+                    distance <= range as f32 + 0.5
+                } else {
+                    distance <= range as f32 * outside_view_factor + 0.5
+                }
+            }
+            _ => self.contains(offset, range),
+        }
+    }
+
+    pub fn chunk_offsets(&self, range: i32) -> Vec<IVec2> {
+        let mut offsets = vec![];
+
+        for x in -range..range + 1 {
+            for z in -range..range + 1 {
+                let offset = IVec2::new(x, z);
+                if self.contains(offset, range) {
+                    offsets.push(offset);
+                }
+            }
+        }
+
+        offsets
+    }
+
+    fn is_in_view(offset: IVec2, view_direction: Vec2, half_fov: f32) -> bool {
+        if view_direction == Vec2::ZERO {
+            return true;
+        }
+
+        offset
+            .as_vec2()
+            .normalize_or_zero()
+            .dot(view_direction.normalize())
+            >= half_fov.cos()
+    }
+}
+
 #[derive(Component)]
 pub struct ChunkLoader {
     pub load_range: i32,
     pub unload_range: i32,
     pub lod_range: [i32; MAX_LOD.usize() - 1],
+    pub load_shape: LoadShape,
+    /// Horizontal direction the loader is looking at, used by [`LoadShape::ViewWeighted`].
+    pub view_direction: Vec2,
+    /// How many seconds ahead chunks are preloaded along the current velocity. 0 disables it.
+    pub lookahead_time: f32,
+    pub velocity: Vec3,
+    pub(crate) last_position: Option<DVec3>,
 }
 
 impl Default for ChunkLoader {
@@ -34,21 +121,111 @@ impl Default for ChunkLoader {
             load_range: 8,
             unload_range: 10,
             lod_range: [2, 2, 2, 2, 2, 2, 2],
+            load_shape: LoadShape::Square,
+            view_direction: Vec2::ZERO,
+            lookahead_time: 0.,
+            velocity: Vec3::ZERO,
+            last_position: None,
+        }
+    }
+}
+
+impl ChunkLoader {
+    pub fn predicted_position(&self, position: DVec3) -> DVec3 {
+        position + (self.velocity * self.lookahead_time).as_dvec3()
+    }
+
+    /// Positions along the projected path, starting at `position` and at most `spacing` apart.
+    pub fn lookahead_positions(
+        &self,
+        position: DVec3,
+        spacing: f32,
+    ) -> impl Iterator<Item = DVec3> {
+        let offset = self.predicted_position(position) - position;
+        let steps = (offset.xz().length() / spacing as f64).ceil().min(64.) as usize;
+
+        (0..=steps).map(move |step| position + offset * step as f64 / steps.max(1) as f64)
+    }
+
+    /// Lower values get generated first. Chunks ahead of the loader get preferred over the ones
+    /// behind it while it is moving.
+    pub fn priority(&self, position: DVec3, chunk_center: DVec3) -> f32 {
+        let offset = (chunk_center - position).xz().as_vec2();
+        let mut distance = offset.length();
+        let velocity = self.velocity.xz();
+
+        if let LoadShape::ViewWeighted { half_fov, .. } = self.load_shape {
+            if !LoadShape::is_in_view(offset.round().as_ivec2(), self.view_direction, half_fov) {
+                distance *= 2.;
+            }
+        }
+
+        if self.lookahead_time <= 0. || velocity == Vec2::ZERO {
+            return distance;
+        }
+
+        let alignment = offset.normalize_or_zero().dot(velocity.normalize());
+        distance * (1. - alignment * 0.5)
+    }
+
+    /// Whether the chunk at `offset` should be split into chunks of the next lower LOD. Chunks
+    /// that `is_split` already get a wider view cone.
+    pub fn should_split(&self, offset: IVec2, lod: ChunkLod, is_split: bool) -> bool {
+        let range = self.lod_range[MAX_LOD.usize() - lod.usize()];
+        let load_shape = match self.load_shape {
+            LoadShape::ViewWeighted {
+                half_fov,
+                outside_view_factor,
+            } if is_split => LoadShape::ViewWeighted {
+                half_fov: half_fov + VIEW_HYSTERESIS,
+                outside_view_factor,
+            },
+            load_shape => load_shape,
+        };
+
+        load_shape.contains_in_view(offset, range, self.view_direction)
+    }
+}
+
+fn update_chunk_loader_velocity(
+    time: Res<Time>,
+    mut chunk_loaders: Query<(&mut ChunkLoader, &WorldPosition)>,
+) {
+    let delta = time.delta_seconds();
+
+    for (mut chunk_loader, world_position) in &mut chunk_loaders {
+        if let Some(last_position) = chunk_loader.last_position {
+            if delta > 0. {
+                let current_velocity = (world_position.0 - last_position).as_vec3() / delta;
+                chunk_loader.velocity = chunk_loader
+                    .velocity
+                    .lerp(current_velocity, (delta * 4.).min(1.));
+            }
         }
+
+        chunk_loader.last_position = Some(world_position.0);
     }
 }
 
 fn load_chunks(
     mut voxel_world: ResMut<QuadTreeVoxelWorld>,
     mut commands: Commands,
-    chunk_loaders: Query<(&ChunkLoader, &Transform)>,
+    chunk_loaders: Query<(&ChunkLoader, &WorldPosition)>,
 ) {
-    for (chunk_loader, transform) in &chunk_loaders {
-        let loader_chunk_pos = get_chunk_position(transform.translation, MAX_LOD);
+    for (chunk_loader, world_position) in &chunk_loaders {
+        for position in
+            chunk_loader.lookahead_positions(world_position.0, chunk_world_size(MAX_LOD))
+        {
+            let loader_chunk_pos = get_chunk_position(position, MAX_LOD);
 
-        for x in -chunk_loader.load_range..chunk_loader.load_range + 1 {
-            for z in -chunk_loader.load_range..chunk_loader.load_range + 1 {
-                let chunk_pos = [loader_chunk_pos[0] + x, loader_chunk_pos[1] + z];
+            for offset in chunk_loader
+                .load_shape
+                .chunk_offsets(chunk_loader.load_range)
+            {
+                let chunk_pos = [
+                    loader_chunk_pos[0] + offset.x,
+                    loader_chunk_pos[1] + offset.y,
+                ];
                 if !voxel_world.has_chunk(chunk_pos) {
                     commands.spawn((ChunkGenerator(chunk_pos), ChunkParent(chunk_pos)));
                     if !voxel_world.add_chunk(chunk_pos, None) {
@@ -63,22 +240,32 @@ fn load_chunks(
 fn unload_chunks(
     mut voxel_world: ResMut<QuadTreeVoxelWorld>,
     mut commands: Commands,
-    chunk_loaders: Query<(&ChunkLoader, &Transform)>,
+    chunk_loaders: Query<(&ChunkLoader, &WorldPosition)>,
     chunks: Query<(Entity, &ChunkParent)>,
     children: Query<(Entity, &ChunkGenerationTask)>,
+    mut chunk_unloaded_events: EventWriter<ChunkUnloaded>,
 ) {
     for (entity, chunk_parent) in &chunks {
         let mut should_unload = true;
 
         let chunk_position = chunk_parent.0;
 
-        for (chunk_loader, chunk_loader_transform) in &chunk_loaders {
-            let loader_chunk_pos = get_chunk_position(chunk_loader_transform.translation, MAX_LOD);
-            if (chunk_position[0] - loader_chunk_pos[0]).abs() < chunk_loader.unload_range
-                && (chunk_position[1] - loader_chunk_pos[1]).abs() < chunk_loader.unload_range
+        'loaders: for (chunk_loader, world_position) in &chunk_loaders {
+            for position in
+                chunk_loader.lookahead_positions(world_position.0, chunk_world_size(MAX_LOD))
             {
-                should_unload = false;
-                break;
+                let loader_chunk_pos = get_chunk_position(position, MAX_LOD);
+                let offset = IVec2::new(
+                    chunk_position[0] - loader_chunk_pos[0],
+                    chunk_position[1] - loader_chunk_pos[1],
+                );
+                if chunk_loader
+                    .load_shape
+                    .contains(offset, chunk_loader.unload_range - 1)
+                {
+                    should_unload = false;
+                    break 'loaders;
+                }
             }
         }
 
@@ -91,6 +278,13 @@ fn unload_chunks(
             chunk_owner
                 .remove::<ChunkParent>()
                 .insert(DespawnAnimation::default());
+
+            let parent_pos = IVec2::from_array(chunk_position);
+            chunk_unloaded_events.send(ChunkUnloaded {
+                chunk_parent: entity,
+                parent_pos,
+                bounds: ChunkBounds::column(parent_pos, MAX_LOD, IVec2::ZERO),
+            });
             for child in &children {
                 if child.1 .1 == entity {
                     info!("Cancelled Child!");
@@ -101,11 +295,143 @@ fn unload_chunks(
     }
 }
 
-pub fn get_chunk_position(global_position: Vec3, lod: ChunkLod) -> [i32; 2] {
+pub fn chunk_world_size(lod: ChunkLod) -> f32 {
+    CHUNK_SIZE[0] as f32 * VOXEL_SIZE * lod.multiplier_f32()
+}
+
+pub fn get_chunk_position(global_position: DVec3, lod: ChunkLod) -> [i32; 2] {
     [
-        (global_position.x / (CHUNK_SIZE[0] as f32 * VOXEL_SIZE * lod.multiplier_f32())).floor()
-            as i32,
-        (global_position.z / (CHUNK_SIZE[2] as f32 * VOXEL_SIZE * lod.multiplier_f32())).floor()
-            as i32,
+        (global_position.x
+            / (CHUNK_SIZE[0] as f64 * VOXEL_SIZE as f64 * lod.multiplier_i32() as f64))
+            .floor() as i32,
+        (global_position.z
+            / (CHUNK_SIZE[2] as f64 * VOXEL_SIZE as f64 * lod.multiplier_i32() as f64))
+            .floor() as i32,
     ]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn square_selects_full_square() {
+        let offsets = LoadShape::Square.chunk_offsets(3);
+
+        assert_eq!(offsets.len(), 7 * 7);
+        assert!(offsets.contains(&IVec2::new(3, 3)));
+        assert!(offsets.contains(&IVec2::new(-3, 3)));
+        assert!(!offsets.contains(&IVec2::new(4, 0)));
+    }
+
+    #[test]
+    fn circle_skips_corners() {
+        let offsets = LoadShape::Circle.chunk_offsets(3);
+
+        assert!(offsets.contains(&IVec2::ZERO));
+        assert!(offsets.contains(&IVec2::new(3, 0)));
+        assert!(offsets.contains(&IVec2::new(0, -3)));
+        assert!(offsets.contains(&IVec2::new(2, 2)));
+        assert!(!offsets.contains(&IVec2::new(3, 3)));
+        assert!(!offsets.contains(&IVec2::new(-3, 2)));
+        assert!(offsets.len() < LoadShape::Square.chunk_offsets(3).len());
+    }
+
+    #[test]
+    fn circle_is_symmetric() {
+        let offsets = LoadShape::Circle.chunk_offsets(5);
+
+        for offset in &offsets {
+            assert!(offsets.contains(&IVec2::new(-offset.x, offset.y)));
+            assert!(offsets.contains(&IVec2::new(offset.y, offset.x)));
+        }
+    }
+
+    #[test]
+    fn view_weighted_prefers_view_direction() {
+        let shape = LoadShape::ViewWeighted {
+            half_fov: 45f32.to_radians(),
+            outside_view_factor: 0.5,
+        };
+        let view_direction = Vec2::X;
+
+        assert!(shape.contains_in_view(IVec2::new(4, 0), 4, view_direction));
+        assert!(shape.contains_in_view(IVec2::new(3, 2), 4, view_direction));
+        assert!(!shape.contains_in_view(IVec2::new(-4, 0), 4, view_direction));
+        assert!(!shape.contains_in_view(IVec2::new(0, 4), 4, view_direction));
+        assert!(shape.contains_in_view(IVec2::new(-2, 0), 4, view_direction));
+        assert!(shape.contains_in_view(IVec2::new(-1, -1), 4, view_direction));
+    }
+
+    #[test]
+    fn view_weighted_loads_like_circle() {
+        let shape = LoadShape::ViewWeighted {
+            half_fov: 45f32.to_radians(),
+            outside_view_factor: 0.5,
+        };
+
+        assert_eq!(shape.chunk_offsets(4), LoadShape::Circle.chunk_offsets(4));
+    }
+
+    #[test]
+    fn view_weighted_without_direction_splits_everywhere() {
+        let shape = LoadShape::ViewWeighted {
+            half_fov: 45f32.to_radians(),
+            outside_view_factor: 0.5,
+        };
+
+        for offset in LoadShape::Circle.chunk_offsets(4) {
+            assert!(shape.contains_in_view(offset, 4, Vec2::ZERO));
+        }
+    }
+
+    #[test]
+    fn view_weighted_keeps_split_chunks_near_the_view_cone() {
+        let chunk_loader = ChunkLoader {
+            lod_range: [8; MAX_LOD.usize() - 1],
+            load_shape: LoadShape::ViewWeighted {
+                half_fov: 45f32.to_radians(),
+                outside_view_factor: 0.5,
+            },
+            view_direction: Vec2::X,
+            ..Default::default()
+        };
+
+        // 51 degrees off the view direction.
+        let edge = IVec2::new(4, 5);
+        assert!(!chunk_loader.should_split(edge, ChunkLod::Half, false));
+        assert!(chunk_loader.should_split(edge, ChunkLod::Half, true));
+
+        let behind = IVec2::new(-5, 4);
+        assert!(!chunk_loader.should_split(behind, ChunkLod::Half, true));
+        assert!(chunk_loader.should_split(IVec2::new(6, 1), ChunkLod::Half, false));
+    }
+
+    #[test]
+    fn priority_uses_the_rounded_view_offset() {
+        let chunk_loader = ChunkLoader {
+            load_shape: LoadShape::ViewWeighted {
+                half_fov: 30f32.to_radians(),
+                outside_view_factor: 0.5,
+            },
+            view_direction: Vec2::X,
+            ..Default::default()
+        };
+
+        // Truncated to (0, 1) this would be outside the view cone.
+        let in_view = DVec3::new(0.9, 0., 0.4);
+        let distance = in_view.xz().length() as f32;
+        assert_eq!(chunk_loader.priority(DVec3::ZERO, in_view), distance);
+
+        let out_of_view = DVec3::new(0.4, 0., 0.9);
+        assert_eq!(
+            chunk_loader.priority(DVec3::ZERO, out_of_view),
+            distance * 2.
+        );
+    }
+
+    #[test]
+    fn default_load_shape_is_square() {
+        assert_eq!(ChunkLoader::default().load_shape, LoadShape::Square);
+    }
+}
@@ -1,12 +1,20 @@
+use crate::world_generation::chunk_generation::structure_placement::StructureRegion;
 use crate::world_generation::chunk_generation::voxel_generation::get_terrain_noise;
-use crate::world_generation::chunk_generation::BlockType;
-use crate::world_generation::generation_options::{GenerationCacheItem, GenerationOptions};
+use crate::world_generation::chunk_loading::country_layout::{CountryLayout, CountryMetadata};
+use crate::world_generation::chunk_loading::road_decoration::{
+    decorate_junctions, decorate_path, RoadProp, RoadPropKind,
+};
+use crate::world_generation::generation_options::disk_cache::{
+    ByteReader, ByteWriter, DiskCacheItem,
+};
+use crate::world_generation::generation_options::{
+    GenerationCache, GenerationCacheItem, GenerationOptions,
+};
+use crate::world_generation::navigation::RoadGraph;
 use crate::world_generation::voxel_world::ChunkLod;
-use bevy::log::info;
+use bevy::log::{info, warn};
 use bevy::math::{IVec2, Vec2};
 use noise::NoiseFn;
-use rand::prelude::StdRng;
-use rand::{Rng, SeedableRng};
 use std::cmp::Ordering;
 use std::collections::{BinaryHeap, HashMap};
 use std::sync::Arc;
@@ -14,15 +22,25 @@ use std::sync::Arc;
 #[derive(Clone)]
 pub struct CountryCache {
     pub country_pos: IVec2,
-    pub grass_color: BlockType,
+    pub metadata: Arc<CountryMetadata>,
     pub structure_cache: Arc<StructureCache>,
-    pub this_path_cache: Arc<PathCache>,
-    pub bottom_path_cache: Arc<PathCache>,
-    pub left_path_cache: Arc<PathCache>,
+    /// Every road starting or ending in this country.
+    pub path_caches: Vec<Arc<PathCache>>,
+    /// Signposts at the road junctions, of this country and the ones it got merged with.
+    pub junction_props: Vec<RoadProp>,
 }
 
+/// Structure regions kept per country, the least recently used ones get dropped.
+const STRUCTURE_REGION_CAPACITY: usize = 512;
+
+/// Roads are searched on the grid of this LOD, they only stay within their two countries at its
+/// sample points.
+const PATH_FINDING_LOD: ChunkLod = ChunkLod::Sixtyfourth;
+
 pub struct StructureCache {
     pub city_location: IVec2,
+    /// Placed structures by generator and region, filled in as they are asked for.
+    pub instances: GenerationCache<(usize, IVec2), StructureRegion>,
 }
 
 pub struct PathCache {
@@ -33,6 +51,7 @@ pub struct Path {
     pub lines: Vec<PathLine>,
     pub box_pos_start: IVec2,
     pub box_pos_end: IVec2,
+    pub props: Vec<RoadProp>,
 }
 
 impl Path {
@@ -41,6 +60,24 @@ impl Path {
         let bb_end = self.box_pos_end + margin;
         !(point.x < bb_start.x || point.x > bb_end.x || point.y < bb_start.y || point.y > bb_end.y)
     }
+
+    /// Sample points of all lines, the point shared by two lines is only returned once.
+    pub fn sample_points(&self) -> impl Iterator<Item = IVec2> + '_ {
+        self.lines
+            .iter()
+            .enumerate()
+            .flat_map(|(index, line)| line.sample_points.iter().skip((index > 0) as usize))
+            .copied()
+    }
+
+    /// Graded height at every point of [`Path::sample_points`].
+    pub fn sample_heights(&self) -> impl Iterator<Item = f32> + '_ {
+        self.lines
+            .iter()
+            .enumerate()
+            .flat_map(|(index, line)| line.heights.iter().skip((index > 0) as usize))
+            .copied()
+    }
 }
 
 pub struct PathLine {
@@ -52,6 +89,8 @@ pub struct PathLine {
     pub box_pos_end: IVec2,
     pub estimated_length: f32,
     pub sample_points: Vec<IVec2>,
+    /// Graded road height at every sample point, in full resolution voxels.
+    pub heights: Vec<f32>,
 }
 
 impl PathLine {
@@ -78,6 +117,7 @@ impl PathLine {
             box_pos_end,
             estimated_length,
             sample_points: vec![start],
+            heights: vec![],
         };
 
         let num_points = (estimated_length / 20.).max(2.);
@@ -116,6 +156,33 @@ impl PathLine {
         distance_to_start / (distance_to_start + distance_to_end)
     }
 
+    /// Graded road height at the point of the line closest to `position`.
+    pub fn height_at(&self, position: Vec2) -> f32 {
+        let mut closest = (
+            f32::INFINITY,
+            self.heights.first().copied().unwrap_or_default(),
+        );
+
+        for i in 1..self.sample_points.len().min(self.heights.len()) {
+            let start = self.sample_points[i - 1].as_vec2();
+            let end = self.sample_points[i].as_vec2();
+            let length_squared = start.distance_squared(end);
+            let t = if length_squared == 0. {
+                0.
+            } else {
+                ((position - start).dot(end - start) / length_squared).clamp(0., 1.)
+            };
+
+            let distance_squared = position.distance_squared(start.lerp(end, t));
+            if distance_squared < closest.0 {
+                let height = Self::lerp_f32(self.heights[i - 1], self.heights[i], t);
+                closest = (distance_squared, height);
+            }
+        }
+
+        closest.1
+    }
+
     pub fn closest_point_on_path(&self, point: IVec2, margin: IVec2) -> Option<(Vec2, Vec2)> {
         let mut min_squared = i32::MAX;
         let mut closest = Vec2::ZERO;
@@ -149,7 +216,7 @@ impl PathLine {
         }
     }
 
-    fn get_closest_point_to_line(line_start: IVec2, line_end: IVec2, point: IVec2) -> Vec2 {
+    pub fn get_closest_point_to_line(line_start: IVec2, line_end: IVec2, point: IVec2) -> Vec2 {
         let length_squared = (line_end - line_start).length_squared();
         if length_squared == 0 {
             return line_start.as_vec2();
@@ -176,6 +243,10 @@ impl PathLine {
     fn lerp(p1: Vec2, p2: Vec2, t: f32) -> Vec2 {
         (1. - t) * p1 + t * p2
     }
+
+    fn lerp_f32(a: f32, b: f32, t: f32) -> f32 {
+        (1. - t) * a + t * b
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -199,98 +270,222 @@ impl Ord for AStarCandidate {
 }
 
 impl GenerationCacheItem<IVec2> for CountryCache {
+    /// Countries whose roads fail to generate get none.
     fn generate(key: IVec2, generation_options: &GenerationOptions) -> Self {
-        let mut rng = rand::thread_rng();
+        Self::try_generate(key, generation_options)
+            .unwrap_or_else(|_| Self::without_paths(key, generation_options))
+    }
 
-        Self {
+    fn try_generate(key: IVec2, generation_options: &GenerationOptions) -> Result<Self, String> {
+        let metadata = generation_options.country_layout.metadata(key);
+
+        // Roads are stored with the lower one of the two countries they link.
+        let path_caches = std::iter::once(key)
+            .chain(
+                metadata
+                    .neighbours
+                    .iter()
+                    .copied()
+                    .filter(|neighbour| is_lower_country(*neighbour, key)),
+            )
+            .map(|country| {
+                generation_options
+                    .path_cache
+                    .try_get_cache_entry(country, generation_options)
+            })
+            .collect::<Result<Vec<Arc<PathCache>>, _>>()?;
+
+        let road_graph =
+            RoadGraph::from_paths(path_caches.iter().flat_map(|path_cache| &path_cache.paths));
+        let junction_props = decorate_junctions(
+            &road_graph,
+            key,
+            |position| generation_options.country_layout.country_at(position),
+            generation_options.road_grading.half_width,
+        );
+
+        Ok(Self {
             country_pos: key,
-            grass_color: BlockType::Custom(rng.gen(), rng.gen(), rng.gen()),
+            metadata: Arc::new(metadata),
             structure_cache: generation_options
                 .structure_cache
                 .get_cache_entry(key, generation_options),
-            this_path_cache: generation_options
-                .path_cache
+            path_caches,
+            junction_props,
+        })
+    }
+}
+
+impl CountryCache {
+    /// Fallback for countries that keep failing to generate, their terrain is generated without roads.
+    pub fn without_paths(key: IVec2, generation_options: &GenerationOptions) -> Self {
+        Self {
+            country_pos: key,
+            metadata: Arc::new(generation_options.country_layout.metadata(key)),
+            structure_cache: generation_options
+                .structure_cache
                 .get_cache_entry(key, generation_options),
-            bottom_path_cache: generation_options
-                .path_cache
-                .get_cache_entry(key + IVec2::NEG_X, generation_options),
-            left_path_cache: generation_options
-                .path_cache
-                .get_cache_entry(key + IVec2::NEG_Y, generation_options),
+            path_caches: vec![],
+            junction_props: vec![],
         }
     }
+
+    /// Cache for chunks crossing borders, keeps the metadata of `self` and adds the roads of
+    /// `others`.
+    pub fn merged<'a>(&self, others: impl IntoIterator<Item = &'a CountryCache>) -> Self {
+        let mut merged = self.clone();
+
+        for other in others {
+            merged
+                .junction_props
+                .extend(other.junction_props.iter().copied());
+            for path_cache in &other.path_caches {
+                if !merged
+                    .path_caches
+                    .iter()
+                    .any(|existing| Arc::ptr_eq(existing, path_cache))
+                {
+                    merged.path_caches.push(path_cache.clone());
+                }
+            }
+        }
+
+        merged
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.path_caches
+            .iter()
+            .flat_map(|path_cache| &path_cache.paths)
+    }
+
+    /// Props along the roads and at the junctions.
+    pub fn road_props(&self) -> impl Iterator<Item = &RoadProp> {
+        self.paths()
+            .flat_map(|path| &path.props)
+            .chain(&self.junction_props)
+    }
 }
 
-impl GenerationCacheItem<IVec2> for StructureCache {
-    fn generate(key: IVec2, generation_options: &GenerationOptions) -> Self {
-        let mut rng = StdRng::seed_from_u64(if key.x < 0 {
-            generation_options.seed.wrapping_sub(key.x.abs() as u64)
-        } else {
-            generation_options.seed.wrapping_add(key.x.abs() as u64)
-        });
-        let mut rng = StdRng::seed_from_u64(if key.x < 0 {
-            rng.gen::<u64>().wrapping_sub(key.y.abs() as u64)
-        } else {
-            rng.gen::<u64>().wrapping_add(key.y.abs() as u64)
-        });
+fn is_lower_country(a: IVec2, b: IVec2) -> bool {
+    (a.x, a.y) < (b.x, b.y)
+}
 
-        let min_offset = 100i32;
+/// Countries whose [`PathCache`] can hold a road through the area between `min` and `max`. Roads
+/// stay within the two countries they link and are stored with the lower one of them.
+fn road_countries_in_area(country_layout: &CountryLayout, min: IVec2, max: IVec2) -> Vec<IVec2> {
+    let margin = PATH_FINDING_LOD.multiplier_i32();
+    let mut road_countries = vec![];
+    for country in country_layout.countries_in_area(min - margin, max + margin) {
+        for road_country in std::iter::once(country).chain(
+            country_layout
+                .neighbours(country)
+                .into_iter()
+                .filter(|neighbour| is_lower_country(*neighbour, country)),
+        ) {
+            if !road_countries.contains(&road_country) {
+                road_countries.push(road_country);
+            }
+        }
+    }
 
-        let city_x = rng.gen_range(min_offset..COUNTRY_SIZE as i32 - min_offset);
-        let city_z = rng.gen_range(min_offset..COUNTRY_SIZE as i32 - min_offset);
+    road_countries
+}
 
+impl GenerationCacheItem<IVec2> for StructureCache {
+    fn generate(key: IVec2, generation_options: &GenerationOptions) -> Self {
         Self {
-            city_location: IVec2::new(city_x, city_z) + key * COUNTRY_SIZE as i32,
+            city_location: generation_options.country_layout.capital(key),
+            instances: GenerationCache::with_capacity(STRUCTURE_REGION_CAPACITY),
         }
     }
+
+    fn load(key: IVec2, generation_options: &GenerationOptions) -> Option<Self> {
+        generation_options.disk_cache.load(key)
+    }
+
+    fn store(&self, key: IVec2, generation_options: &GenerationOptions) {
+        generation_options.disk_cache.store(key, self);
+    }
 }
 
 impl GenerationCacheItem<IVec2> for PathCache {
+    /// Countries whose roads can't be found get none.
     fn generate(key: IVec2, generation_options: &GenerationOptions) -> Self {
-        let top_country_pos = key + IVec2::X;
-        let right_country_pos = key + IVec2::Y;
+        Self::try_generate(key, generation_options).unwrap_or_else(|error| {
+            warn!("Generating country {} without roads: {}", key, error);
+            Self { paths: vec![] }
+        })
+    }
 
-        let current_structure_cache = generation_options
-            .structure_cache
-            .get_cache_entry(key, generation_options);
-        let top_structure_cache = generation_options
-            .structure_cache
-            .get_cache_entry(top_country_pos, generation_options);
-        let right_structure_cache = generation_options
-            .structure_cache
-            .get_cache_entry(right_country_pos, generation_options);
+    /// Failed roads are neither cached nor stored on disk, so the country gets retried.
+    fn try_generate(key: IVec2, generation_options: &GenerationOptions) -> Result<Self, String> {
+        Self::generate_paths(key, generation_options)
+    }
 
-        let path_finding_lod = ChunkLod::Sixtyfourth;
+    fn load(key: IVec2, generation_options: &GenerationOptions) -> Option<Self> {
+        generation_options.disk_cache.load(key)
+    }
 
-        Self {
-            paths: vec![
-                PathCache::generate_path(
-                    current_structure_cache.city_location,
-                    top_structure_cache.city_location,
-                    [key, top_country_pos],
-                    path_finding_lod,
-                    generation_options,
-                ),
-                PathCache::generate_path(
-                    current_structure_cache.city_location,
-                    right_structure_cache.city_location,
-                    [key, right_country_pos],
-                    path_finding_lod,
-                    generation_options,
-                ),
-            ],
-        }
+    fn store(&self, key: IVec2, generation_options: &GenerationOptions) {
+        generation_options.disk_cache.store(key, self);
     }
 }
 
 impl PathCache {
+    /// Every road that can pass through the area between `min` and `max`, independent of the
+    /// countries a caller has loaded. Roads that fail to generate are returned as errors.
+    pub fn in_area(
+        min: IVec2,
+        max: IVec2,
+        generation_options: &GenerationOptions,
+    ) -> Vec<Result<Arc<PathCache>, String>> {
+        road_countries_in_area(&generation_options.country_layout, min, max)
+            .into_iter()
+            .map(|country| {
+                generation_options
+                    .path_cache
+                    .try_get_cache_entry(country, generation_options)
+            })
+            .collect()
+    }
+
+    /// Roads from the capital of `key` to the capitals of all neighbours higher than `key`.
+    fn generate_paths(key: IVec2, generation_options: &GenerationOptions) -> Result<Self, String> {
+        let current_structure_cache = generation_options
+            .structure_cache
+            .get_cache_entry(key, generation_options);
+
+        Ok(Self {
+            paths: generation_options
+                .country_layout
+                .neighbours(key)
+                .into_iter()
+                .filter(|neighbour| is_lower_country(key, *neighbour))
+                .map(|neighbour| {
+                    let neighbour_structure_cache = generation_options
+                        .structure_cache
+                        .get_cache_entry(neighbour, generation_options);
+
+                    PathCache::generate_path(
+                        current_structure_cache.city_location,
+                        neighbour_structure_cache.city_location,
+                        [key, neighbour],
+                        PATH_FINDING_LOD,
+                        generation_options,
+                    )
+                })
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
     fn generate_path(
         mut start_pos: IVec2,
         mut end_pos: IVec2,
         country_positions: [IVec2; 2],
         path_finding_lod: ChunkLod,
         generation_options: &GenerationOptions,
-    ) -> Path {
+    ) -> Result<Path, String> {
         start_pos /= path_finding_lod.multiplier_i32();
         end_pos /= path_finding_lod.multiplier_i32();
 
@@ -325,16 +520,10 @@ impl PathCache {
         };
 
         let is_outside_of_countries = |pos: IVec2| -> bool {
-            let pos = pos * path_finding_lod.multiplier_i32();
-            let is_outside_first_country = pos.x < country_positions[0].x * COUNTRY_SIZE as i32
-                || pos.x >= (country_positions[0] + IVec2::X).x * COUNTRY_SIZE as i32
-                || pos.y < country_positions[0].y * COUNTRY_SIZE as i32
-                || pos.y >= (country_positions[0] + IVec2::Y).y * COUNTRY_SIZE as i32;
-            let is_outside_second_country = pos.x < country_positions[1].x * COUNTRY_SIZE as i32
-                || pos.x >= (country_positions[1] + IVec2::X).x * COUNTRY_SIZE as i32
-                || pos.y < country_positions[1].y * COUNTRY_SIZE as i32
-                || pos.y >= (country_positions[1] + IVec2::Y).y * COUNTRY_SIZE as i32;
-            is_outside_first_country && is_outside_second_country
+            let country = generation_options
+                .country_layout
+                .country_at(pos * path_finding_lod.multiplier_i32());
+            !country_positions.contains(&country)
         };
 
         let mut queue = BinaryHeap::new();
@@ -425,10 +614,13 @@ impl PathCache {
             }
 
             while current != start_pos {
-                let prev = previous
-                    .get(&current)
-                    .copied()
-                    .expect("We reached the target, but are unable to reconsistute the path");
+                let Some(prev) = previous.get(&current).copied() else {
+                    warn!("We reached the target, but are unable to reconstitute the path");
+                    return Err(format!(
+                        "Road between {} and {} couldn't be reconstructed",
+                        country_positions[0], country_positions[1]
+                    ));
+                };
 
                 let dir = prev - current;
 
@@ -476,20 +668,186 @@ impl PathCache {
                 }
             }
 
-            Path {
+            let mut path = Path {
                 lines: path,
                 box_pos_start: IVec2::new(min_x, min_y),
                 box_pos_end: IVec2::new(max_x, max_y),
-            }
+                props: vec![],
+            };
+
+            let full_terrain_noise = get_terrain_noise(ChunkLod::Full, generation_options);
+            generation_options
+                .road_grading
+                .grade_path(&mut path, |pos| {
+                    full_terrain_noise.get(pos.as_dvec2().to_array()) as f32
+                });
+
+            // The path was traced back from the end, so its first point is at the end capital.
+            path.props = decorate_path(
+                &path,
+                [country_positions[1], country_positions[0]],
+                generation_options.road_grading.half_width,
+            );
+
+            Ok(path)
         } else {
-            info!("NO PATH COULD BE CREATED!");
-            Path {
-                lines: vec![],
-                box_pos_start: Default::default(),
-                box_pos_end: Default::default(),
+            warn!(
+                "No road could be found between the capitals of {} and {}",
+                country_positions[0], country_positions[1]
+            );
+            Err(format!(
+                "No road between {} and {}",
+                country_positions[0], country_positions[1]
+            ))
+        }
+    }
+}
+
+impl DiskCacheItem for StructureCache {
+    const NAME: &'static str = "structures";
+
+    fn write(&self, writer: &mut ByteWriter) {
+        writer.write_ivec2(self.city_location);
+    }
+
+    fn read(reader: &mut ByteReader) -> Option<Self> {
+        Some(Self {
+            city_location: reader.read_ivec2()?,
+            instances: GenerationCache::with_capacity(STRUCTURE_REGION_CAPACITY),
+        })
+    }
+}
+
+impl DiskCacheItem for PathCache {
+    const NAME: &'static str = "paths";
+
+    fn write(&self, writer: &mut ByteWriter) {
+        writer.write_len(self.paths.len());
+        for path in &self.paths {
+            writer.write_ivec2(path.box_pos_start);
+            writer.write_ivec2(path.box_pos_end);
+            writer.write_len(path.props.len());
+            for prop in &path.props {
+                writer.write_u32(prop.kind.index());
+                writer.write_ivec2(prop.position);
+                writer.write_f32(prop.height);
+                writer.write_ivec2(prop.target);
+            }
+            writer.write_len(path.lines.len());
+            for line in &path.lines {
+                writer.write_ivec2(line.start);
+                writer.write_ivec2(line.end);
+                writer.write_vec2(line.spline_one);
+                writer.write_vec2(line.spline_two);
+                writer.write_ivec2(line.box_pos_start);
+                writer.write_ivec2(line.box_pos_end);
+                writer.write_f32(line.estimated_length);
+                writer.write_len(line.sample_points.len());
+                for sample_point in &line.sample_points {
+                    writer.write_ivec2(*sample_point);
+                }
+                writer.write_len(line.heights.len());
+                for height in &line.heights {
+                    writer.write_f32(*height);
+                }
             }
         }
     }
+
+    fn read(reader: &mut ByteReader) -> Option<Self> {
+        let path_count = reader.read_len()?;
+        let mut paths = Vec::with_capacity(path_count);
+        for _ in 0..path_count {
+            let box_pos_start = reader.read_ivec2()?;
+            let box_pos_end = reader.read_ivec2()?;
+            let prop_count = reader.read_len()?;
+            let props = (0..prop_count)
+                .map(|_| {
+                    Some(RoadProp {
+                        kind: RoadPropKind::from_index(reader.read_u32()?)?,
+                        position: reader.read_ivec2()?,
+                        height: reader.read_f32()?,
+                        target: reader.read_ivec2()?,
+                    })
+                })
+                .collect::<Option<Vec<_>>>()?;
+            let line_count = reader.read_len()?;
+            let mut lines = Vec::with_capacity(line_count);
+            for _ in 0..line_count {
+                let start = reader.read_ivec2()?;
+                let end = reader.read_ivec2()?;
+                let spline_one = reader.read_vec2()?;
+                let spline_two = reader.read_vec2()?;
+                let box_pos_start = reader.read_ivec2()?;
+                let box_pos_end = reader.read_ivec2()?;
+                let estimated_length = reader.read_f32()?;
+                let sample_point_count = reader.read_len()?;
+                let sample_points = (0..sample_point_count)
+                    .map(|_| reader.read_ivec2())
+                    .collect::<Option<Vec<_>>>()?;
+                let height_count = reader.read_len()?;
+                let heights = (0..height_count)
+                    .map(|_| reader.read_f32())
+                    .collect::<Option<Vec<_>>>()?;
+
+                lines.push(PathLine {
+                    start,
+                    end,
+                    spline_one,
+                    spline_two,
+                    box_pos_start,
+                    box_pos_end,
+                    estimated_length,
+                    sample_points,
+                    heights,
+                });
+            }
+
+            paths.push(Path {
+                lines,
+                box_pos_start,
+                box_pos_end,
+                props,
+            });
+        }
+
+        Some(Self { paths })
+    }
 }
 
 pub const COUNTRY_SIZE: usize = 2usize.pow(16);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn road_countries_cover_every_road_through_the_area() {
+        let layout = CountryLayout::new(5);
+        let size = COUNTRY_SIZE as i32;
+
+        for (min, max) in [
+            (IVec2::new(-300, -300), IVec2::new(300, 300)),
+            (
+                IVec2::new(size / 3, -size / 2),
+                IVec2::new(size / 3 + 1000, -size / 2 + 1000),
+            ),
+        ] {
+            let road_countries = road_countries_in_area(&layout, min, max);
+
+            for x in (min.x..=max.x).step_by(100) {
+                for y in (min.y..=max.y).step_by(100) {
+                    let country = layout.country_at(IVec2::new(x, y));
+                    for neighbour in layout.neighbours(country) {
+                        let road_country = if is_lower_country(neighbour, country) {
+                            neighbour
+                        } else {
+                            country
+                        };
+                        assert!(road_countries.contains(&road_country), "{road_country}");
+                    }
+                }
+            }
+        }
+    }
+}
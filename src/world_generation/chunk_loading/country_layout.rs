@@ -0,0 +1,422 @@
+use crate::utils::hash_position;
+use crate::world_generation::chunk_generation::BlockType;
+use crate::world_generation::chunk_loading::country_cache::COUNTRY_SIZE;
+use crate::world_generation::generation_options::GenerationCache;
+use crate::world_generation::naming::{NameGenerator, NameKind};
+use bevy::math::{DVec2, IVec2};
+use noise::{NoiseFn, Perlin};
+use std::collections::HashSet;
+
+const WARP_FREQUENCY: f64 = 1. / 16384.;
+/// Capitals are at least half a country apart, as long as the warp stays below a quarter of
+/// [`COUNTRY_SIZE`] every capital lies inside its own country.
+const WARP_STRENGTH: f64 = COUNTRY_SIZE as f64 / 16.;
+/// Countries this many grid cells apart or closer never share a country or capital name.
+const UNIQUE_NAME_RADIUS: i32 = 3;
+/// Names tried per country before giving up on uniqueness, which practically never happens.
+const NAME_ATTEMPTS: u64 = 3;
+/// Country and capital names kept, the least recently used ones get dropped.
+const UNIQUE_NAME_CAPACITY: usize = 1024;
+
+/// Countries are the Voronoi cells around one capital per `COUNTRY_SIZE` grid cell, with their
+/// borders warped by noise. Countries are identified by the grid cell of their capital.
+pub struct CountryLayout {
+    seed: u64,
+    warp_x: Perlin,
+    warp_z: Perlin,
+    names: NameGenerator,
+    /// Country and capital names, checking them against the neighbourhood is too slow to repeat
+    /// every frame.
+    unique_names: GenerationCache<(NameKind, IVec2), String>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Culture {
+    Alpine,
+    Steppe,
+    Forest,
+    Marsh,
+}
+
+#[derive(Copy, Clone)]
+pub struct CountryPalette {
+    pub grass: BlockType,
+    pub stone: BlockType,
+    pub path: BlockType,
+}
+
+#[derive(Clone)]
+pub struct CountryMetadata {
+    pub name: String,
+    pub capital_name: String,
+    pub culture: Culture,
+    pub palette: CountryPalette,
+    /// Countries sharing a border with this one, their capitals are linked by roads.
+    pub neighbours: Vec<IVec2>,
+}
+
+impl CountryLayout {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            warp_x: Perlin::new(hash_position(seed, IVec2::ZERO, 1) as u32),
+            warp_z: Perlin::new(hash_position(seed, IVec2::ZERO, 2) as u32),
+            names: NameGenerator::new(seed),
+            unique_names: GenerationCache::with_capacity(UNIQUE_NAME_CAPACITY),
+        }
+    }
+
+    /// Capitals are kept in the central half of their grid cell, so the closest capital of any
+    /// position is always one of the surrounding 3x3 cells.
+    pub fn capital(&self, country: IVec2) -> IVec2 {
+        let hash = hash_position(self.seed, country, 0);
+        let quarter = COUNTRY_SIZE as i32 / 4;
+        let offset = IVec2::new(
+            (hash % (2 * quarter) as u64) as i32,
+            ((hash >> 32) % (2 * quarter) as u64) as i32,
+        );
+
+        country * COUNTRY_SIZE as i32 + quarter + offset
+    }
+
+    pub fn country_at(&self, position: IVec2) -> IVec2 {
+        let warped = self.warp(position);
+        let cell = (warped / COUNTRY_SIZE as f64).floor().as_ivec2();
+
+        let mut closest = cell;
+        let mut closest_distance = f64::INFINITY;
+
+        for x in -1..=1 {
+            for y in -1..=1 {
+                let country = cell + IVec2::new(x, y);
+                let distance = self.capital(country).as_dvec2().distance_squared(warped);
+                if distance < closest_distance {
+                    closest = country;
+                    closest_distance = distance;
+                }
+            }
+        }
+
+        closest
+    }
+
+    /// Countries overlapping the area, inclusive on both ends. The country at the center of the
+    /// area comes first. The warp only moves borders by up to [`WARP_STRENGTH`], countries closer
+    /// to the area than that may be included too.
+    pub fn countries_in_area(&self, min: IVec2, max: IVec2) -> Vec<IVec2> {
+        let mut countries = vec![self.country_at((min + max) / 2)];
+
+        let warped_min = min.as_dvec2() - WARP_STRENGTH;
+        let warped_max = max.as_dvec2() + WARP_STRENGTH;
+        // The closest capital of every position is in one of the 3x3 cells around it.
+        let cell_min = (warped_min / COUNTRY_SIZE as f64).floor().as_ivec2() - 1;
+        let cell_max = (warped_max / COUNTRY_SIZE as f64).floor().as_ivec2() + 1;
+
+        for x in cell_min.x..=cell_max.x {
+            for y in cell_min.y..=cell_max.y {
+                let country = IVec2::new(x, y);
+                if !countries.contains(&country)
+                    && self.cell_overlaps(country, warped_min, warped_max)
+                {
+                    countries.push(country);
+                }
+            }
+        }
+
+        countries
+    }
+
+    /// Whether the unwarped Voronoi cell of `country` overlaps the box, by clipping the box with
+    /// the half planes towards the capitals around it.
+    fn cell_overlaps(&self, country: IVec2, min: DVec2, max: DVec2) -> bool {
+        let capital = self.capital(country).as_dvec2();
+        let mut polygon = vec![min, DVec2::new(max.x, min.y), max, DVec2::new(min.x, max.y)];
+
+        for x in -2..=2 {
+            for y in -2..=2 {
+                if x == 0 && y == 0 {
+                    continue;
+                }
+                let other = self.capital(country + IVec2::new(x, y)).as_dvec2();
+                // Positions closer to `capital` than to `other`.
+                let normal = other - capital;
+                let limit = (other.length_squared() - capital.length_squared()) / 2.;
+                let inside = |point: DVec2| point.dot(normal) <= limit;
+
+                let mut clipped = Vec::with_capacity(polygon.len() + 1);
+                for (index, &point) in polygon.iter().enumerate() {
+                    let next = polygon[(index + 1) % polygon.len()];
+                    if inside(point) {
+                        clipped.push(point);
+                    }
+                    if inside(point) != inside(next) {
+                        let t = (limit - point.dot(normal)) / (next - point).dot(normal);
+                        clipped.push(point + (next - point) * t);
+                    }
+                }
+
+                polygon = clipped;
+                if polygon.is_empty() {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Neighbours in the Gabriel graph of the capitals: two capitals are linked if no other
+    /// capital lies in the circle spanned by them.
+    pub fn neighbours(&self, country: IVec2) -> Vec<IVec2> {
+        let capital = self.capital(country).as_dvec2();
+        let mut candidates = Vec::with_capacity(24);
+        for x in -2..=2 {
+            for y in -2..=2 {
+                if x != 0 || y != 0 {
+                    let candidate = country + IVec2::new(x, y);
+                    candidates.push((candidate, self.capital(candidate).as_dvec2()));
+                }
+            }
+        }
+
+        candidates
+            .iter()
+            .filter(|(candidate, _)| (*candidate - country).abs().max_element() == 1)
+            .filter(|(candidate, candidate_capital)| {
+                let center = (capital + *candidate_capital) / 2.;
+                let radius_squared = capital.distance_squared(*candidate_capital) / 4.;
+
+                candidates.iter().all(|(other, other_capital)| {
+                    other == candidate || other_capital.distance_squared(center) >= radius_squared
+                })
+            })
+            .map(|(candidate, _)| *candidate)
+            .collect()
+    }
+
+    pub fn culture(&self, country: IVec2) -> Culture {
+        match hash_position(self.seed, country, 3) % 4 {
+            0 => Culture::Alpine,
+            1 => Culture::Steppe,
+            2 => Culture::Forest,
+            _ => Culture::Marsh,
+        }
+    }
+
+    /// Terrain colors of the country, shaded a little differently for every country.
+    pub fn palette(&self, country: IVec2) -> CountryPalette {
+        CountryPalette::new(self.culture(country), hash_position(self.seed, country, 4))
+    }
+
+    pub fn country_name(&self, country: IVec2) -> String {
+        self.unique_name(NameKind::Country, country)
+    }
+
+    pub fn city_name(&self, country: IVec2) -> String {
+        self.unique_name(NameKind::City, country)
+    }
+
+    fn unique_name(&self, kind: NameKind, country: IVec2) -> String {
+        let name = self.unique_names.get_or_generate((kind, country), || {
+            unique_name(country, |country, attempt| {
+                self.name_candidate(kind, country, attempt)
+            })
+        });
+
+        name.as_ref().clone()
+    }
+
+    fn name_candidate(&self, kind: NameKind, country: IVec2, attempt: u64) -> String {
+        let key = match kind {
+            NameKind::City => self.capital(country),
+            _ => country,
+        };
+        self.names
+            .alternative_name(kind, self.culture(country), key, attempt)
+    }
+
+    /// Rivers and peaks take the culture of the country they are in and are keyed by their
+    /// voxel position, usually their source or summit.
+    pub fn landmark_name(&self, kind: NameKind, position: IVec2) -> String {
+        self.names
+            .name(kind, self.culture(self.country_at(position)), position)
+    }
+
+    pub fn metadata(&self, country: IVec2) -> CountryMetadata {
+        let culture = self.culture(country);
+
+        CountryMetadata {
+            name: self.country_name(country),
+            capital_name: self.city_name(country),
+            culture,
+            palette: self.palette(country),
+            neighbours: self.neighbours(country),
+        }
+    }
+
+    fn warp(&self, position: IVec2) -> DVec2 {
+        let position = position.as_dvec2();
+        let sample = (position * WARP_FREQUENCY).to_array();
+
+        position + DVec2::new(self.warp_x.get(sample), self.warp_z.get(sample)) * WARP_STRENGTH
+    }
+}
+
+/// First candidate of `country` that none of the countries around it could get. They skip the
+/// candidates of `country` the same way, so whichever names they end up with, all are different.
+fn unique_name(country: IVec2, candidate: impl Fn(IVec2, u64) -> String) -> String {
+    let taken = (-UNIQUE_NAME_RADIUS..=UNIQUE_NAME_RADIUS)
+        .flat_map(|x| {
+            (-UNIQUE_NAME_RADIUS..=UNIQUE_NAME_RADIUS).map(move |y| country + IVec2::new(x, y))
+        })
+        .filter(|other| *other != country)
+        .flat_map(|other| (0..NAME_ATTEMPTS).map(move |attempt| (other, attempt)))
+        .map(|(other, attempt)| candidate(other, attempt))
+        .collect::<HashSet<_>>();
+
+    (0..NAME_ATTEMPTS)
+        .map(|attempt| candidate(country, attempt))
+        .find(|name| !taken.contains(name))
+        .unwrap_or_else(|| candidate(country, NAME_ATTEMPTS))
+}
+
+impl CountryPalette {
+    /// The country's colors for grass, stone and path blocks, other blocks are kept.
+    pub fn apply(&self, block: BlockType) -> BlockType {
+        match block {
+            BlockType::Grass => self.grass,
+            BlockType::Stone => self.stone,
+            BlockType::Path => self.path,
+            _ => block,
+        }
+    }
+
+    fn new(culture: Culture, hash: u64) -> Self {
+        let (grass, stone, path) = match culture {
+            Culture::Alpine => ([70, 170, 95], [160, 165, 170], [120, 90, 70]),
+            Culture::Steppe => ([165, 180, 80], [175, 160, 130], [150, 115, 75]),
+            Culture::Forest => ([40, 150, 60], [130, 140, 125], [95, 65, 45]),
+            Culture::Marsh => ([90, 140, 85], [120, 125, 115], [85, 75, 60]),
+        };
+
+        let shade = |color: [u8; 3], shift: u64| {
+            let offset = ((hash >> shift) % 21) as i16 - 10;
+            BlockType::Custom(
+                (color[0] as i16 + offset).clamp(0, 255) as u8,
+                (color[1] as i16 + offset).clamp(0, 255) as u8,
+                (color[2] as i16 + offset).clamp(0, 255) as u8,
+            )
+        };
+
+        Self {
+            grass: shade(grass, 0),
+            stone: shade(stone, 8),
+            path: shade(path, 16),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(min: IVec2, max: IVec2, step: i32) -> impl Iterator<Item = IVec2> {
+        (min.x..=max.x).step_by(step as usize).flat_map(move |x| {
+            (min.y..=max.y)
+                .step_by(step as usize)
+                .map(move |y| IVec2::new(x, y))
+        })
+    }
+
+    #[test]
+    fn country_at_finds_the_closest_capital() {
+        let layout = CountryLayout::new(5);
+        let size = COUNTRY_SIZE as i32;
+
+        for position in grid(IVec2::splat(-2 * size), IVec2::splat(2 * size), size / 7) {
+            let warped = layout.warp(position);
+            let closest = grid(IVec2::splat(-5), IVec2::splat(5), 1)
+                .min_by(|a, b| {
+                    let a = layout.capital(*a).as_dvec2().distance_squared(warped);
+                    let b = layout.capital(*b).as_dvec2().distance_squared(warped);
+                    a.total_cmp(&b)
+                })
+                .unwrap();
+            assert_eq!(layout.country_at(position), closest, "{position}");
+        }
+
+        for country in grid(IVec2::splat(-3), IVec2::splat(3), 1) {
+            assert_eq!(layout.country_at(layout.capital(country)), country);
+        }
+    }
+
+    #[test]
+    fn neighbours_are_adjacent_and_mutual() {
+        let layout = CountryLayout::new(5);
+
+        for country in grid(IVec2::splat(-4), IVec2::splat(4), 1) {
+            let neighbours = layout.neighbours(country);
+            assert!(neighbours.len() >= 2, "{country}: {neighbours:?}");
+
+            for neighbour in neighbours {
+                assert_eq!((neighbour - country).abs().max_element(), 1);
+                assert!(layout.neighbours(neighbour).contains(&country));
+            }
+        }
+    }
+
+    #[test]
+    fn countries_in_area_finds_every_country() {
+        let layout = CountryLayout::new(5);
+        let size = COUNTRY_SIZE as i32;
+
+        for (min, max) in [
+            (IVec2::new(-size, -size), IVec2::new(size, size)),
+            (IVec2::new(100, -3000), IVec2::new(2 * size, 200)),
+            (
+                IVec2::new(-size / 2, size / 3),
+                IVec2::new(-size / 2 + 500, size / 3 + 500),
+            ),
+        ] {
+            let countries = layout.countries_in_area(min, max);
+            assert_eq!(countries[0], layout.country_at((min + max) / 2));
+
+            for position in grid(min, max, 256) {
+                let country = layout.country_at(position);
+                assert!(countries.contains(&country), "{country} at {position}");
+            }
+        }
+
+        let capital = layout.capital(IVec2::new(2, -1));
+        assert_eq!(
+            layout.countries_in_area(capital - 10, capital + 10),
+            vec![IVec2::new(2, -1)]
+        );
+    }
+
+    #[test]
+    fn colliding_names_are_replaced() {
+        let twins = [IVec2::new(0, 0), IVec2::new(2, -1)];
+        let candidate = |country: IVec2, attempt: u64| {
+            if attempt == 0 && twins.contains(&country) {
+                "Twin".to_string()
+            } else {
+                format!("{}/{}/{}", country.x, country.y, attempt)
+            }
+        };
+
+        for twin in twins {
+            let name = unique_name(twin, candidate);
+            assert_ne!(name, "Twin");
+
+            for other in grid(twin - UNIQUE_NAME_RADIUS, twin + UNIQUE_NAME_RADIUS, 1) {
+                if other != twin {
+                    assert_ne!(unique_name(other, candidate), name, "{other}");
+                }
+            }
+        }
+
+        // Countries without a collision keep their first name.
+        assert_eq!(unique_name(IVec2::new(1, 0), candidate), "1/0/0");
+    }
+}
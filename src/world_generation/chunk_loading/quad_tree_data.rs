@@ -1,5 +1,5 @@
 use crate::world_generation::chunk_loading::quad_tree_data::QuadTreeNode::Node;
-use bevy::prelude::{Commands, Entity};
+use bevy::prelude::{Commands, DespawnRecursiveExt, Entity};
 use std::sync::{Arc, Mutex};
 
 #[derive(Debug)]
@@ -49,7 +49,15 @@ impl<T> QuadTreeNode<T> {
         }
     }
 
-    pub fn add_to_parent(&mut self, depth: i32, position: [i32; 2], commands: &mut Commands) {
+    pub fn add_to_parent<F>(
+        &mut self,
+        depth: i32,
+        position: [i32; 2],
+        commands: &mut Commands,
+        on_completed: &mut F,
+    ) where
+        F: FnMut(i32, [i32; 2]),
+    {
         let mut further = false;
         if let Some(Node(_, _, _, _, child_progress, entities)) =
             self.get_parent_node(depth, position)
@@ -59,11 +67,13 @@ impl<T> QuadTreeNode<T> {
 
             if *child_progress_lock == 4 {
                 for entity in entities {
-                    if let Some(mut entity) = commands.get_entity(entity.clone()) {
-                        entity.despawn();
+                    if let Some(entity) = commands.get_entity(entity.clone()) {
+                        entity.despawn_recursive();
                     }
                 }
 
+                on_completed(depth - 1, [position[0] / 2, position[1] / 2]);
+
                 if depth != 1 {
                     further = true;
                 }
@@ -71,7 +81,12 @@ impl<T> QuadTreeNode<T> {
         }
 
         if further {
-            self.add_to_parent(depth - 1, [position[0] / 2, position[1] / 2], commands);
+            self.add_to_parent(
+                depth - 1,
+                [position[0] / 2, position[1] / 2],
+                commands,
+                on_completed,
+            );
         }
     }
 
@@ -0,0 +1,356 @@
+use crate::world_generation::chunk_generation::structure_model::StructureModel;
+use crate::world_generation::chunk_loading::country_cache::Path;
+use crate::world_generation::navigation::RoadGraph;
+use bevy::math::{IVec2, Vec2};
+use std::sync::Arc;
+
+/// Distance of the junction signposts from the junction, along the road they point down.
+const JUNCTION_DISTANCE: f32 = 24.;
+/// Distance of the entrance signposts from the capital at either end of a road.
+const ENTRANCE_DISTANCE: f32 = 160.;
+const MILESTONE_SPACING: f32 = 2000.;
+/// Roads are lit up to this distance from the capitals.
+const LANTERN_RANGE: f32 = 320.;
+const LANTERN_SPACING: f32 = 48.;
+/// How far inside the road edge props are placed.
+const ROADSIDE_INSET: f32 = 1.5;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RoadPropKind {
+    /// Points at the capital of `target`.
+    Signpost,
+    /// Counts the distance to the capital of `target`.
+    Milestone,
+    Lantern,
+}
+
+impl RoadPropKind {
+    pub fn from_index(index: u32) -> Option<Self> {
+        match index {
+            0 => Some(Self::Signpost),
+            1 => Some(Self::Milestone),
+            2 => Some(Self::Lantern),
+            _ => None,
+        }
+    }
+
+    pub fn index(self) -> u32 {
+        self as u32
+    }
+}
+
+/// Prop standing next to a road, placed once per [`Path`] when its cache gets generated, or once
+/// per junction by [`decorate_junctions`].
+#[derive(Copy, Clone, Debug)]
+pub struct RoadProp {
+    pub kind: RoadPropKind,
+    /// Center of the bottom of the model.
+    pub position: IVec2,
+    /// Graded road height below the prop, in full resolution voxels.
+    pub height: f32,
+    /// Country whose capital the prop refers to.
+    pub target: IVec2,
+}
+
+#[derive(Clone)]
+pub struct RoadPropModel {
+    pub model: Arc<StructureModel>,
+    pub model_size: [i32; 3],
+}
+
+pub struct RoadPropModels {
+    pub signpost: RoadPropModel,
+    pub milestone: RoadPropModel,
+    pub lantern: RoadPropModel,
+}
+
+impl RoadPropModels {
+    pub fn get(&self, kind: RoadPropKind) -> &RoadPropModel {
+        match kind {
+            RoadPropKind::Signpost => &self.signpost,
+            RoadPropKind::Milestone => &self.milestone,
+            RoadPropKind::Lantern => &self.lantern,
+        }
+    }
+}
+
+/// Places the props along a graded `path` running from the capital of `countries[0]` to the one of
+/// `countries[1]`. Only depends on the path, so every chunk and LOD gets the same props.
+pub fn decorate_path(path: &Path, countries: [IVec2; 2], half_width: f32) -> Vec<RoadProp> {
+    let polyline = Polyline::new(path);
+    let length = polyline.length();
+    if length < ENTRANCE_DISTANCE * 2. {
+        return vec![];
+    }
+
+    let side_offset = half_width - ROADSIDE_INSET;
+    let mut props = vec![];
+    let mut place = |kind: RoadPropKind, distance: f32, side: f32, target: IVec2| {
+        let (position, direction, height) = polyline.at(distance);
+        let right = Vec2::new(-direction.y, direction.x);
+        props.push(RoadProp {
+            kind,
+            position: (position + right * side * side_offset).round().as_ivec2(),
+            height,
+            target,
+        });
+    };
+
+    // Both ends get the same props, mirrored. Traffic leaving a capital is on the right side.
+    for (end, from_end) in [(0, false), (1, true)] {
+        let distance_at = |distance: f32| {
+            if from_end {
+                length - distance
+            } else {
+                distance
+            }
+        };
+        let side = if from_end { -1. } else { 1. };
+
+        place(
+            RoadPropKind::Signpost,
+            distance_at(ENTRANCE_DISTANCE),
+            -side,
+            countries[end],
+        );
+
+        let mut distance = LANTERN_SPACING;
+        let mut lantern_side = side;
+        while distance <= LANTERN_RANGE.min(length / 2.) {
+            if (distance - ENTRANCE_DISTANCE).abs() > LANTERN_SPACING / 2. {
+                place(
+                    RoadPropKind::Lantern,
+                    distance_at(distance),
+                    lantern_side,
+                    countries[end],
+                );
+            }
+            lantern_side = -lantern_side;
+            distance += LANTERN_SPACING;
+        }
+    }
+
+    let mut distance = MILESTONE_SPACING;
+    while distance < length - LANTERN_RANGE {
+        place(RoadPropKind::Milestone, distance, 1., countries[1]);
+        distance += MILESTONE_SPACING;
+    }
+
+    props
+}
+
+/// Signposts next to every road leaving the junctions of `graph` that lie in `country`, pointing
+/// at the capital of the country the road leads to. Only depends on the roads of the country, so
+/// every chunk gets the same signposts.
+pub fn decorate_junctions(
+    graph: &RoadGraph,
+    country: IVec2,
+    country_at: impl Fn(IVec2) -> IVec2,
+    half_width: f32,
+) -> Vec<RoadProp> {
+    graph
+        .junction_exits(JUNCTION_DISTANCE)
+        .into_iter()
+        .filter(|exit| country_at(exit.junction) == country)
+        .map(|exit| {
+            // Traffic leaving the junction is on the right side.
+            let right = Vec2::new(-exit.direction.y, exit.direction.x);
+            RoadProp {
+                kind: RoadPropKind::Signpost,
+                position: (exit.position + right * (half_width - ROADSIDE_INSET))
+                    .round()
+                    .as_ivec2(),
+                height: exit.height,
+                target: country_at(exit.leads_to),
+            }
+        })
+        .collect()
+}
+
+/// The sample points of a whole path, measured along its length.
+struct Polyline {
+    points: Vec<Vec2>,
+    heights: Vec<f32>,
+    distances: Vec<f32>,
+}
+
+impl Polyline {
+    fn new(path: &Path) -> Self {
+        let points = path
+            .sample_points()
+            .map(|point| point.as_vec2())
+            .collect::<Vec<_>>();
+        let heights = path.sample_heights().collect::<Vec<_>>();
+
+        let mut distances = Vec::with_capacity(points.len());
+        let mut distance = 0.;
+        for (index, point) in points.iter().enumerate() {
+            if index > 0 {
+                distance += points[index - 1].distance(*point);
+            }
+            distances.push(distance);
+        }
+
+        Self {
+            points,
+            heights,
+            distances,
+        }
+    }
+
+    fn length(&self) -> f32 {
+        self.distances.last().copied().unwrap_or_default()
+    }
+
+    /// Position, direction and height `distance` voxels along the path.
+    fn at(&self, distance: f32) -> (Vec2, Vec2, f32) {
+        let index = self
+            .distances
+            .partition_point(|&point_distance| point_distance < distance)
+            .clamp(1, self.points.len() - 1);
+
+        let segment_length = self.distances[index] - self.distances[index - 1];
+        let t = if segment_length > 0. {
+            ((distance - self.distances[index - 1]) / segment_length).clamp(0., 1.)
+        } else {
+            0.
+        };
+
+        let start = self.points[index - 1];
+        let end = self.points[index];
+        let height_start = self.heights.get(index - 1).copied().unwrap_or_default();
+        let height_end = self.heights.get(index).copied().unwrap_or_default();
+
+        (
+            start.lerp(end, t),
+            (end - start).normalize_or_zero(),
+            height_start + (height_end - height_start) * t,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_generation::chunk_loading::country_cache::PathLine;
+
+    fn path(sample_points: Vec<IVec2>) -> Path {
+        let box_pos_start = sample_points.iter().copied().reduce(IVec2::min).unwrap();
+        let box_pos_end = sample_points.iter().copied().reduce(IVec2::max).unwrap();
+
+        Path {
+            lines: vec![PathLine {
+                start: sample_points[0],
+                end: *sample_points.last().unwrap(),
+                spline_one: Vec2::ZERO,
+                spline_two: Vec2::ZERO,
+                box_pos_start,
+                box_pos_end,
+                estimated_length: 0.,
+                heights: vec![0.; sample_points.len()],
+                sample_points,
+            }],
+            box_pos_start,
+            box_pos_end,
+            props: vec![],
+        }
+    }
+
+    fn straight_path(end: IVec2) -> Path {
+        let steps = (end.as_vec2().length() / 8.).ceil() as i32;
+        path(
+            (0..=steps)
+                .map(|step| {
+                    (end.as_vec2() * step as f32 / steps as f32)
+                        .round()
+                        .as_ivec2()
+                })
+                .collect(),
+        )
+    }
+
+    fn positions(props: &[RoadProp], kind: RoadPropKind) -> Vec<IVec2> {
+        props
+            .iter()
+            .filter(|prop| prop.kind == kind)
+            .map(|prop| prop.position)
+            .collect()
+    }
+
+    #[test]
+    fn path_props_are_spaced_along_the_road_side() {
+        let countries = [IVec2::new(0, 0), IVec2::new(1, 0)];
+        let props = decorate_path(&straight_path(IVec2::new(5000, 0)), countries, 4.5);
+
+        // The road runs along x, so the right side of traffic leaving the start is +y.
+        assert_eq!(
+            positions(&props, RoadPropKind::Signpost),
+            vec![IVec2::new(160, -3), IVec2::new(4840, 3)]
+        );
+        let signpost_targets = props
+            .iter()
+            .filter(|prop| prop.kind == RoadPropKind::Signpost)
+            .map(|prop| prop.target)
+            .collect::<Vec<_>>();
+        assert_eq!(signpost_targets, countries.to_vec());
+
+        assert_eq!(
+            positions(&props, RoadPropKind::Milestone),
+            vec![IVec2::new(2000, 3), IVec2::new(4000, 3)]
+        );
+
+        // The lantern next to the entrance signpost is left out.
+        let mut lanterns = positions(&props, RoadPropKind::Lantern);
+        lanterns.sort_by_key(|position| position.x);
+        assert_eq!(
+            lanterns,
+            vec![
+                IVec2::new(48, 3),
+                IVec2::new(96, -3),
+                IVec2::new(192, -3),
+                IVec2::new(240, 3),
+                IVec2::new(288, -3),
+                IVec2::new(4712, 3),
+                IVec2::new(4760, -3),
+                IVec2::new(4808, 3),
+                IVec2::new(4904, 3),
+                IVec2::new(4952, -3),
+            ]
+        );
+    }
+
+    #[test]
+    fn short_paths_have_no_props() {
+        let props = decorate_path(
+            &straight_path(IVec2::new(300, 0)),
+            [IVec2::new(0, 0), IVec2::new(1, 0)],
+            4.5,
+        );
+        assert!(props.is_empty());
+    }
+
+    #[test]
+    fn junction_signposts_point_down_every_exit() {
+        let paths = [
+            straight_path(IVec2::new(800, 0)),
+            straight_path(IVec2::new(0, 800)),
+            straight_path(IVec2::new(-800, -800)),
+        ];
+        let graph = RoadGraph::from_paths(&paths);
+        let country_at = |position: IVec2| IVec2::new(position.x.signum(), position.y.signum());
+
+        let mut props = decorate_junctions(&graph, IVec2::ZERO, country_at, 4.5);
+        props.sort_by_key(|prop| (prop.target.x, prop.target.y));
+
+        assert_eq!(props.len(), 3);
+        assert!(props.iter().all(|prop| prop.kind == RoadPropKind::Signpost));
+        assert_eq!(
+            props.iter().map(|prop| prop.target).collect::<Vec<_>>(),
+            vec![IVec2::new(-1, -1), IVec2::new(0, 1), IVec2::new(1, 0)]
+        );
+        // 24 voxels down the road and 3 to the right of it.
+        assert_eq!(props[1].position, IVec2::new(-3, 24));
+        assert_eq!(props[2].position, IVec2::new(24, 3));
+        assert_eq!(props[0].position, IVec2::new(-15, -19));
+    }
+}
@@ -0,0 +1,300 @@
+use crate::world_generation::chunk_loading::country_cache::Path;
+use bevy::math::IVec2;
+
+/// How roads get fitted into the terrain. Distances and heights are in full resolution voxels,
+/// slopes are the rise per voxel.
+#[derive(Copy, Clone, Debug)]
+pub struct RoadGrading {
+    /// Steepest rise a road is allowed to have along its length.
+    pub max_grade: f32,
+    /// Terrain heights along a road are averaged over this distance in both directions.
+    pub smoothing_distance: f32,
+    /// Distance from the center of a road to its edge.
+    pub half_width: f32,
+    /// Slope of the fill below roads running above the terrain.
+    pub embankment_slope: f32,
+    /// Slope of the cut above roads running below the terrain.
+    pub cut_slope: f32,
+    /// Cuts deeper than this are held back by a retaining wall instead of a slope.
+    pub retaining_wall_height: f32,
+    /// Thickness of retaining walls.
+    pub retaining_wall_width: f32,
+    /// Distance from the road edge after which the terrain is left untouched.
+    pub influence_distance: f32,
+}
+
+impl Default for RoadGrading {
+    fn default() -> Self {
+        Self {
+            max_grade: 0.15,
+            smoothing_distance: 60.,
+            half_width: 8.75,
+            embankment_slope: 0.8,
+            cut_slope: 1.2,
+            retaining_wall_height: 6.,
+            retaining_wall_width: 2.,
+            influence_distance: 24.,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RoadSurface {
+    Road,
+    RetainingWall,
+    Terrain,
+}
+
+impl RoadGrading {
+    /// Values that change the cached paths, mixed into the disk cache fingerprint. The grade and
+    /// smoothing shape the stored height profiles, the half width places the stored road props.
+    /// The other values only change how the terrain is blended around the roads.
+    pub fn parameters(&self) -> [f32; 3] {
+        [self.max_grade, self.smoothing_distance, self.half_width]
+    }
+
+    /// How far from the center line a road changes the terrain.
+    pub fn reach(&self) -> f32 {
+        self.half_width + self.influence_distance
+    }
+
+    /// Fills in the height profile of every line of `path`. The terrain heights at the sample
+    /// points get smoothed and then limited to `max_grade` in both directions, lines share the
+    /// height of the point they meet at.
+    pub fn grade_path(&self, path: &mut Path, terrain_height: impl Fn(IVec2) -> f32) {
+        let points = path.sample_points().collect::<Vec<_>>();
+        if points.is_empty() {
+            return;
+        }
+
+        let mut distances = Vec::with_capacity(points.len());
+        let mut distance = 0.;
+        for (index, point) in points.iter().enumerate() {
+            if index > 0 {
+                distance += points[index - 1].as_vec2().distance(point.as_vec2());
+            }
+            distances.push(distance);
+        }
+
+        let raw_heights = points
+            .iter()
+            .map(|point| terrain_height(*point))
+            .collect::<Vec<_>>();
+
+        let mut heights = Vec::with_capacity(points.len());
+        let mut window_start = 0;
+        let mut window_end = 0;
+        for index in 0..points.len() {
+            while distances[index] - distances[window_start] > self.smoothing_distance {
+                window_start += 1;
+            }
+            while window_end + 1 < points.len()
+                && distances[window_end + 1] - distances[index] <= self.smoothing_distance
+            {
+                window_end += 1;
+            }
+
+            let window = &raw_heights[window_start..=window_end];
+            heights.push(window.iter().sum::<f32>() / window.len() as f32);
+        }
+
+        for index in 1..heights.len() {
+            let max_rise = (distances[index] - distances[index - 1]) * self.max_grade;
+            heights[index] =
+                heights[index].clamp(heights[index - 1] - max_rise, heights[index - 1] + max_rise);
+        }
+        for index in (0..heights.len() - 1).rev() {
+            let max_rise = (distances[index + 1] - distances[index]) * self.max_grade;
+            heights[index] =
+                heights[index].clamp(heights[index + 1] - max_rise, heights[index + 1] + max_rise);
+        }
+
+        let mut first_point = 0;
+        for line in &mut path.lines {
+            line.heights = heights[first_point..first_point + line.sample_points.len()].to_vec();
+            first_point += line.sample_points.len() - 1;
+        }
+    }
+
+    /// Terrain height `distance` voxels away from the center of a road at `road_height`.
+    pub fn blend(
+        &self,
+        terrain_height: f32,
+        road_height: f32,
+        distance: f32,
+    ) -> (f32, RoadSurface) {
+        if distance <= self.half_width {
+            return (road_height, RoadSurface::Road);
+        }
+
+        let edge_distance = distance - self.half_width;
+        if edge_distance >= self.influence_distance {
+            return (terrain_height, RoadSurface::Terrain);
+        }
+
+        let cut_depth = terrain_height - road_height;
+        if cut_depth > self.retaining_wall_height {
+            let surface = if edge_distance <= self.retaining_wall_width {
+                RoadSurface::RetainingWall
+            } else {
+                RoadSurface::Terrain
+            };
+            return (terrain_height, surface);
+        }
+
+        let graded_height = if cut_depth > 0. {
+            terrain_height.min(road_height + edge_distance * self.cut_slope)
+        } else {
+            terrain_height.max(road_height - edge_distance * self.embankment_slope)
+        };
+
+        // Fades out over the last quarter, so the fill doesn't end in a cliff.
+        let fade_start = self.influence_distance * 0.75;
+        let fade = ((edge_distance - fade_start) / (self.influence_distance - fade_start)).max(0.);
+
+        (
+            graded_height + (terrain_height - graded_height) * fade,
+            RoadSurface::Terrain,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_generation::chunk_loading::country_cache::PathLine;
+    use bevy::math::Vec2;
+
+    fn line(sample_points: Vec<IVec2>) -> PathLine {
+        PathLine {
+            start: sample_points[0],
+            end: *sample_points.last().unwrap(),
+            spline_one: Vec2::ZERO,
+            spline_two: Vec2::ZERO,
+            box_pos_start: sample_points.iter().copied().reduce(IVec2::min).unwrap(),
+            box_pos_end: sample_points.iter().copied().reduce(IVec2::max).unwrap(),
+            estimated_length: 0.,
+            heights: vec![],
+            sample_points,
+        }
+    }
+
+    /// Path along the x axis in two lines of `steps` points each, 8 voxels apart.
+    fn straight_path(steps: i32) -> Path {
+        let points = |range: std::ops::RangeInclusive<i32>| {
+            range.map(|step| IVec2::new(step * 8, 0)).collect()
+        };
+
+        Path {
+            lines: vec![line(points(0..=steps)), line(points(steps..=steps * 2))],
+            box_pos_start: IVec2::ZERO,
+            box_pos_end: IVec2::new(steps * 16, 0),
+            props: vec![],
+        }
+    }
+
+    #[test]
+    fn graded_paths_stay_below_the_max_grade() {
+        let grading = RoadGrading::default();
+        let mut path = straight_path(40);
+        // A cliff halfway along the road.
+        grading.grade_path(&mut path, |point| if point.x < 320 { 0. } else { 30. });
+
+        let heights = path.sample_heights().collect::<Vec<_>>();
+        assert_eq!(heights.len(), 81);
+        for pair in heights.windows(2) {
+            assert!(pair[1] >= pair[0]);
+            assert!(pair[1] - pair[0] <= 8. * grading.max_grade + 1e-4);
+        }
+        assert!(heights[0] < 1.);
+        assert!(heights[80] > 29.);
+
+        assert_eq!(path.lines[0].heights.last(), path.lines[1].heights.first());
+    }
+
+    #[test]
+    fn smoothing_removes_bumps() {
+        let grading = RoadGrading::default();
+        let mut path = straight_path(40);
+        grading.grade_path(&mut path, |point| if point.x == 320 { 6. } else { 2. });
+
+        for height in path.sample_heights() {
+            assert!((2. ..2.5).contains(&height));
+        }
+    }
+
+    #[test]
+    fn roads_are_flat_and_far_terrain_is_untouched() {
+        let grading = RoadGrading::default();
+
+        assert_eq!(grading.blend(30., 10., 0.), (10., RoadSurface::Road));
+        assert_eq!(
+            grading.blend(30., 10., grading.half_width),
+            (10., RoadSurface::Road)
+        );
+        assert_eq!(
+            grading.blend(-30., 10., grading.reach()),
+            (-30., RoadSurface::Terrain)
+        );
+    }
+
+    #[test]
+    fn embankments_and_cuts_follow_their_slopes() {
+        let grading = RoadGrading {
+            embankment_slope: 0.5,
+            cut_slope: 2.,
+            ..RoadGrading::default()
+        };
+        let distance = grading.half_width + 2.;
+
+        // Filled up below the road, cut down above it.
+        assert_eq!(grading.blend(0., 10., distance), (9., RoadSurface::Terrain));
+        assert_eq!(grading.blend(5., 0., distance), (4., RoadSurface::Terrain));
+        // Terrain that's already within the slope stays.
+        assert_eq!(
+            grading.blend(9.5, 10., distance),
+            (9.5, RoadSurface::Terrain)
+        );
+    }
+
+    #[test]
+    fn deep_cuts_get_retaining_walls() {
+        let grading = RoadGrading::default();
+        let depth = grading.retaining_wall_height + 1.;
+
+        assert_eq!(
+            grading.blend(depth, 0., grading.half_width + grading.retaining_wall_width),
+            (depth, RoadSurface::RetainingWall)
+        );
+        assert_eq!(
+            grading.blend(
+                depth,
+                0.,
+                grading.half_width + grading.retaining_wall_width + 1.
+            ),
+            (depth, RoadSurface::Terrain)
+        );
+    }
+
+    #[test]
+    fn parameters_cover_the_stored_road_data() {
+        let grading = RoadGrading::default();
+
+        for changed in [
+            RoadGrading {
+                max_grade: 0.2,
+                ..grading
+            },
+            RoadGrading {
+                smoothing_distance: 30.,
+                ..grading
+            },
+            RoadGrading {
+                half_width: 6.,
+                ..grading
+            },
+        ] {
+            assert_ne!(changed.parameters(), grading.parameters());
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use crate::world_generation::chunk_generation::{ChunkParent, VOXEL_SIZE};
+use crate::world_generation::chunk_loading::chunk_loader::chunk_world_size;
+use crate::world_generation::voxel_world::MAX_LOD;
+use bevy::app::App;
+use bevy::math::{DVec2, DVec3};
+use bevy::prelude::{
+    Component, Entity, Event, EventWriter, First, IntoSystemConfigs, Node, Parent, Plugin, Query,
+    Res, ResMut, Resource, Transform, Vec3, Vec3Swizzles, With, Without,
+};
+
+pub struct FloatingOriginPlugin;
+
+impl Plugin for FloatingOriginPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FloatingOrigin>()
+            .add_event::<OriginShifted>()
+            .add_systems(First, (shift_origin, update_world_positions).chain());
+    }
+}
+
+/// Keeps the [`FloatingOriginAnchor`] close to zero by moving everything else once it gets too far
+/// away. `Transform`s are relative to `origin`, rapier picks the changed transforms up on its own.
+/// Only the chunk colliders around the anchor are in the physics world, see [`ChunkCollider`].
+///
+/// [`ChunkCollider`]: crate::world_generation::chunk_generation::ChunkCollider
+#[derive(Resource)]
+pub struct FloatingOrigin {
+    pub origin: DVec3,
+    pub shift_threshold: f32,
+    pub shift_step: f32,
+}
+
+impl Default for FloatingOrigin {
+    fn default() -> Self {
+        Self {
+            origin: DVec3::ZERO,
+            shift_threshold: chunk_world_size(MAX_LOD) * 2.,
+            shift_step: chunk_world_size(MAX_LOD),
+        }
+    }
+}
+
+impl FloatingOrigin {
+    pub fn to_world(&self, translation: Vec3) -> DVec3 {
+        self.origin + translation.as_dvec3()
+    }
+
+    pub fn to_render(&self, world_position: DVec3) -> Vec3 {
+        (world_position - self.origin).as_vec3()
+    }
+
+    pub fn voxel_to_render(&self, voxel_position: Vec3) -> Vec3 {
+        self.to_render(voxel_position.as_dvec3() * VOXEL_SIZE as f64)
+    }
+}
+
+/// Position of a root entity in the unshifted world.
+#[derive(Component, Default, Copy, Clone, Debug)]
+pub struct WorldPosition(pub DVec3);
+
+impl WorldPosition {
+    /// Horizontal position in voxels, the space roads and countries are generated in.
+    pub fn voxel_xz(&self) -> DVec2 {
+        DVec2::new(self.0.x, self.0.z) / VOXEL_SIZE as f64
+    }
+}
+
+#[derive(Component)]
+pub struct FloatingOriginAnchor;
+
+#[derive(Event, Copy, Clone, Debug)]
+pub struct OriginShifted {
+    /// Amount every `Transform` got moved by.
+    pub offset: Vec3,
+    pub origin: DVec3,
+}
+
+/// Chunk parents are left at zero and UI nodes are laid out in screen space.
+type ShiftedRoot = (Without<Parent>, Without<ChunkParent>, Without<Node>);
+
+fn shift_origin(
+    mut floating_origin: ResMut<FloatingOrigin>,
+    anchors: Query<Entity, With<FloatingOriginAnchor>>,
+    mut roots: Query<&mut Transform, ShiftedRoot>,
+    mut children: Query<(&mut Transform, &Parent), Without<ChunkParent>>,
+    chunk_parents: Query<(), With<ChunkParent>>,
+    mut origin_shifted_events: EventWriter<OriginShifted>,
+) {
+    let Some(anchor_translation) = anchors
+        .iter()
+        .find_map(|anchor| roots.get(anchor).ok())
+        .map(|transform| transform.translation)
+    else {
+        return;
+    };
+
+    if anchor_translation.xz().abs().max_element() < floating_origin.shift_threshold {
+        return;
+    }
+
+    let step = floating_origin.shift_step;
+    let shift = Vec3::new(
+        (anchor_translation.x / step).round() * step,
+        0.,
+        (anchor_translation.z / step).round() * step,
+    );
+
+    floating_origin.origin += shift.as_dvec3();
+
+    for mut transform in &mut roots {
+        transform.translation -= shift;
+    }
+
+    // Chunk parents stay at zero, so their chunks need to be moved one by one.
+    for (mut transform, parent) in &mut children {
+        if chunk_parents.contains(parent.get()) {
+            transform.translation -= shift;
+        }
+    }
+
+    origin_shifted_events.send(OriginShifted {
+        offset: -shift,
+        origin: floating_origin.origin,
+    });
+}
+
+fn update_world_positions(
+    floating_origin: Res<FloatingOrigin>,
+    mut world_positions: Query<(&mut WorldPosition, &Transform), Without<Parent>>,
+) {
+    for (mut world_position, transform) in &mut world_positions {
+        world_position.0 = floating_origin.to_world(transform.translation);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::prelude::{BuildWorldChildren, TransformBundle};
+
+    #[test]
+    fn shift_keeps_world_positions() {
+        let mut app = App::new();
+        app.add_plugins(FloatingOriginPlugin);
+
+        let threshold = app.world.resource::<FloatingOrigin>().shift_threshold;
+        let anchor_position = Vec3::new(threshold * 1.3, 20., -threshold * 2.6);
+        let other_position = Vec3::new(-100., 5., 40.);
+        let chunk_position = Vec3::new(320., 0., 64.);
+
+        let anchor = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_translation(anchor_position)),
+                WorldPosition::default(),
+                FloatingOriginAnchor,
+            ))
+            .id();
+        let other = app
+            .world
+            .spawn((
+                TransformBundle::from_transform(Transform::from_translation(other_position)),
+                WorldPosition::default(),
+            ))
+            .id();
+        let mut chunk = Entity::PLACEHOLDER;
+        app.world
+            .spawn((TransformBundle::default(), ChunkParent([0, 0])))
+            .with_children(|parent| {
+                chunk = parent
+                    .spawn(TransformBundle::from_transform(
+                        Transform::from_translation(chunk_position),
+                    ))
+                    .id();
+            });
+
+        app.update();
+
+        let floating_origin = app.world.resource::<FloatingOrigin>();
+        assert_ne!(floating_origin.origin, DVec3::ZERO);
+        let anchor_translation = app.world.get::<Transform>(anchor).unwrap().translation;
+        assert!(anchor_translation.xz().abs().max_element() < floating_origin.shift_threshold);
+
+        for (entity, position) in [(anchor, anchor_position), (other, other_position)] {
+            let translation = app.world.get::<Transform>(entity).unwrap().translation;
+            assert_eq!(floating_origin.to_world(translation), position.as_dvec3());
+            assert_eq!(
+                app.world.get::<WorldPosition>(entity).unwrap().0,
+                position.as_dvec3()
+            );
+        }
+        let chunk_translation = app.world.get::<Transform>(chunk).unwrap().translation;
+        assert_eq!(
+            floating_origin.to_world(chunk_translation),
+            chunk_position.as_dvec3()
+        );
+    }
+}
@@ -1,18 +1,35 @@
-use crate::world_generation::chunk_generation::voxel_generation::StructureGenerator;
+pub mod disk_cache;
+
+use crate::world_generation::chunk_generation::jigsaw::{Connector, JigsawRules};
+use crate::world_generation::chunk_generation::structure_model::StructureModel;
+use crate::world_generation::chunk_generation::structure_placement::{
+    FoundationMode, PaletteSwap, PlacementRules,
+};
+use crate::world_generation::chunk_generation::voxel_generation::{
+    ColumnData, StructureGenerator, StructureVariant,
+};
 use crate::world_generation::chunk_generation::BlockType;
 use crate::world_generation::chunk_loading::country_cache::{
     CountryCache, PathCache, StructureCache,
 };
-use bevy::prelude::{IVec2, Resource};
+use crate::world_generation::chunk_loading::country_layout::CountryLayout;
+use crate::world_generation::chunk_loading::road_decoration::{RoadPropModel, RoadPropModels};
+use crate::world_generation::chunk_loading::road_grading::RoadGrading;
+use crate::world_generation::generation_options::disk_cache::DiskCache;
+use crate::world_generation::vox::read_vox_file;
+use crate::world_generation::voxel_world::ChunkLod;
+use bevy::prelude::{error, IVec2, IVec3, Resource};
 use bracket_noise::prelude::FastNoise;
 use bracket_noise::prelude::NoiseType::WhiteNoise;
 use rand::prelude::StdRng;
 use rand::{Rng, SeedableRng};
 use std::collections::HashMap;
+use std::convert::Infallible;
 use std::hash::Hash;
 use std::ops::Deref;
-use std::sync::{Arc, RwLock};
-use vox_format::{from_file, VoxData};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
 
 #[derive(Resource)]
 pub struct GenerationOptionsResource(
@@ -20,61 +37,187 @@ pub struct GenerationOptionsResource(
     pub HashMap<IVec2, GenerationState<CountryCache>>,
 );
 
+/// Parts of the [`GenerationOptions`] that aren't derived from the seed.
+#[derive(Clone, Default)]
+pub struct GenerationSettings {
+    /// Directory the generated caches are kept in, see [`DiskCache`]. No disk cache if `None`.
+    pub cache_root: Option<PathBuf>,
+    pub road_grading: RoadGrading,
+}
+
 impl GenerationOptionsResource {
+    /// Options with the default settings, without a disk cache.
     pub fn from_seed(seed: u64) -> Self {
-        let tree = vox_data_to_structure_data(&from_file("assets/tree_2.vox").unwrap());
-        let tree_house = vox_data_to_structure_data(&from_file("assets/tree_house.vox").unwrap());
-        let box_structure = vox_data_to_structure_data(&from_file("assets/box.vox").unwrap());
+        Self::with_settings(seed, GenerationSettings::default())
+    }
+
+    pub fn with_settings(seed: u64, settings: GenerationSettings) -> Self {
+        let tree = load_structure("assets/tree_2.vox");
+        let small_tree = load_structure("assets/tree.vox");
+        let box_structure = load_structure("assets/box.vox");
+        let road_prop = |path: &str| {
+            let (model, model_size) = load_structure(path);
+            RoadPropModel { model, model_size }
+        };
+
+        let tree_variants = vec![
+            StructureVariant {
+                model: tree.0.clone(),
+                model_size: tree.1,
+                weight: 3,
+                connectors: vec![],
+            },
+            StructureVariant {
+                model: small_tree.0.clone(),
+                model_size: small_tree.1,
+                weight: 1,
+                connectors: vec![],
+            },
+        ];
+        let tree_palettes = vec![
+            PaletteSwap::default(),
+            PaletteSwap::from_models(&[tree.0.blocks(), small_tree.0.blocks()], autumn_leaves),
+        ];
 
         let mut rng = StdRng::seed_from_u64(seed);
+        let road_grading = settings.road_grading;
 
         Self {
             0: Arc::new(GenerationOptions {
                 seed,
-                path_cache: GenerationCache::new(),
-                structure_cache: GenerationCache::new(),
+                path_cache: GenerationCache::with_capacity(64),
+                structure_cache: GenerationCache::with_capacity(64),
+                country_cache: GenerationCache::with_capacity(64),
+                column_cache: GenerationCache::with_capacity(1024),
+                disk_cache: DiskCache::new(seed, settings.cache_root)
+                    .with_parameters(&road_grading.parameters()),
+                country_layout: CountryLayout::new(seed),
                 structures: vec![
                     StructureGenerator {
-                        model: tree.0.clone(),
-                        model_size: tree.1,
+                        name: "tree_shifted",
+                        variants: tree_variants.clone(),
+                        palettes: tree_palettes.clone(),
+                        random_rotation: true,
+                        random_mirroring: true,
                         noise: get_seeded_white_noise(rng.gen()),
                         generation_size: [30, 30],
                         grid_offset: [15, 15],
                         generate_debug_blocks: false,
                         debug_rgb_multiplier: [1., 0., 0.],
+                        placement: PlacementRules {
+                            priority: 0,
+                            exclusion_radius: 0,
+                            road_clearance: 8,
+                        },
+                        foundation: FoundationMode::Rooted,
+                        blend_radius: 0,
+                        jigsaw: None,
                     },
                     StructureGenerator {
-                        model: tree.0.clone(),
-                        model_size: tree.1,
+                        name: "tree",
+                        variants: tree_variants.clone(),
+                        palettes: tree_palettes.clone(),
+                        random_rotation: true,
+                        random_mirroring: true,
                         noise: get_seeded_white_noise(rng.gen()),
                         generation_size: [30, 30],
                         grid_offset: [0, 0],
                         generate_debug_blocks: false,
                         debug_rgb_multiplier: [0., 1., 0.],
+                        placement: PlacementRules {
+                            priority: 0,
+                            exclusion_radius: 0,
+                            road_clearance: 8,
+                        },
+                        foundation: FoundationMode::Rooted,
+                        blend_radius: 0,
+                        jigsaw: None,
                     },
                     StructureGenerator {
-                        model: tree_house.0.clone(),
-                        model_size: tree_house.1,
+                        name: "tree_house",
+                        variants: load_structure_variants("assets/tree_house.vox"),
+                        palettes: vec![],
+                        random_rotation: true,
+                        random_mirroring: true,
                         noise: get_seeded_white_noise(rng.gen()),
                         generation_size: [1000, 1000],
                         grid_offset: [7, 11],
                         generate_debug_blocks: false,
                         debug_rgb_multiplier: [1., 1., 1.],
+                        placement: PlacementRules {
+                            priority: 10,
+                            exclusion_radius: 12,
+                            road_clearance: 24,
+                        },
+                        foundation: FoundationMode::Flatten,
+                        blend_radius: 8,
+                        jigsaw: None,
+                    },
+                    StructureGenerator {
+                        name: "mine",
+                        variants: load_jigsaw_pieces("assets/mine.vox", mine_pieces()),
+                        palettes: vec![],
+                        random_rotation: true,
+                        random_mirroring: false,
+                        noise: get_seeded_white_noise(rng.gen()),
+                        generation_size: [600, 600],
+                        grid_offset: [300, 300],
+                        generate_debug_blocks: false,
+                        debug_rgb_multiplier: [0.5, 0.5, 0.5],
+                        placement: PlacementRules {
+                            priority: 5,
+                            exclusion_radius: 0,
+                            road_clearance: 16,
+                        },
+                        foundation: FoundationMode::Buried,
+                        blend_radius: 0,
+                        jigsaw: Some(JigsawRules {
+                            start: 0,
+                            max_pieces: 24,
+                            max_radius: 96,
+                        }),
                     },
                 ],
-                structure_assets: vec![StructureAsset((*box_structure.0).clone())],
+                structure_assets: vec![StructureAsset(box_structure.0.blocks().clone())],
+                road_grading,
+                road_prop_models: RoadPropModels {
+                    signpost: road_prop("assets/signpost.vox"),
+                    milestone: road_prop("assets/milestone.vox"),
+                    lantern: road_prop("assets/lantern.vox"),
+                },
             }),
             1: HashMap::new(),
         }
     }
 }
 
+impl GenerationOptionsResource {
+    /// Caches of `countries` that are done generating, missing ones are skipped.
+    pub fn generated_country_caches(
+        &self,
+        countries: impl IntoIterator<Item = IVec2>,
+    ) -> Vec<&CountryCache> {
+        countries
+            .into_iter()
+            .filter_map(|country| match self.1.get(&country) {
+                Some(GenerationState::Some(country_cache)) => Some(country_cache),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
 impl Default for GenerationOptionsResource {
     fn default() -> Self {
         Self::from_seed(3)
     }
 }
 
+/// Turns green leaves orange, keeping their brightness.
+fn autumn_leaves([r, g, b]: [u8; 3]) -> Option<[u8; 3]> {
+    (g > r && g > b).then(|| [g, (g as f32 * 0.55) as u8, b / 3])
+}
+
 fn get_seeded_white_noise(seed: u64) -> FastNoise {
     let mut noise = FastNoise::seeded(seed);
     noise.set_noise_type(WhiteNoise);
@@ -85,6 +228,12 @@ fn get_seeded_white_noise(seed: u64) -> FastNoise {
 pub enum GenerationState<T> {
     Generating,
     Some(T),
+    /// Generation failed, it is retried once `retry_at` seconds since startup have passed.
+    Failed {
+        error: String,
+        attempts: u32,
+        retry_at: f32,
+    },
 }
 
 pub struct GenerationOptions {
@@ -93,35 +242,167 @@ pub struct GenerationOptions {
     pub structure_assets: Vec<StructureAsset>,
     pub path_cache: GenerationCache<IVec2, PathCache>,
     pub structure_cache: GenerationCache<IVec2, StructureCache>,
+    /// Countries with their roads, shared by the chunk loader, the map and region exports.
+    pub country_cache: GenerationCache<IVec2, CountryCache>,
+    pub column_cache: GenerationCache<(IVec2, ChunkLod), ColumnData>,
+    pub disk_cache: DiskCache,
+    pub country_layout: CountryLayout,
+    pub road_grading: RoadGrading,
+    pub road_prop_models: RoadPropModels,
+}
+
+impl GenerationOptions {
+    /// Index of the first structure generator called `name`.
+    pub fn structure_generator(&self, name: &str) -> Option<usize> {
+        self.structures
+            .iter()
+            .position(|structure| structure.name == name)
+    }
 }
 
-pub trait GenerationCacheItem<K: Copy + Eq + Hash> {
+pub trait GenerationCacheItem<K: Copy + Eq + Hash>: Sized {
     fn generate(key: K, generation_options: &GenerationOptions) -> Self;
+
+    /// Like [`GenerationCacheItem::generate`], for items that report failures instead of working
+    /// around them.
+    fn try_generate(key: K, generation_options: &GenerationOptions) -> Result<Self, String> {
+        Ok(Self::generate(key, generation_options))
+    }
+
+    /// Previously stored item of `key`, checked before generating it.
+    fn load(_key: K, _generation_options: &GenerationOptions) -> Option<Self> {
+        None
+    }
+
+    /// Called with every newly generated item.
+    fn store(&self, _key: K, _generation_options: &GenerationOptions) {}
+}
+
+/// Items that failed to generate are not cached, the entry stays empty and gets generated again by
+/// the next caller. Locks poisoned by a panicking generator are recovered the same way.
+pub struct GenerationCache<K: Copy + Eq + Hash, T> {
+    cache_lock: RwLock<HashMap<K, CacheSlot<T>>>,
+    capacity: Option<usize>,
+    clock: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+struct CacheSlot<T> {
+    entry: Arc<RwLock<Option<Arc<T>>>>,
+    last_used: AtomicU64,
 }
 
-pub struct GenerationCache<K: Copy + Eq + Hash, T: GenerationCacheItem<K>> {
-    cache_lock: RwLock<HashMap<K, Arc<RwLock<Option<Arc<T>>>>>>,
+#[derive(Copy, Clone, Debug, Default)]
+pub struct GenerationCacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
 }
 
 impl<K: Copy + Eq + Hash, T: GenerationCacheItem<K>> GenerationCache<K, T> {
+    pub fn get_cache_entry(&self, key: K, generation_options: &GenerationOptions) -> Arc<T> {
+        self.get_or_generate(key, || {
+            T::load(key, generation_options).unwrap_or_else(|| {
+                let item = T::generate(key, generation_options);
+                item.store(key, generation_options);
+                item
+            })
+        })
+    }
+
+    /// Like [`GenerationCache::get_cache_entry`], but failed items are returned as errors and
+    /// neither cached nor stored.
+    pub fn try_get_cache_entry(
+        &self,
+        key: K,
+        generation_options: &GenerationOptions,
+    ) -> Result<Arc<T>, String> {
+        self.try_get_or_generate(key, || match T::load(key, generation_options) {
+            Some(item) => Ok(item),
+            None => {
+                let item = T::try_generate(key, generation_options)?;
+                item.store(key, generation_options);
+                Ok(item)
+            }
+        })
+    }
+}
+
+impl<K: Copy + Eq + Hash, T> GenerationCache<K, T> {
     pub fn new() -> Self {
         Self {
             cache_lock: RwLock::new(HashMap::new()),
+            capacity: None,
+            clock: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
         }
     }
 
-    pub fn get_cache_entry(&self, key: K, generation_options: &GenerationOptions) -> Arc<T> {
-        self.get_generated_cache_entry(self.get_hash_lock_entry(key), key, generation_options)
+    /// Once more than `capacity` entries are cached the least recently used ones get evicted.
+    /// Evicted entries stay alive for as long as someone still holds their `Arc`.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity: Some(capacity),
+            ..Self::new()
+        }
+    }
+
+    /// Like [`GenerationCache::get_cache_entry`], for items that need more than the key to be generated.
+    pub fn get_or_generate<F: FnOnce() -> T>(&self, key: K, generate: F) -> Arc<T> {
+        self.try_get_or_generate(key, || Ok::<_, Infallible>(generate()))
+            .unwrap_or_else(|infallible| match infallible {})
+    }
+
+    /// Like [`GenerationCache::get_or_generate`], the entry stays empty if `generate` fails.
+    pub fn try_get_or_generate<E, F: FnOnce() -> Result<T, E>>(
+        &self,
+        key: K,
+        generate: F,
+    ) -> Result<Arc<T>, E> {
+        self.get_generated_cache_entry(self.get_hash_lock_entry(key), generate)
+    }
+
+    pub fn retain<F: FnMut(&K) -> bool>(&self, mut keep: F) {
+        let mut write = self
+            .cache_lock
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        let len = write.len();
+        write.retain(|key, _| keep(key));
+        self.evictions
+            .fetch_add((len - write.len()) as u64, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> GenerationCacheStats {
+        GenerationCacheStats {
+            entries: self
+                .cache_lock
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
     }
 
     pub fn try_get_entry_no_lock(&self, key: K) -> Option<Arc<T>> {
         match self.cache_lock.try_read() {
             Ok(read) => {
-                let entry = read.get(&key)?;
-                match entry.try_read() {
+                let slot = read.get(&key)?;
+                match slot.entry.try_read() {
                     Ok(read) => match read.deref() {
                         None => None,
-                        Some(t) => Some(t.clone()),
+                        Some(t) => {
+                            slot.last_used.store(self.tick(), Ordering::Relaxed);
+                            self.hits.fetch_add(1, Ordering::Relaxed);
+                            Some(t.clone())
+                        }
                     },
                     Err(_) => None,
                 }
@@ -130,86 +411,282 @@ impl<K: Copy + Eq + Hash, T: GenerationCacheItem<K>> GenerationCache<K, T> {
         }
     }
 
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed)
+    }
+
     fn get_hash_lock_entry(&self, key: K) -> Arc<RwLock<Option<Arc<T>>>> {
-        let read = self.cache_lock.read().unwrap();
+        let read = self
+            .cache_lock
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
         match read.get(&key) {
             None => {
                 drop(read);
-                let mut write = self.cache_lock.write().unwrap();
+                let mut write = self
+                    .cache_lock
+                    .write()
+                    .unwrap_or_else(PoisonError::into_inner);
                 let result = match write.get(&key) {
                     None => {
                         let lock = Arc::new(RwLock::new(None));
-                        write.insert(key, lock);
-                        write.get(&key).unwrap().clone()
+                        write.insert(
+                            key,
+                            CacheSlot {
+                                entry: lock.clone(),
+                                last_used: AtomicU64::new(self.tick()),
+                            },
+                        );
+                        self.evict_least_recently_used(&mut write);
+                        lock
                     }
-                    Some(cache) => cache.clone(),
+                    Some(slot) => slot.entry.clone(),
                 };
                 drop(write);
                 result
             }
-            Some(cache) => cache.clone(),
+            Some(slot) => {
+                slot.last_used.store(self.tick(), Ordering::Relaxed);
+                slot.entry.clone()
+            }
+        }
+    }
+
+    /// Entries that are still being generated are skipped, evicting them would only cause a
+    /// second generation of the same item.
+    fn evict_least_recently_used(&self, cache: &mut HashMap<K, CacheSlot<T>>) {
+        let Some(capacity) = self.capacity else {
+            return;
+        };
+
+        if cache.len() <= capacity {
+            return;
+        }
+
+        let mut generated = cache
+            .iter()
+            .filter(|(_, slot)| matches!(slot.entry.try_read().as_deref(), Ok(Some(_))))
+            .map(|(key, slot)| (*key, slot.last_used.load(Ordering::Relaxed)))
+            .collect::<Vec<_>>();
+        generated.sort_unstable_by_key(|(_, last_used)| *last_used);
+
+        for (key, _) in generated.into_iter().take(cache.len() - capacity) {
+            cache.remove(&key);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
         }
     }
 
-    fn get_generated_cache_entry(
+    fn get_generated_cache_entry<E, F: FnOnce() -> Result<T, E>>(
         &self,
         hash_lock_entry: Arc<RwLock<Option<Arc<T>>>>,
-        key: K,
-        generation_options: &GenerationOptions,
-    ) -> Arc<T> {
-        let read = hash_lock_entry.read().unwrap();
+        generate: F,
+    ) -> Result<Arc<T>, E> {
+        let read = hash_lock_entry
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
         match read.deref() {
             None => {
                 drop(read);
-                let mut write = hash_lock_entry.write().unwrap();
+                let mut write = hash_lock_entry
+                    .write()
+                    .unwrap_or_else(PoisonError::into_inner);
                 match write.deref() {
-                    None => write
-                        .insert(Arc::new(T::generate(key, generation_options)))
-                        .clone(),
-                    Some(country_cache) => country_cache.clone(),
+                    None => {
+                        self.misses.fetch_add(1, Ordering::Relaxed);
+                        Ok(write.insert(Arc::new(generate()?)).clone())
+                    }
+                    Some(country_cache) => {
+                        self.hits.fetch_add(1, Ordering::Relaxed);
+                        Ok(country_cache.clone())
+                    }
                 }
             }
-            Some(country_cache) => country_cache.clone(),
+            Some(country_cache) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Ok(country_cache.clone())
+            }
         }
     }
 }
 
 pub struct StructureAsset(Vec<Vec<Vec<BlockType>>>);
 
-fn vox_data_to_blocks(vox_data: &VoxData) -> Vec<Vec<Vec<BlockType>>> {
-    let model = vox_data.models.first().unwrap();
-    let mut result: Vec<Vec<Vec<BlockType>>> = Vec::with_capacity(model.size.x as usize);
-    for x in 0..model.size.x {
-        result.push(Vec::with_capacity(model.size.z as usize));
-        for y in 0..model.size.z {
-            result[x as usize].push(Vec::with_capacity(model.size.y as usize));
-            for _ in 0..model.size.y {
-                result[x as usize][y as usize].push(BlockType::Air);
-            }
+/// Blocks of all models of the scene at `path`. Broken files get logged and replaced by an empty
+/// structure, so a bad asset doesn't stop the world from generating.
+fn load_structure(path: &str) -> (Arc<StructureModel>, [i32; 3]) {
+    match read_vox_file(path).and_then(|scene| scene.to_blocks()) {
+        Ok(blocks) => (Arc::new(StructureModel::new(blocks.blocks)), blocks.size),
+        Err(error) => {
+            error!("Failed to load structure {}: {}", path, error);
+            (
+                Arc::new(StructureModel::new(vec![vec![vec![BlockType::Air]]])),
+                [1, 1, 1],
+            )
         }
     }
+}
+
+/// Every named part of the scene at `path` as its own variant, or the whole scene if nothing in
+/// it has a name.
+fn load_structure_variants(path: &str) -> Vec<StructureVariant> {
+    let parts = match read_vox_file(path).and_then(|scene| scene.named_parts()) {
+        Ok(parts) => parts,
+        Err(error) => {
+            error!("Failed to load structure {}: {}", path, error);
+            vec![]
+        }
+    };
 
-    for voxel in model.voxels.iter() {
-        let color = vox_data.palette.colors[voxel.color_index.0 as usize];
-        result[voxel.point.x as usize][voxel.point.z as usize][voxel.point.y as usize] =
-            BlockType::Custom(color.r, color.g, color.b);
+    if parts.is_empty() {
+        let (model, model_size) = load_structure(path);
+        return vec![StructureVariant {
+            model,
+            model_size,
+            weight: 1,
+            connectors: vec![],
+        }];
     }
 
-    result
+    parts
+        .into_iter()
+        .map(|(_, blocks)| StructureVariant {
+            model: Arc::new(StructureModel::new(blocks.blocks)),
+            model_size: blocks.size,
+            weight: 1,
+            connectors: vec![],
+        })
+        .collect()
+}
+
+/// Parts of the scene at `path` with the given names, weights and connectors, in the same order.
+/// Missing parts get logged and replaced by an empty piece, so the indices stay the same.
+fn load_jigsaw_pieces(
+    path: &str,
+    pieces: Vec<(&str, u32, Vec<Connector>)>,
+) -> Vec<StructureVariant> {
+    let mut parts = match read_vox_file(path).and_then(|scene| scene.named_parts()) {
+        Ok(parts) => parts,
+        Err(error) => {
+            error!("Failed to load structure {}: {}", path, error);
+            vec![]
+        }
+    };
+
+    pieces
+        .into_iter()
+        .map(|(name, weight, connectors)| {
+            let (model, model_size) = match parts.iter().position(|(part, _)| part == name) {
+                Some(index) => {
+                    let (_, blocks) = parts.swap_remove(index);
+                    (Arc::new(StructureModel::new(blocks.blocks)), blocks.size)
+                }
+                None => {
+                    error!("Structure {} has no part called {}", path, name);
+                    (
+                        Arc::new(StructureModel::new(vec![vec![vec![BlockType::Air]]])),
+                        [1, 1, 1],
+                    )
+                }
+            };
+
+            StructureVariant {
+                model,
+                model_size,
+                weight,
+                connectors,
+            }
+        })
+        .collect()
 }
 
-fn vox_data_model_size(vox_data: &VoxData) -> [i32; 3] {
-    let model_size = vox_data.models.first().unwrap().size;
-    [
-        model_size.x as i32,
-        model_size.z as i32,
-        model_size.y as i32,
+/// Pieces of `assets/mine.vox`, all joined by tunnels with their floor one block up.
+fn mine_pieces() -> Vec<(&'static str, u32, Vec<Connector>)> {
+    let tunnel = |x, y, z, facing| Connector {
+        position: IVec3::new(x, y, z),
+        facing,
+        kind: "tunnel",
+    };
+
+    vec![
+        ("entrance", 0, vec![tunnel(6, 1, 3, IVec3::X)]),
+        (
+            "tunnel",
+            6,
+            vec![tunnel(0, 1, 3, IVec3::NEG_X), tunnel(8, 1, 3, IVec3::X)],
+        ),
+        (
+            "stairs",
+            2,
+            vec![tunnel(0, 8, 3, IVec3::NEG_X), tunnel(12, 1, 3, IVec3::X)],
+        ),
+        (
+            "room",
+            2,
+            vec![
+                tunnel(0, 1, 7, IVec3::NEG_X),
+                tunnel(14, 1, 7, IVec3::X),
+                tunnel(7, 1, 0, IVec3::NEG_Z),
+                tunnel(7, 1, 14, IVec3::Z),
+            ],
+        ),
     ]
 }
 
-fn vox_data_to_structure_data(vox_data: &VoxData) -> (Arc<Vec<Vec<Vec<BlockType>>>>, [i32; 3]) {
-    (
-        Arc::new(vox_data_to_blocks(vox_data)),
-        vox_data_model_size(vox_data),
-    )
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mine_connectors_are_openings_on_the_piece_boundary() {
+        let pieces = mine_pieces();
+        let variants = load_jigsaw_pieces("assets/mine.vox", mine_pieces());
+
+        for ((name, _, _), variant) in pieces.iter().zip(&variants) {
+            assert!(variant.model_size != [1, 1, 1], "{} is missing", name);
+            let size = IVec3::from_array(variant.model_size);
+
+            for connector in &variant.connectors {
+                let position = connector.position;
+                assert!(
+                    position.cmpge(IVec3::ZERO).all() && position.cmplt(size).all(),
+                    "{} has a connector outside of it at {}",
+                    name,
+                    position
+                );
+                // The block past the connector is just outside of the model.
+                let outside = position + connector.facing;
+                assert!(
+                    outside.cmplt(IVec3::ZERO).any() || outside.cmpge(size).any(),
+                    "{} has a connector at {} inside of it",
+                    name,
+                    position
+                );
+                assert!(
+                    variant.model.blocks()[position.x as usize][position.y as usize]
+                        [position.z as usize]
+                        == BlockType::Air,
+                    "{} has no opening at {}",
+                    name,
+                    position
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn failed_entries_are_generated_again() {
+        let cache = GenerationCache::<i32, i32>::new();
+
+        assert_eq!(
+            cache.try_get_or_generate(1, || Err("no roads")),
+            Err("no roads")
+        );
+        assert!(cache.try_get_entry_no_lock(1).is_none());
+
+        assert_eq!(
+            cache.try_get_or_generate(1, || Ok::<_, &str>(5)),
+            Ok(Arc::new(5))
+        );
+        assert_eq!(*cache.get_or_generate(1, || 6), 5);
+    }
 }
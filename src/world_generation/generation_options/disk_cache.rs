@@ -0,0 +1,418 @@
+use crate::world_generation::chunk_loading::country_cache::COUNTRY_SIZE;
+use bevy::log::{info, warn};
+use bevy::prelude::{IVec2, Vec2};
+use std::path::PathBuf;
+use std::{env, fs, io};
+
+/// Bump this whenever the output of the cached generators changes, old cache files get ignored.
+pub const GENERATOR_VERSION: u32 = 5;
+
+/// Overrides the directory the game keeps its cache in.
+const CACHE_DIRECTORY_VARIABLE: &str = "SPELLHAVEN_CACHE_DIR";
+const MAGIC: [u8; 4] = *b"SHGC";
+const HEADER_SIZE: usize = 4 + 4 + 8 + 8 + 8;
+
+/// Generated data that can be stored in a [`DiskCache`].
+pub trait DiskCacheItem: Sized {
+    /// Prefix of the cache files, has to be unique per item type.
+    const NAME: &'static str;
+
+    fn write(&self, writer: &mut ByteWriter);
+    fn read(reader: &mut ByteReader) -> Option<Self>;
+}
+
+/// Per-seed directory of generated items, keyed by country position.
+///
+/// Every file starts with a header holding the generator version, a fingerprint of the generation
+/// parameters and a checksum of the payload. Files that don't match are regenerated.
+///
+/// Without a directory nothing is read or written, which is the default outside of the game.
+pub struct DiskCache {
+    directory: Option<PathBuf>,
+    fingerprint: u64,
+}
+
+impl DiskCache {
+    /// Cache of `seed` below `root`, disabled if `root` is `None`.
+    pub fn new(seed: u64, root: Option<PathBuf>) -> Self {
+        let mut fingerprint = Fnv1a::new();
+        fingerprint.write(&GENERATOR_VERSION.to_le_bytes());
+        fingerprint.write(&seed.to_le_bytes());
+        fingerprint.write(&(COUNTRY_SIZE as u64).to_le_bytes());
+
+        Self {
+            directory: root.map(|root| root.join(seed.to_string())),
+            fingerprint: fingerprint.finish(),
+        }
+    }
+
+    /// Mixes generation parameters that aren't derived from the seed into the fingerprint.
+    pub fn with_parameters(mut self, parameters: &[f32]) -> Self {
+        let mut fingerprint = Fnv1a(self.fingerprint);
+        for parameter in parameters {
+            fingerprint.write(&parameter.to_le_bytes());
+        }
+        self.fingerprint = fingerprint.finish();
+
+        self
+    }
+
+    /// Root of the game's cache: `SPELLHAVEN_CACHE_DIR` if it's set, otherwise the platform cache
+    /// directory. `None` if neither is known.
+    pub fn default_root() -> Option<PathBuf> {
+        if let Some(directory) = env::var_os(CACHE_DIRECTORY_VARIABLE) {
+            return Some(PathBuf::from(directory));
+        }
+
+        let platform_directory = if cfg!(windows) {
+            env::var_os("LOCALAPPDATA").map(PathBuf::from)
+        } else if cfg!(target_os = "macos") {
+            env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Caches"))
+        } else {
+            env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        };
+
+        platform_directory
+            .filter(|directory| directory.is_absolute())
+            .map(|directory| directory.join("spellhaven"))
+    }
+
+    /// Cached item of `key`, `None` if it isn't cached or the file is outdated or corrupted.
+    pub fn load<T: DiskCacheItem>(&self, key: IVec2) -> Option<T> {
+        let bytes = fs::read(self.file_path::<T>(key)?).ok()?;
+
+        let item = self.decode(&bytes);
+        if item.is_none() {
+            info!(
+                "Ignoring outdated or corrupted cache file for {} {}",
+                T::NAME,
+                key
+            );
+        }
+
+        item
+    }
+
+    /// Writes `item` to the cache, failures are only logged since the item can be generated again.
+    pub fn store<T: DiskCacheItem>(&self, key: IVec2, item: &T) {
+        if let Err(error) = self.write(key, item) {
+            warn!(
+                "Failed to write {} {} to the disk cache: {}",
+                T::NAME,
+                key,
+                error
+            );
+        }
+    }
+
+    /// Removes every cached file of this seed.
+    pub fn invalidate(&self) -> io::Result<()> {
+        let Some(directory) = &self.directory else {
+            return Ok(());
+        };
+
+        match fs::remove_dir_all(directory) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+
+    fn file_path<T: DiskCacheItem>(&self, key: IVec2) -> Option<PathBuf> {
+        let directory = self.directory.as_ref()?;
+        Some(directory.join(format!("{}_{}_{}.bin", T::NAME, key.x, key.y)))
+    }
+
+    fn decode<T: DiskCacheItem>(&self, bytes: &[u8]) -> Option<T> {
+        let mut reader = ByteReader::new(bytes);
+
+        if reader.read_bytes(4)? != MAGIC
+            || reader.read_u32()? != GENERATOR_VERSION
+            || reader.read_u64()? != self.fingerprint
+        {
+            return None;
+        }
+
+        let length = reader.read_u64()? as usize;
+        let checksum = reader.read_u64()?;
+        let payload = reader.read_bytes(length)?;
+
+        if Fnv1a::hash(payload) != checksum {
+            return None;
+        }
+
+        let mut reader = ByteReader::new(payload);
+        let item = T::read(&mut reader)?;
+
+        reader.is_empty().then_some(item)
+    }
+
+    fn write<T: DiskCacheItem>(&self, key: IVec2, item: &T) -> io::Result<()> {
+        let (Some(directory), Some(path)) = (&self.directory, self.file_path::<T>(key)) else {
+            return Ok(());
+        };
+
+        let mut payload = ByteWriter::default();
+        item.write(&mut payload);
+
+        let mut file = ByteWriter::default();
+        file.bytes.reserve(HEADER_SIZE + payload.bytes.len());
+        file.write_bytes(&MAGIC);
+        file.write_u32(GENERATOR_VERSION);
+        file.write_u64(self.fingerprint);
+        file.write_u64(payload.bytes.len() as u64);
+        file.write_u64(Fnv1a::hash(&payload.bytes));
+        file.write_bytes(&payload.bytes);
+
+        fs::create_dir_all(directory)?;
+
+        // Written to a temporary file first, so a crash never leaves a half written cache file behind.
+        let temporary_path = path.with_extension("tmp");
+        fs::write(&temporary_path, &file.bytes)?;
+        fs::rename(temporary_path, path)
+    }
+}
+
+#[derive(Default)]
+pub struct ByteWriter {
+    bytes: Vec<u8>,
+}
+
+impl ByteWriter {
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_i32(&mut self, value: i32) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_len(&mut self, len: usize) {
+        self.write_u32(len as u32);
+    }
+
+    pub fn write_ivec2(&mut self, value: IVec2) {
+        self.write_i32(value.x);
+        self.write_i32(value.y);
+    }
+
+    pub fn write_vec2(&mut self, value: Vec2) {
+        self.write_f32(value.x);
+        self.write_f32(value.y);
+    }
+}
+
+pub struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < len {
+            return None;
+        }
+
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.read_bytes(N)?.try_into().ok()
+    }
+
+    pub fn read_u32(&mut self) -> Option<u32> {
+        self.read_array().map(u32::from_le_bytes)
+    }
+
+    pub fn read_u64(&mut self) -> Option<u64> {
+        self.read_array().map(u64::from_le_bytes)
+    }
+
+    pub fn read_i32(&mut self) -> Option<i32> {
+        self.read_array().map(i32::from_le_bytes)
+    }
+
+    pub fn read_f32(&mut self) -> Option<f32> {
+        self.read_array().map(f32::from_le_bytes)
+    }
+
+    /// Lengths larger than the remaining bytes can only come from a corrupted file.
+    pub fn read_len(&mut self) -> Option<usize> {
+        let len = self.read_u32()? as usize;
+        (len <= self.bytes.len()).then_some(len)
+    }
+
+    pub fn read_ivec2(&mut self) -> Option<IVec2> {
+        Some(IVec2::new(self.read_i32()?, self.read_i32()?))
+    }
+
+    pub fn read_vec2(&mut self) -> Option<Vec2> {
+        Some(Vec2::new(self.read_f32()?, self.read_f32()?))
+    }
+}
+
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn hash(bytes: &[u8]) -> u64 {
+        let mut hasher = Self::new();
+        hasher.write(bytes);
+        hasher.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Item {
+        values: Vec<i32>,
+        position: Vec2,
+    }
+
+    impl DiskCacheItem for Item {
+        const NAME: &'static str = "item";
+
+        fn write(&self, writer: &mut ByteWriter) {
+            writer.write_len(self.values.len());
+            for value in &self.values {
+                writer.write_i32(*value);
+            }
+            writer.write_vec2(self.position);
+        }
+
+        fn read(reader: &mut ByteReader) -> Option<Self> {
+            let len = reader.read_len()?;
+            let values = (0..len)
+                .map(|_| reader.read_i32())
+                .collect::<Option<Vec<_>>>()?;
+            Some(Self {
+                values,
+                position: reader.read_vec2()?,
+            })
+        }
+    }
+
+    fn item() -> Item {
+        Item {
+            values: vec![1, -2, 300],
+            position: Vec2::new(0.5, -4.),
+        }
+    }
+
+    /// Cache in a fresh temporary directory, removed again when the test finishes.
+    struct TemporaryCache {
+        root: PathBuf,
+        cache: DiskCache,
+    }
+
+    impl TemporaryCache {
+        fn new(name: &str) -> Self {
+            let root = env::temp_dir().join(format!(
+                "spellhaven_disk_cache_{}_{}",
+                std::process::id(),
+                name
+            ));
+            let _ = fs::remove_dir_all(&root);
+
+            Self {
+                cache: DiskCache::new(7, Some(root.clone())),
+                root,
+            }
+        }
+    }
+
+    impl Drop for TemporaryCache {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+
+    #[test]
+    fn stored_items_are_loaded_again() {
+        let temporary = TemporaryCache::new("round_trip");
+        let key = IVec2::new(-3, 5);
+
+        assert_eq!(temporary.cache.load::<Item>(key), None);
+        temporary.cache.store(key, &item());
+
+        assert_eq!(temporary.cache.load::<Item>(key), Some(item()));
+        assert_eq!(temporary.cache.load::<Item>(IVec2::new(5, -3)), None);
+        assert_eq!(
+            DiskCache::new(7, Some(temporary.root.clone())).load::<Item>(key),
+            Some(item())
+        );
+    }
+
+    #[test]
+    fn corrupted_and_outdated_files_are_ignored() {
+        let temporary = TemporaryCache::new("corrupted");
+        let key = IVec2::new(1, 2);
+        temporary.cache.store(key, &item());
+
+        let path = temporary.cache.file_path::<Item>(key).unwrap();
+        let bytes = fs::read(&path).unwrap();
+
+        let mut flipped = bytes.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        fs::write(&path, flipped).unwrap();
+        assert_eq!(temporary.cache.load::<Item>(key), None);
+
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert_eq!(temporary.cache.load::<Item>(key), None);
+
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(temporary.cache.load::<Item>(key), Some(item()));
+
+        let other_parameters =
+            DiskCache::new(7, Some(temporary.root.clone())).with_parameters(&[1.]);
+        assert_eq!(other_parameters.load::<Item>(key), None);
+    }
+
+    #[test]
+    fn caches_without_a_directory_are_disabled() {
+        let cache = DiskCache::new(7, None);
+        cache.store(IVec2::ZERO, &item());
+
+        assert_eq!(cache.load::<Item>(IVec2::ZERO), None);
+        assert!(cache.invalidate().is_ok());
+    }
+}
@@ -0,0 +1,475 @@
+use crate::world_generation::chunk_generation::mesh_generation::generate_mesh;
+use crate::world_generation::chunk_generation::voxel_generation::generate_voxels;
+use crate::world_generation::chunk_generation::{CHUNK_SIZE, VOXEL_SIZE};
+use crate::world_generation::generation_options::GenerationOptions;
+use crate::world_generation::region_export::chunk_country_cache;
+use crate::world_generation::voxel_world::ChunkLod;
+use bevy::math::{IVec2, Vec3};
+use bevy::render::mesh::{Indices, Mesh, VertexAttributeValues};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MeshFormat {
+    Obj,
+    /// Binary glTF, everything in a single file.
+    Glb,
+}
+
+impl MeshFormat {
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        match path.as_ref().extension()?.to_str()? {
+            "obj" => Some(Self::Obj),
+            "glb" => Some(Self::Glb),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ExportMaterial {
+    Opaque,
+    Transparent,
+    Emissive,
+    /// The physics mesh, without normals and colors.
+    Collider,
+}
+
+/// Part of an exported region, with positions in world units.
+pub struct ExportMesh {
+    pub material: ExportMaterial,
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub colors: Vec<[f32; 4]>,
+    pub indices: Vec<u32>,
+}
+
+impl ExportMesh {
+    fn new(material: ExportMaterial) -> Self {
+        Self {
+            material,
+            positions: vec![],
+            normals: vec![],
+            colors: vec![],
+            indices: vec![],
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self.material {
+            ExportMaterial::Opaque => "terrain",
+            ExportMaterial::Transparent => "transparent",
+            ExportMaterial::Emissive => "emissive",
+            ExportMaterial::Collider => "collider",
+        }
+    }
+
+    fn append(&mut self, mesh: &Mesh, offset: Vec3) {
+        let first_index = self.positions.len() as u32;
+
+        if let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        {
+            self.positions.extend(
+                positions
+                    .iter()
+                    .map(|position| (Vec3::from_array(*position) + offset).to_array()),
+            );
+        }
+        if let Some(VertexAttributeValues::Float32x3(normals)) =
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+        {
+            self.normals.extend(normals);
+        }
+        // Some blocks are brighter than white in the game, which other tools don't expect.
+        if let Some(VertexAttributeValues::Float32x4(colors)) =
+            mesh.attribute(Mesh::ATTRIBUTE_COLOR)
+        {
+            self.colors.extend(
+                colors
+                    .iter()
+                    .map(|color| color.map(|channel| channel.clamp(0., 1.))),
+            );
+        }
+        if let Some(Indices::U32(indices)) = mesh.indices() {
+            self.indices
+                .extend(indices.iter().map(|index| index + first_index));
+        }
+    }
+}
+
+/// Meshes every chunk touching the area from `min` to `max` at `chunk_lod`, the same way the
+/// game does. Colliders only exist at [`ChunkLod::Full`].
+pub fn generate_region_meshes(
+    generation_options: &GenerationOptions,
+    min: IVec2,
+    max: IVec2,
+    chunk_lod: ChunkLod,
+    with_collider: bool,
+) -> Vec<ExportMesh> {
+    let mut meshes = [
+        ExportMaterial::Opaque,
+        ExportMaterial::Transparent,
+        ExportMaterial::Emissive,
+        ExportMaterial::Collider,
+    ]
+    .map(ExportMesh::new);
+    let mut country_caches = HashMap::new();
+
+    let chunk_size = IVec2::new(CHUNK_SIZE[0] as i32, CHUNK_SIZE[2] as i32);
+    let lod_chunk_size = chunk_size * chunk_lod.multiplier_i32();
+    let first_chunk = IVec2::new(
+        min.x.div_euclid(lod_chunk_size.x),
+        min.y.div_euclid(lod_chunk_size.y),
+    );
+    let last_chunk = IVec2::new(
+        max.x.div_euclid(lod_chunk_size.x),
+        max.y.div_euclid(lod_chunk_size.y),
+    );
+
+    for chunk_x in first_chunk.x..=last_chunk.x {
+        for chunk_z in first_chunk.y..=last_chunk.y {
+            let chunk_position = IVec2::new(chunk_x, chunk_z) * chunk_lod.multiplier_i32();
+            let chunk_min = chunk_position * chunk_size;
+            let country_cache = chunk_country_cache(
+                generation_options,
+                &mut country_caches,
+                chunk_min,
+                chunk_min + lod_chunk_size,
+            );
+            let offset = Vec3::new(chunk_min.x as f32, 0., chunk_min.y as f32) * VOXEL_SIZE;
+
+            let mut chunk_height = 0;
+            loop {
+                let voxels = generate_voxels(
+                    [chunk_position.x, chunk_height, chunk_position.y],
+                    generation_options,
+                    chunk_lod,
+                    &country_cache,
+                );
+                let (chunk_meshes, generate_more) = generate_mesh(voxels, chunk_lod);
+
+                if let Some((chunk_meshes, collider_positions, collider_triangles)) = chunk_meshes {
+                    // Emissive meshes of every color share the unlit material, which shows their
+                    // vertex colors.
+                    let material_meshes = chunk_meshes
+                        .opaque
+                        .iter()
+                        .map(|mesh| (0, mesh))
+                        .chain(chunk_meshes.transparent.iter().map(|mesh| (1, mesh)))
+                        .chain(chunk_meshes.emissive.iter().map(|(_, mesh)| (2, mesh)));
+                    for (material, mesh) in material_meshes {
+                        meshes[material].append(mesh, offset);
+                    }
+
+                    if with_collider {
+                        let collider = &mut meshes[3];
+                        let first_index = collider.positions.len() as u32;
+                        collider.positions.extend(
+                            collider_positions
+                                .iter()
+                                .map(|position| (*position + offset).to_array()),
+                        );
+                        collider.indices.extend(
+                            collider_triangles
+                                .iter()
+                                .flatten()
+                                .map(|index| index + first_index),
+                        );
+                    }
+                }
+
+                if !generate_more {
+                    break;
+                }
+                chunk_height += 1;
+            }
+        }
+    }
+
+    meshes
+        .into_iter()
+        .filter(|mesh| !mesh.indices.is_empty())
+        .collect()
+}
+
+pub fn export_region_mesh(
+    generation_options: &GenerationOptions,
+    min: IVec2,
+    max: IVec2,
+    chunk_lod: ChunkLod,
+    with_collider: bool,
+    format: MeshFormat,
+    path: impl AsRef<Path>,
+) -> io::Result<()> {
+    let meshes = generate_region_meshes(generation_options, min, max, chunk_lod, with_collider);
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    match format {
+        MeshFormat::Obj => write_obj(&meshes, &mut writer)?,
+        MeshFormat::Glb => writer.write_all(&glb_bytes(&meshes))?,
+    }
+
+    writer.flush()
+}
+
+/// Vertex colors are written after the positions, which most tools read. Transparency doesn't
+/// survive, every mesh becomes its own object.
+pub fn write_obj(meshes: &[ExportMesh], writer: &mut impl Write) -> io::Result<()> {
+    let mut first_vertex = 1;
+
+    for mesh in meshes {
+        writeln!(writer, "o {}", mesh.name())?;
+
+        for (index, [x, y, z]) in mesh.positions.iter().enumerate() {
+            match mesh.colors.get(index) {
+                Some([r, g, b, _]) => writeln!(writer, "v {} {} {} {} {} {}", x, y, z, r, g, b)?,
+                None => writeln!(writer, "v {} {} {}", x, y, z)?,
+            }
+        }
+        for [x, y, z] in &mesh.normals {
+            writeln!(writer, "vn {} {} {}", x, y, z)?;
+        }
+
+        let with_normals = mesh.normals.len() == mesh.positions.len();
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|corner| triangle[corner] as usize + first_vertex);
+            if with_normals {
+                writeln!(writer, "f {a}//{a} {b}//{b} {c}//{c}")?;
+            } else {
+                writeln!(writer, "f {a} {b} {c}")?;
+            }
+        }
+
+        first_vertex += mesh.positions.len();
+    }
+
+    Ok(())
+}
+
+/// Binary glTF with one node per mesh. Emissive meshes are unlit, transparent ones blended.
+pub fn glb_bytes(meshes: &[ExportMesh]) -> Vec<u8> {
+    let mut buffer = vec![];
+    let mut buffer_views = vec![];
+    let mut accessors = vec![];
+    let mut gltf_meshes = vec![];
+    let mut nodes = vec![];
+
+    // Positions need the bounds of the values, the other accessors leave them out.
+    let mut add_accessor =
+        |data: Vec<u8>, count: usize, kind: &str, component: u32, bounds: Option<(Vec3, Vec3)>| {
+            buffer_views.push(json!({
+                "buffer": 0,
+                "byteOffset": buffer.len(),
+                "byteLength": data.len(),
+            }));
+            buffer.extend(data);
+            accessors.push(json!({
+                "bufferView": buffer_views.len() - 1,
+                "componentType": component,
+                "count": count,
+                "type": kind,
+            }));
+            if let Some((min, max)) = bounds {
+                let accessor = accessors.last_mut().unwrap();
+                accessor["min"] = json!(min.to_array());
+                accessor["max"] = json!(max.to_array());
+            }
+            accessors.len() - 1
+        };
+
+    for mesh in meshes {
+        let (min, max) = mesh.positions.iter().fold(
+            (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+            |(min, max), position| {
+                let position = Vec3::from_array(*position);
+                (min.min(position), max.max(position))
+            },
+        );
+
+        let positions = add_accessor(
+            floats(mesh.positions.iter().flatten()),
+            mesh.positions.len(),
+            "VEC3",
+            FLOAT,
+            Some((min, max)),
+        );
+
+        let mut attributes = json!({ "POSITION": positions });
+        if mesh.normals.len() == mesh.positions.len() {
+            attributes["NORMAL"] = json!(add_accessor(
+                floats(mesh.normals.iter().flatten()),
+                mesh.normals.len(),
+                "VEC3",
+                FLOAT,
+                None,
+            ));
+        }
+        if mesh.colors.len() == mesh.positions.len() {
+            attributes["COLOR_0"] = json!(add_accessor(
+                floats(mesh.colors.iter().flatten()),
+                mesh.colors.len(),
+                "VEC4",
+                FLOAT,
+                None,
+            ));
+        }
+        let indices = add_accessor(
+            mesh.indices
+                .iter()
+                .flat_map(|index| index.to_le_bytes())
+                .collect(),
+            mesh.indices.len(),
+            "SCALAR",
+            UNSIGNED_INT,
+            None,
+        );
+
+        gltf_meshes.push(json!({
+            "name": mesh.name(),
+            "primitives": [{
+                "attributes": attributes,
+                "indices": indices,
+                "material": mesh.material as usize,
+            }],
+        }));
+        nodes.push(json!({
+            "name": mesh.name(),
+            "mesh": gltf_meshes.len() - 1,
+        }));
+    }
+
+    let gltf = json!({
+        "asset": { "version": "2.0", "generator": "spellhaven" },
+        "extensionsUsed": ["KHR_materials_unlit"],
+        "scene": 0,
+        "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<_>>() }],
+        "nodes": nodes,
+        "meshes": gltf_meshes,
+        "materials": [
+            { "name": "terrain", "pbrMetallicRoughness": { "metallicFactor": 0 } },
+            {
+                "name": "transparent",
+                "alphaMode": "BLEND",
+                "pbrMetallicRoughness": { "metallicFactor": 0 },
+            },
+            { "name": "emissive", "extensions": { "KHR_materials_unlit": {} } },
+            { "name": "collider", "pbrMetallicRoughness": { "metallicFactor": 0 } },
+        ],
+        "accessors": accessors,
+        "bufferViews": buffer_views,
+        "buffers": [{ "byteLength": buffer.len() }],
+    });
+    let mut json = gltf.to_string().into_bytes();
+
+    // Chunks have to be 4 byte aligned, JSON is padded with spaces.
+    json.resize(json.len().next_multiple_of(4), b' ');
+    buffer.resize(buffer.len().next_multiple_of(4), 0);
+
+    let mut glb = vec![];
+    glb.extend(b"glTF");
+    glb.extend(2u32.to_le_bytes());
+    glb.extend(((12 + 8 + json.len() + 8 + buffer.len()) as u32).to_le_bytes());
+    glb.extend((json.len() as u32).to_le_bytes());
+    glb.extend(b"JSON");
+    glb.extend(json);
+    glb.extend((buffer.len() as u32).to_le_bytes());
+    glb.extend(b"BIN\0");
+    glb.extend(buffer);
+    glb
+}
+
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+
+fn floats<'a>(values: impl Iterator<Item = &'a f32>) -> Vec<u8> {
+    values.flat_map(|value| value.to_le_bytes()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle(material: ExportMaterial) -> ExportMesh {
+        ExportMesh {
+            material,
+            positions: vec![[0., 0., 0.], [1., 0., 0.], [0., 0., 1.]],
+            normals: vec![[0., 1., 0.]; 3],
+            colors: vec![[1., 0.5, 0., 1.]; 3],
+            indices: vec![0, 2, 1],
+        }
+    }
+
+    #[test]
+    fn obj_indices_continue_across_objects() {
+        let mut collider = triangle(ExportMaterial::Collider);
+        collider.normals.clear();
+        collider.colors.clear();
+
+        let mut obj = vec![];
+        write_obj(&[triangle(ExportMaterial::Opaque), collider], &mut obj).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        let lines = obj.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines[0], "o terrain");
+        assert_eq!(lines[1], "v 0 0 0 1 0.5 0");
+        assert!(lines.contains(&"f 1//1 3//3 2//2"));
+        assert!(lines.contains(&"o collider"));
+        assert!(lines.contains(&"v 1 0 0"));
+        assert_eq!(lines.last(), Some(&"f 4 6 5"));
+    }
+
+    #[test]
+    fn glb_chunks_are_aligned_and_sized() {
+        let glb = glb_bytes(&[
+            triangle(ExportMaterial::Opaque),
+            triangle(ExportMaterial::Emissive),
+        ]);
+        let read_u32 = |offset: usize| {
+            u32::from_le_bytes(glb[offset..offset + 4].try_into().unwrap()) as usize
+        };
+
+        assert_eq!(&glb[0..4], b"glTF");
+        assert_eq!(read_u32(8), glb.len());
+
+        let json_length = read_u32(12);
+        assert_eq!(&glb[16..20], b"JSON");
+        assert_eq!(json_length % 4, 0);
+        let json: serde_json::Value = serde_json::from_slice(&glb[20..20 + json_length]).unwrap();
+        assert_eq!(json["nodes"][1]["name"], "emissive");
+        assert_eq!(json["nodes"][1]["mesh"], 1);
+        assert_eq!(json["meshes"][1]["primitives"][0]["material"], 2);
+        assert_eq!(json["accessors"][0]["max"], serde_json::json!([1., 0., 1.]));
+
+        let bin_start = 20 + json_length;
+        assert_eq!(&glb[bin_start + 4..bin_start + 8], b"BIN\0");
+        // Positions, normals, colors and indices of both triangles.
+        assert_eq!(read_u32(bin_start), 2 * (36 + 36 + 48 + 12));
+    }
+
+    #[test]
+    fn colors_are_clamped() {
+        let mut mesh = Mesh::new(
+            bevy::render::mesh::PrimitiveTopology::TriangleList,
+            bevy::render::render_asset::RenderAssetUsages::all(),
+        );
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0f32; 3]; 2]);
+        mesh.insert_attribute(
+            Mesh::ATTRIBUTE_COLOR,
+            vec![[5f32, 5., 5., 1.], [0.5, -0.1, 1.2, 1.]],
+        );
+
+        let mut export_mesh = ExportMesh::new(ExportMaterial::Opaque);
+        export_mesh.append(&mesh, Vec3::ONE);
+
+        assert_eq!(export_mesh.positions, vec![[1.; 3]; 2]);
+        assert_eq!(
+            export_mesh.colors,
+            vec![[1., 1., 1., 1.], [0.5, 0., 1., 1.]]
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use crate::utils::hash_position;
+use crate::world_generation::chunk_loading::country_layout::Culture;
+use bevy::math::IVec2;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NameKind {
+    Country,
+    City,
+    River,
+    Peak,
+}
+
+impl NameKind {
+    fn salt(self) -> u64 {
+        match self {
+            NameKind::Country => 11,
+            NameKind::City => 12,
+            NameKind::River => 13,
+            NameKind::Peak => 14,
+        }
+    }
+}
+
+/// Sounds a culture builds its names from.
+struct Phonology {
+    onsets: &'static [&'static str],
+    vowels: &'static [&'static str],
+    codas: &'static [&'static str],
+    country_suffixes: &'static [&'static str],
+    city_suffixes: &'static [&'static str],
+    river_suffixes: &'static [&'static str],
+    peak_suffixes: &'static [&'static str],
+}
+
+const ALPINE: Phonology = Phonology {
+    onsets: &[
+        "b", "br", "d", "f", "g", "h", "k", "kr", "l", "m", "n", "r", "sch", "st", "w", "z",
+    ],
+    vowels: &["a", "e", "i", "o", "u", "au", "ei", "ie"],
+    codas: &[
+        "", "", "n", "r", "rn", "lt", "ld", "k", "ch", "st", "g", "m",
+    ],
+    country_suffixes: &["land", "mark", "ria", "tal"],
+    city_suffixes: &["berg", "heim", "stadt", "dorf", "burg", "au"],
+    river_suffixes: &["ach", "bach", "au", "inn"],
+    peak_suffixes: &["horn", "spitz", "stock", "kogel"],
+};
+
+const STEPPE: Phonology = Phonology {
+    onsets: &[
+        "b", "ch", "d", "j", "k", "m", "n", "q", "s", "sh", "t", "y", "z", "zh", "kh", "or",
+    ],
+    vowels: &["a", "o", "u", "e", "i", "ai", "uu", "ya"],
+    codas: &["", "", "n", "r", "k", "t", "l", "sh", "z", "ng", "m", "q"],
+    country_suffixes: &["istan", "ar", "eli", "yurt"],
+    city_suffixes: &["kent", "abad", "tash", "balyk", "kul", "orda"],
+    river_suffixes: &["su", "daria", "gol", "irmak"],
+    peak_suffixes: &["tau", "dag", "uul", "tepe"],
+};
+
+const FOREST: Phonology = Phonology {
+    onsets: &[
+        "c", "d", "el", "f", "gl", "gw", "l", "m", "n", "r", "s", "th", "br", "v", "ae", "tr",
+    ],
+    vowels: &["a", "e", "i", "o", "ae", "ia", "ei", "y"],
+    codas: &[
+        "", "", "n", "th", "l", "r", "ll", "s", "dh", "nd", "w", "rn",
+    ],
+    country_suffixes: &["wen", "mar", "dor", "nor"],
+    city_suffixes: &["wyn", "dell", "mere", "ford", "holt", "glen"],
+    river_suffixes: &["afon", "duin", "wy", "rith"],
+    peak_suffixes: &["bryn", "carn", "moel", "pen"],
+};
+
+const MARSH: Phonology = Phonology {
+    onsets: &[
+        "b", "dr", "kr", "m", "p", "r", "s", "sv", "t", "v", "z", "zl", "br", "g", "l", "ch",
+    ],
+    vowels: &["a", "o", "e", "i", "u", "y", "ia", "ie"],
+    codas: &["", "", "v", "k", "sk", "n", "l", "ch", "r", "sl", "d", "m"],
+    country_suffixes: &["ovia", "ska", "ania", "enia"],
+    city_suffixes: &["grad", "ov", "ice", "mir", "ava", "insk"],
+    river_suffixes: &["ava", "ina", "isla", "ets"],
+    peak_suffixes: &["gora", "vrh", "kamen", "tur"],
+};
+
+impl Culture {
+    fn phonology(self) -> &'static Phonology {
+        match self {
+            Culture::Alpine => &ALPINE,
+            Culture::Steppe => &STEPPE,
+            Culture::Forest => &FOREST,
+            Culture::Marsh => &MARSH,
+        }
+    }
+}
+
+/// Gives every named thing a stable name derived from the seed, its kind and its position.
+/// Countries are keyed by their grid cell, everything else by its voxel position.
+pub struct NameGenerator {
+    seed: u64,
+}
+
+impl NameGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    pub fn name(&self, kind: NameKind, culture: Culture, key: IVec2) -> String {
+        self.alternative_name(kind, culture, key, 0)
+    }
+
+    /// Another name for the same thing, for when the first ones are taken. Attempt 0 is
+    /// [`NameGenerator::name`].
+    pub fn alternative_name(
+        &self,
+        kind: NameKind,
+        culture: Culture,
+        key: IVec2,
+        attempt: u64,
+    ) -> String {
+        let phonology = culture.phonology();
+        let mut hash = NameHash(hash_position(self.seed, key, kind.salt() + attempt * 16));
+
+        // Countries and cities always get two syllables, so names stay unique over large regions.
+        let syllable_count = match kind {
+            NameKind::Country | NameKind::City => 2,
+            NameKind::River | NameKind::Peak => 1 + hash.next(2),
+        };
+
+        let mut name = String::new();
+        for _ in 0..syllable_count {
+            name.push_str(hash.pick(phonology.onsets));
+            name.push_str(hash.pick(phonology.vowels));
+            name.push_str(hash.pick(phonology.codas));
+        }
+
+        name.push_str(hash.pick(match kind {
+            NameKind::Country => phonology.country_suffixes,
+            NameKind::City => phonology.city_suffixes,
+            NameKind::River => phonology.river_suffixes,
+            NameKind::Peak => phonology.peak_suffixes,
+        }));
+
+        capitalize(&name)
+    }
+}
+
+struct NameHash(u64);
+
+impl NameHash {
+    /// Draws a number below `bound`, rehashing once the bits of the current hash run low.
+    fn next(&mut self, bound: usize) -> usize {
+        let value = (self.0 % bound as u64) as usize;
+        self.0 /= bound as u64;
+        if self.0 < u16::MAX as u64 {
+            self.0 = hash_position(self.0 ^ value as u64, IVec2::ZERO, 0);
+        }
+        value
+    }
+
+    fn pick(&mut self, options: &'static [&'static str]) -> &'static str {
+        options[self.next(options.len())]
+    }
+}
+
+fn capitalize(name: &str) -> String {
+    let mut characters = name.chars();
+    match characters.next() {
+        Some(first) => first.to_uppercase().chain(characters).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_generation::chunk_loading::country_layout::CountryLayout;
+    use std::collections::HashSet;
+
+    const CULTURES: [Culture; 4] = [
+        Culture::Alpine,
+        Culture::Steppe,
+        Culture::Forest,
+        Culture::Marsh,
+    ];
+
+    fn region() -> impl Iterator<Item = IVec2> {
+        (-8..8).flat_map(|x| (-8..8).map(move |y| IVec2::new(x, y)))
+    }
+
+    #[test]
+    fn same_seed_gives_same_names() {
+        let first = NameGenerator::new(42);
+        let second = NameGenerator::new(42);
+
+        for key in region() {
+            for culture in CULTURES {
+                for kind in [
+                    NameKind::Country,
+                    NameKind::City,
+                    NameKind::River,
+                    NameKind::Peak,
+                ] {
+                    assert_eq!(
+                        first.name(kind, culture, key),
+                        second.name(kind, culture, key)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn different_seeds_give_different_names() {
+        let first = NameGenerator::new(1);
+        let second = NameGenerator::new(2);
+
+        let differing = region()
+            .filter(|key| {
+                first.name(NameKind::Country, Culture::Alpine, *key)
+                    != second.name(NameKind::Country, Culture::Alpine, *key)
+            })
+            .count();
+
+        assert!(differing > region().count() * 9 / 10);
+    }
+
+    #[test]
+    fn names_are_capitalized_and_not_empty() {
+        let names = NameGenerator::new(7);
+
+        for key in region() {
+            for culture in CULTURES {
+                let name = names.name(NameKind::City, culture, key);
+                assert!(name.len() >= 3);
+                assert!(name.chars().next().unwrap().is_uppercase());
+            }
+        }
+    }
+
+    #[test]
+    fn countries_and_cities_are_unique_within_a_region() {
+        for seed in 0..4 {
+            let layout = CountryLayout::new(seed);
+
+            let country_names = region()
+                .map(|country| layout.country_name(country))
+                .collect::<HashSet<_>>();
+            let city_names = region()
+                .map(|country| layout.city_name(country))
+                .collect::<HashSet<_>>();
+
+            assert_eq!(country_names.len(), region().count());
+            assert_eq!(city_names.len(), region().count());
+        }
+    }
+}
@@ -0,0 +1,653 @@
+use crate::player::Player;
+use crate::world_generation::chunk_generation::voxel_generation::get_terrain_noise;
+use crate::world_generation::chunk_loading::country_cache::{CountryCache, Path};
+use crate::world_generation::floating_origin::{FloatingOrigin, WorldPosition};
+use crate::world_generation::generation_options::{GenerationOptionsResource, GenerationState};
+use crate::world_generation::voxel_world::ChunkLod;
+use bevy::prelude::*;
+use noise::NoiseFn;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Positions further away from a road than this many voxels can't be snapped to it.
+pub const SNAP_RANGE: f32 = 4096.;
+/// Path ends closer than this are treated as the same junction, roads of different countries
+/// don't end on exactly the same voxel.
+const JUNCTION_RADIUS: i32 = 128;
+/// The route is computed again once the player moved this many voxels.
+const REROUTE_DISTANCE: f32 = 64.;
+const ROUTE_GIZMO_RANGE: f32 = 2048.;
+
+pub struct NavigationPlugin;
+
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Navigation>()
+            .add_systems(Update, (update_route, draw_route_gizmos).chain());
+    }
+}
+
+/// Route of the player to the capital of `target`.
+#[derive(Resource, Default)]
+pub struct Navigation {
+    pub target: Option<IVec2>,
+    pub route: Option<Route>,
+    /// Player position and number of generated countries the route was computed with.
+    computed_with: Option<(Vec2, usize)>,
+}
+
+impl Navigation {
+    pub fn set_target(&mut self, target: Option<IVec2>) {
+        self.target = target;
+        self.route = None;
+        self.computed_with = None;
+    }
+}
+
+pub struct Route {
+    /// Voxel positions from the start to the target.
+    pub points: Vec<Vec2>,
+    /// Length of `points` in voxels.
+    pub distance: f32,
+}
+
+impl Route {
+    fn new(points: Vec<Vec2>) -> Self {
+        Self {
+            distance: polyline_length(&points),
+            points,
+        }
+    }
+
+    /// Point `lookahead` voxels further along the route than the point closest to `position`,
+    /// together with the distance left from there.
+    pub fn waypoint(&self, position: Vec2, lookahead: f32) -> Option<(Vec2, f32)> {
+        let (segment, closest) = closest_segment(&self.points, position)?;
+
+        let mut remaining = lookahead;
+        let mut waypoint = closest;
+        for &point in &self.points[segment + 1..] {
+            let length = waypoint.distance(point);
+            if length >= remaining {
+                waypoint += (point - waypoint).normalize_or_zero() * remaining;
+                break;
+            }
+            remaining -= length;
+            waypoint = point;
+        }
+
+        let left = closest.distance(self.points[segment + 1])
+            + polyline_length(&self.points[segment + 1..]);
+        Some((waypoint, left))
+    }
+}
+
+struct RoadEdge {
+    from: usize,
+    to: usize,
+    points: Vec<Vec2>,
+    /// Distance from the first point to every point.
+    distances: Vec<f32>,
+    /// Graded road height at every point.
+    heights: Vec<f32>,
+    box_min: Vec2,
+    box_max: Vec2,
+}
+
+impl RoadEdge {
+    fn length(&self) -> f32 {
+        *self.distances.last().unwrap()
+    }
+
+    /// Position, direction and height `distance` voxels along the edge, from its end instead of
+    /// its start if `from_end` is set.
+    fn at(&self, distance: f32, from_end: bool) -> (Vec2, Vec2, f32) {
+        let distance = if from_end {
+            self.length() - distance
+        } else {
+            distance
+        };
+        let index = self
+            .distances
+            .partition_point(|&point_distance| point_distance < distance)
+            .clamp(1, self.points.len() - 1);
+
+        let segment_length = self.distances[index] - self.distances[index - 1];
+        let t = if segment_length > 0. {
+            ((distance - self.distances[index - 1]) / segment_length).clamp(0., 1.)
+        } else {
+            0.
+        };
+        let direction = (self.points[index] - self.points[index - 1]).normalize_or_zero();
+        let height_start = self.heights.get(index - 1).copied().unwrap_or_default();
+        let height_end = self.heights.get(index).copied().unwrap_or_default();
+
+        (
+            self.points[index - 1].lerp(self.points[index], t),
+            if from_end { -direction } else { direction },
+            height_start + (height_end - height_start) * t,
+        )
+    }
+
+    fn distance_at(&self, snap: &RoadSnap) -> f32 {
+        self.distances[snap.segment] + self.points[snap.segment].distance(snap.point)
+    }
+
+    /// Points from `snap` to the start or the end of the edge.
+    fn points_from(&self, snap: &RoadSnap, towards_start: bool) -> Vec<Vec2> {
+        let mut points = vec![snap.point];
+        if towards_start {
+            points.extend(self.points[..=snap.segment].iter().rev());
+        } else {
+            points.extend(&self.points[snap.segment + 1..]);
+        }
+        points
+    }
+}
+
+/// Closest point on a road.
+pub struct RoadSnap {
+    pub point: Vec2,
+    pub edge: usize,
+    segment: usize,
+}
+
+/// Road leaving a junction of a [`RoadGraph`].
+pub struct JunctionExit {
+    pub junction: IVec2,
+    /// Point some distance along the road, with the direction away from the junction there.
+    pub position: Vec2,
+    pub direction: Vec2,
+    pub height: f32,
+    /// The next junction along the road.
+    pub leads_to: IVec2,
+}
+
+/// Roads of a set of countries as a graph. Every [`PathLine`] is an edge, the ends of the lines
+/// are the nodes. Nodes where paths end are junctions.
+///
+/// [`PathLine`]: crate::world_generation::chunk_loading::country_cache::PathLine
+#[derive(Default)]
+pub struct RoadGraph {
+    nodes: Vec<IVec2>,
+    node_lookup: HashMap<IVec2, usize>,
+    edges: Vec<RoadEdge>,
+    node_edges: Vec<Vec<usize>>,
+    junctions: Vec<bool>,
+}
+
+impl RoadGraph {
+    pub fn new<'a>(country_caches: impl IntoIterator<Item = &'a CountryCache>) -> Self {
+        let country_caches = country_caches.into_iter().collect::<Vec<_>>();
+        let Some((first, rest)) = country_caches.split_first() else {
+            return Self::default();
+        };
+
+        Self::from_paths(first.merged(rest.iter().copied()).paths())
+    }
+
+    pub fn from_paths<'a>(paths: impl IntoIterator<Item = &'a Path>) -> Self {
+        let mut graph = Self::default();
+
+        for path in paths {
+            let last_line = path.lines.len().saturating_sub(1);
+            for (index, line) in path.lines.iter().enumerate() {
+                let from = graph.node(line.start, index == 0);
+                let to = graph.node(line.end, index == last_line);
+
+                let points = line
+                    .sample_points
+                    .iter()
+                    .map(|point| point.as_vec2())
+                    .collect::<Vec<_>>();
+                let mut distances = vec![0.];
+                for segment in points.windows(2) {
+                    distances.push(distances.last().unwrap() + segment[0].distance(segment[1]));
+                }
+
+                graph.node_edges[from].push(graph.edges.len());
+                graph.node_edges[to].push(graph.edges.len());
+                graph.edges.push(RoadEdge {
+                    from,
+                    to,
+                    box_min: points.iter().copied().fold(Vec2::MAX, Vec2::min),
+                    box_max: points.iter().copied().fold(Vec2::MIN, Vec2::max),
+                    points,
+                    distances,
+                    heights: line.heights.clone(),
+                });
+            }
+        }
+
+        graph
+    }
+
+    fn node(&mut self, position: IVec2, is_path_end: bool) -> usize {
+        if let Some(node) = self.node_lookup.get(&position).copied() {
+            self.junctions[node] |= is_path_end;
+            return node;
+        }
+
+        if is_path_end {
+            if let Some(node) = self
+                .nodes
+                .iter()
+                .position(|node| (*node - position).abs().max_element() <= JUNCTION_RADIUS)
+            {
+                self.node_lookup.insert(position, node);
+                self.junctions[node] = true;
+                return node;
+            }
+        }
+
+        self.nodes.push(position);
+        self.node_edges.push(vec![]);
+        self.junctions.push(is_path_end);
+        self.node_lookup.insert(position, self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+
+    /// Every road leaving a junction, with the point `distance` voxels along it.
+    pub fn junction_exits(&self, distance: f32) -> Vec<JunctionExit> {
+        let mut exits = vec![];
+
+        for (junction, edges) in self.node_edges.iter().enumerate() {
+            if !self.junctions[junction] {
+                continue;
+            }
+
+            for &first_edge in edges {
+                let from_end = self.edges[first_edge].from != junction;
+                let (position, direction, height) = self.edges[first_edge].at(distance, from_end);
+
+                // Follow the road until the next junction, every other node has two edges.
+                let mut node = junction;
+                let mut edge = first_edge;
+                for _ in 0..self.edges.len() {
+                    node = self.other_node(edge, node);
+                    if self.junctions[node] {
+                        break;
+                    }
+                    match self.node_edges[node].iter().find(|other| **other != edge) {
+                        Some(next) => edge = *next,
+                        None => break,
+                    }
+                }
+
+                exits.push(JunctionExit {
+                    junction: self.nodes[junction],
+                    position,
+                    direction,
+                    height,
+                    leads_to: self.nodes[node],
+                });
+            }
+        }
+
+        exits
+    }
+
+    fn other_node(&self, edge: usize, node: usize) -> usize {
+        let edge = &self.edges[edge];
+        if edge.from == node {
+            edge.to
+        } else {
+            edge.from
+        }
+    }
+
+    /// Closest point on any road within `range` voxels of `position`.
+    pub fn snap(&self, position: Vec2, range: f32) -> Option<RoadSnap> {
+        let mut closest: Option<(f32, RoadSnap)> = None;
+
+        for (edge_index, edge) in self.edges.iter().enumerate() {
+            if position.cmplt(edge.box_min - range).any()
+                || position.cmpgt(edge.box_max + range).any()
+            {
+                continue;
+            }
+
+            let Some((segment, point)) = closest_segment(&edge.points, position) else {
+                continue;
+            };
+            let distance = point.distance(position);
+            if distance <= range
+                && closest
+                    .as_ref()
+                    .map(|(min, _)| distance < *min)
+                    .unwrap_or(true)
+            {
+                closest = Some((
+                    distance,
+                    RoadSnap {
+                        point,
+                        edge: edge_index,
+                        segment,
+                    },
+                ));
+            }
+        }
+
+        closest.map(|(_, snap)| snap)
+    }
+
+    /// Shortest route over the roads from `from` to `to`, both get snapped to the closest road.
+    pub fn route(&self, from: Vec2, to: Vec2) -> Option<Route> {
+        let start = self.snap(from, SNAP_RANGE)?;
+        let target = self.snap(to, SNAP_RANGE)?;
+        let start_edge = &self.edges[start.edge];
+        let target_edge = &self.edges[target.edge];
+
+        let mut points = vec![from];
+
+        if start.edge == target.edge {
+            points.push(start.point);
+            if start_edge.distance_at(&target) >= start_edge.distance_at(&start) {
+                points.extend(&start_edge.points[start.segment + 1..=target.segment]);
+            } else {
+                points.extend(
+                    start_edge.points[target.segment + 1..=start.segment]
+                        .iter()
+                        .rev(),
+                );
+            }
+        } else {
+            let start_distance = start_edge.distance_at(&start);
+            let (distances, previous) = self.shortest_paths([
+                (start_edge.from, start_distance),
+                (start_edge.to, start_edge.length() - start_distance),
+            ]);
+
+            let target_distance = target_edge.distance_at(&target);
+            let via_from = distances[target_edge.from] + target_distance;
+            let via_to = distances[target_edge.to] + target_edge.length() - target_distance;
+            let (last_node, target_from_start) = if via_from <= via_to {
+                (target_edge.from, true)
+            } else {
+                (target_edge.to, false)
+            };
+            if !via_from.min(via_to).is_finite() {
+                return None;
+            }
+
+            let mut nodes = vec![last_node];
+            let mut edges = vec![];
+            while let Some(edge) = previous[*nodes.last().unwrap()] {
+                let edge_data = &self.edges[edge];
+                let node = *nodes.last().unwrap();
+                nodes.push(if edge_data.from == node {
+                    edge_data.to
+                } else {
+                    edge_data.from
+                });
+                edges.push(edge);
+            }
+            let first_node = *nodes.last().unwrap();
+
+            points.extend(start_edge.points_from(&start, first_node == start_edge.from));
+            for (&edge, &node) in edges.iter().rev().zip(nodes.iter().rev()) {
+                let edge = &self.edges[edge];
+                if edge.from == node {
+                    points.extend(&edge.points[1..]);
+                } else {
+                    points.extend(edge.points.iter().rev().skip(1));
+                }
+            }
+            points.extend(
+                target_edge
+                    .points_from(&target, target_from_start)
+                    .into_iter()
+                    .rev()
+                    .skip(1),
+            );
+        }
+
+        points.push(target.point);
+        points.push(to);
+        points.dedup();
+
+        Some(Route::new(points))
+    }
+
+    /// Dijkstra from the given nodes and their initial distances. Returns the distance to every
+    /// node and the edge it was reached through.
+    fn shortest_paths(
+        &self,
+        starts: impl IntoIterator<Item = (usize, f32)>,
+    ) -> (Vec<f32>, Vec<Option<usize>>) {
+        let mut distances = vec![f32::INFINITY; self.nodes.len()];
+        let mut previous = vec![None; self.nodes.len()];
+        let mut queue = BinaryHeap::new();
+
+        for (node, distance) in starts {
+            if distance < distances[node] {
+                distances[node] = distance;
+                queue.push(RouteCandidate { distance, node });
+            }
+        }
+
+        while let Some(RouteCandidate { distance, node }) = queue.pop() {
+            if distance > distances[node] {
+                continue;
+            }
+
+            for &edge in &self.node_edges[node] {
+                let edge_data = &self.edges[edge];
+                let next = if edge_data.from == node {
+                    edge_data.to
+                } else {
+                    edge_data.from
+                };
+                let next_distance = distance + edge_data.length();
+                if next_distance < distances[next] {
+                    distances[next] = next_distance;
+                    previous[next] = Some(edge);
+                    queue.push(RouteCandidate {
+                        distance: next_distance,
+                        node: next,
+                    });
+                }
+            }
+        }
+
+        (distances, previous)
+    }
+}
+
+struct RouteCandidate {
+    distance: f32,
+    node: usize,
+}
+
+impl PartialEq for RouteCandidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.distance == other.distance
+    }
+}
+
+impl Eq for RouteCandidate {}
+
+impl PartialOrd for RouteCandidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RouteCandidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.distance.total_cmp(&self.distance)
+    }
+}
+
+/// Index of the segment of `points` closest to `position` and the closest point on it.
+fn closest_segment(points: &[Vec2], position: Vec2) -> Option<(usize, Vec2)> {
+    points
+        .windows(2)
+        .enumerate()
+        .map(|(index, segment)| {
+            let direction = segment[1] - segment[0];
+            let t =
+                ((position - segment[0]).dot(direction) / direction.length_squared()).clamp(0., 1.);
+            let point = if t.is_finite() {
+                segment[0] + direction * t
+            } else {
+                segment[0]
+            };
+            (index, point)
+        })
+        .min_by(|(_, a), (_, b)| {
+            a.distance_squared(position)
+                .total_cmp(&b.distance_squared(position))
+        })
+}
+
+fn polyline_length(points: &[Vec2]) -> f32 {
+    points
+        .windows(2)
+        .map(|segment| segment[0].distance(segment[1]))
+        .sum()
+}
+
+fn update_route(
+    mut navigation: ResMut<Navigation>,
+    generation_options: Res<GenerationOptionsResource>,
+    players: Query<&WorldPosition, With<Player>>,
+) {
+    let (Some(target), Ok(player)) = (navigation.target, players.get_single()) else {
+        return;
+    };
+
+    let position = player.voxel_xz().as_vec2();
+    let country_caches = generation_options
+        .1
+        .values()
+        .filter_map(|state| match state {
+            GenerationState::Some(country_cache) => Some(country_cache),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let up_to_date = navigation
+        .computed_with
+        .is_some_and(|(computed_at, count)| {
+            computed_at.distance(position) < REROUTE_DISTANCE && count == country_caches.len()
+        });
+    if up_to_date {
+        return;
+    }
+
+    let target_city = country_caches
+        .iter()
+        .find(|country_cache| country_cache.country_pos == target)
+        .map(|country_cache| country_cache.structure_cache.city_location)
+        .unwrap_or_else(|| generation_options.0.country_layout.capital(target));
+
+    navigation.route =
+        RoadGraph::new(country_caches.iter().copied()).route(position, target_city.as_vec2());
+    navigation.computed_with = Some((position, country_caches.len()));
+}
+
+fn draw_route_gizmos(
+    mut gizmos: Gizmos,
+    navigation: Res<Navigation>,
+    generation_options: Res<GenerationOptionsResource>,
+    players: Query<&WorldPosition, With<Player>>,
+    floating_origin: Res<FloatingOrigin>,
+) {
+    let (Some(route), Ok(player)) = (&navigation.route, players.get_single()) else {
+        return;
+    };
+
+    let position = player.voxel_xz().as_vec2();
+    let terrain_noise = get_terrain_noise(ChunkLod::Full, &generation_options.0);
+
+    gizmos.linestrip(
+        route
+            .points
+            .iter()
+            .skip_while(|point| point.distance(position) > ROUTE_GIZMO_RANGE)
+            .take_while(|point| point.distance(position) <= ROUTE_GIZMO_RANGE)
+            .map(|point| {
+                let height = terrain_noise.get(point.as_dvec2().to_array()) as f32 + 4.;
+                floating_origin.voxel_to_render(Vec3::new(point.x, height, point.y))
+            }),
+        Color::CYAN,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_generation::chunk_loading::country_cache::PathLine;
+
+    fn path(sample_points: Vec<IVec2>) -> Path {
+        let box_pos_start = sample_points.iter().copied().reduce(IVec2::min).unwrap();
+        let box_pos_end = sample_points.iter().copied().reduce(IVec2::max).unwrap();
+
+        Path {
+            lines: vec![PathLine {
+                start: sample_points[0],
+                end: *sample_points.last().unwrap(),
+                spline_one: Vec2::ZERO,
+                spline_two: Vec2::ZERO,
+                box_pos_start,
+                box_pos_end,
+                estimated_length: 0.,
+                heights: vec![0.; sample_points.len()],
+                sample_points,
+            }],
+            box_pos_start,
+            box_pos_end,
+            props: vec![],
+        }
+    }
+
+    #[test]
+    fn route_takes_the_shortest_roads() {
+        let paths = [
+            path(vec![IVec2::new(-1000, 0), IVec2::new(0, 0)]),
+            path(vec![IVec2::new(0, 0), IVec2::new(1000, 0)]),
+            path(vec![IVec2::new(1000, 0), IVec2::new(2000, 0)]),
+            // A detour around the straight road.
+            path(vec![IVec2::new(0, 0), IVec2::new(1000, 2000)]),
+            path(vec![IVec2::new(1000, 2000), IVec2::new(2000, 0)]),
+            path(vec![IVec2::new(2000, 0), IVec2::new(3000, 0)]),
+        ];
+        let graph = RoadGraph::from_paths(&paths);
+
+        let route = graph
+            .route(Vec2::new(-500., 10.), Vec2::new(2500., -10.))
+            .unwrap();
+        assert_eq!(route.points.first(), Some(&Vec2::new(-500., 10.)));
+        assert_eq!(route.points.last(), Some(&Vec2::new(2500., -10.)));
+        assert!(route.points[1..route.points.len() - 1]
+            .iter()
+            .all(|point| point.y == 0.));
+        assert!((route.distance - 3020.).abs() < 0.01, "{}", route.distance);
+
+        // Without the straight road in the middle the detour is the only way.
+        let graph = RoadGraph::from_paths(paths.iter().filter(|path| {
+            path.lines[0].start != IVec2::new(1000, 0) && path.lines[0].end != IVec2::new(1000, 0)
+        }));
+        let route = graph
+            .route(Vec2::new(-500., 0.), Vec2::new(2500., 0.))
+            .unwrap();
+        assert!(route.points.contains(&Vec2::new(1000., 2000.)));
+    }
+
+    #[test]
+    fn no_route_between_unconnected_roads() {
+        let paths = [
+            path(vec![IVec2::new(0, 0), IVec2::new(1000, 0)]),
+            path(vec![IVec2::new(0, 3000), IVec2::new(1000, 3000)]),
+        ];
+        let graph = RoadGraph::from_paths(&paths);
+
+        assert!(graph
+            .route(Vec2::new(500., 0.), Vec2::new(500., 3000.))
+            .is_none());
+        assert!(graph
+            .route(Vec2::new(500., 0.), Vec2::new(500., 10_000.))
+            .is_none());
+    }
+}
@@ -0,0 +1,403 @@
+use crate::world_generation::chunk_generation::voxel_generation::{
+    generate_voxels, get_terrain_noise,
+};
+use crate::world_generation::chunk_generation::{BlockType, CHUNK_SIZE, ROAD_INFLUENCE_MARGIN};
+use crate::world_generation::chunk_loading::country_cache::CountryCache;
+use crate::world_generation::generation_options::GenerationOptions;
+use crate::world_generation::vox::{VoxError, VoxMaterial, VoxModel, VoxScene};
+use crate::world_generation::voxel_world::ChunkLod;
+use bevy::math::{IVec2, IVec3};
+use noise::NoiseFn;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+/// Largest model MagicaVoxel opens.
+const VOX_MODEL_SIZE: i32 = 256;
+/// Blocks kept below and above the terrain by [`surface_box`], enough for road cuts and
+/// structures.
+const SURFACE_MARGIN: [i32; 2] = [32, 160];
+/// Most blocks a [`RegionBlocks`] holds, a few gigabytes of memory.
+const MAX_REGION_BLOCKS: usize = 1 << 30;
+
+#[derive(Debug)]
+pub enum RegionError {
+    /// The box from `min` to `max` has more than [`MAX_REGION_BLOCKS`] blocks.
+    TooLarge {
+        min: IVec3,
+        max: IVec3,
+    },
+    Vox(VoxError),
+}
+
+impl fmt::Display for RegionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegionError::TooLarge { min, max } => {
+                write!(f, "region from {} to {} is too large", min, max)
+            }
+            RegionError::Vox(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for RegionError {}
+
+impl From<VoxError> for RegionError {
+    fn from(error: VoxError) -> Self {
+        RegionError::Vox(error)
+    }
+}
+
+/// Blocks of a box of the world at full resolution, generated the same way as the chunks.
+pub struct RegionBlocks {
+    pub min: IVec3,
+    pub size: IVec3,
+    blocks: Vec<BlockType>,
+}
+
+impl RegionBlocks {
+    /// Air from `min` to `max`, inclusive on both ends.
+    pub fn new(min: IVec3, max: IVec3) -> Result<Self, RegionError> {
+        let too_large = || RegionError::TooLarge { min, max };
+        let mut size = IVec3::ZERO;
+        let mut block_count = 1usize;
+        for axis in 0..3 {
+            let length = i32::try_from((max[axis] as i64 - min[axis] as i64 + 1).max(0))
+                .map_err(|_| too_large())?;
+            size[axis] = length;
+            block_count = block_count
+                .checked_mul(length as usize)
+                .ok_or_else(too_large)?;
+        }
+        if block_count > MAX_REGION_BLOCKS {
+            return Err(too_large());
+        }
+
+        Ok(Self {
+            min,
+            size,
+            blocks: vec![BlockType::Air; block_count],
+        })
+    }
+
+    pub fn get(&self, position: IVec3) -> BlockType {
+        self.index(position)
+            .map_or(BlockType::Air, |index| self.blocks[index])
+    }
+
+    /// Blocks outside of the region are ignored.
+    pub fn set(&mut self, position: IVec3, block: BlockType) {
+        if let Some(index) = self.index(position) {
+            self.blocks[index] = block;
+        }
+    }
+
+    fn index(&self, position: IVec3) -> Option<usize> {
+        let local = position - self.min;
+        if local.cmplt(IVec3::ZERO).any() || local.cmpge(self.size).any() {
+            return None;
+        }
+
+        let [x, y, z] = local.to_array().map(|value| value as usize);
+        Some((x * self.size.y as usize + y) * self.size.z as usize + z)
+    }
+}
+
+/// Generates the terrain, roads and structures from `min` to `max`, inclusive on both ends. The
+/// needed countries are generated on the calling thread.
+///
+/// Player edits are out of scope: blocks can't be changed in game, so a region is exactly what
+/// the generator makes.
+pub fn generate_region(
+    generation_options: &GenerationOptions,
+    min: IVec3,
+    max: IVec3,
+) -> Result<RegionBlocks, RegionError> {
+    let mut region = RegionBlocks::new(min, max)?;
+    let mut country_caches = HashMap::new();
+    let chunk_size = IVec2::new(CHUNK_SIZE[0] as i32, CHUNK_SIZE[2] as i32);
+
+    // The inner blocks of a chunk start one block after its position.
+    let first_chunk = IVec2::new(
+        (min.x - 1).div_euclid(chunk_size.x),
+        (min.z - 1).div_euclid(chunk_size.y),
+    );
+    let last_chunk = IVec2::new(
+        (max.x - 1).div_euclid(chunk_size.x),
+        (max.z - 1).div_euclid(chunk_size.y),
+    );
+
+    for chunk_x in first_chunk.x..=last_chunk.x {
+        for chunk_z in first_chunk.y..=last_chunk.y {
+            let chunk_min = IVec2::new(chunk_x, chunk_z) * chunk_size;
+            let country_cache = chunk_country_cache(
+                generation_options,
+                &mut country_caches,
+                chunk_min,
+                chunk_min + chunk_size,
+            );
+
+            let mut chunk_height = 0;
+            loop {
+                let (blocks, min_height, generate_more) = generate_voxels(
+                    [chunk_x, chunk_height, chunk_z],
+                    generation_options,
+                    ChunkLod::Full,
+                    &country_cache,
+                );
+
+                for (x, x_blocks) in blocks.iter().enumerate().skip(1).take(CHUNK_SIZE[0]) {
+                    for z in 1..=CHUNK_SIZE[2] {
+                        let column = chunk_min + IVec2::new(x as i32, z as i32);
+
+                        // Everything below the lowest generated block is solid.
+                        if chunk_height == 0 {
+                            for y in min.y..=min_height.min(max.y) {
+                                region.set(IVec3::new(column.x, y, column.y), BlockType::Stone);
+                            }
+                        }
+
+                        for (y, y_blocks) in x_blocks.iter().enumerate().skip(1).take(CHUNK_SIZE[1])
+                        {
+                            region.set(
+                                IVec3::new(column.x, min_height + y as i32, column.y),
+                                y_blocks[z],
+                            );
+                        }
+                    }
+                }
+
+                if !generate_more || min_height + CHUNK_SIZE[1] as i32 >= max.y {
+                    break;
+                }
+                chunk_height += 1;
+            }
+        }
+    }
+
+    Ok(region)
+}
+
+/// Box from `min` to `max` on the ground, reaching from below the lowest terrain in between to
+/// above the highest one.
+pub fn surface_box(
+    generation_options: &GenerationOptions,
+    min: IVec2,
+    max: IVec2,
+) -> (IVec3, IVec3) {
+    let terrain_noise = get_terrain_noise(ChunkLod::Full, generation_options);
+    let mut lowest = f32::INFINITY;
+    let mut highest = f32::NEG_INFINITY;
+
+    for x in (min.x..=max.x).step_by(4).chain([max.x]) {
+        for z in (min.y..=max.y).step_by(4).chain([max.y]) {
+            let height = terrain_noise.get([x as f64, z as f64]) as f32;
+            lowest = lowest.min(height);
+            highest = highest.max(height);
+        }
+    }
+
+    (
+        IVec3::new(min.x, lowest.floor() as i32 - SURFACE_MARGIN[0], min.y),
+        IVec3::new(max.x, highest.ceil() as i32 + SURFACE_MARGIN[1], max.y),
+    )
+}
+
+/// Country cache for a chunk from `chunk_min` to `chunk_max`, like the chunk loader merges them.
+/// Missing countries get generated into `country_caches`, the ones that fail without roads.
+pub(crate) fn chunk_country_cache(
+    generation_options: &GenerationOptions,
+    country_caches: &mut HashMap<IVec2, CountryCache>,
+    chunk_min: IVec2,
+    chunk_max: IVec2,
+) -> CountryCache {
+    let countries = generation_options.country_layout.countries_in_area(
+        chunk_min - ROAD_INFLUENCE_MARGIN,
+        chunk_max + ROAD_INFLUENCE_MARGIN,
+    );
+    for country in &countries {
+        country_caches.entry(*country).or_insert_with(|| {
+            generation_options
+                .country_cache
+                .try_get_cache_entry(*country, generation_options)
+                .map(|country_cache| country_cache.as_ref().clone())
+                .unwrap_or_else(|_| CountryCache::without_paths(*country, generation_options))
+        });
+    }
+
+    country_caches[&countries[0]].merged(
+        countries[1..]
+            .iter()
+            .map(|country| &country_caches[country]),
+    )
+}
+
+pub fn export_region_vox(
+    generation_options: &GenerationOptions,
+    min: IVec3,
+    max: IVec3,
+    path: impl AsRef<Path>,
+) -> Result<(), RegionError> {
+    region_to_vox(&generate_region(generation_options, min, max)?).write_file(path)?;
+    Ok(())
+}
+
+/// Splits `region` into models MagicaVoxel can open, with the minimum of the region at the
+/// origin. The palette holds the 255 most common block colors, the other blocks get the closest
+/// one of them.
+pub fn region_to_vox(region: &RegionBlocks) -> VoxScene {
+    let mut counts = HashMap::new();
+    for block in &region.blocks {
+        if *block != BlockType::Air {
+            *counts.entry(block_color(*block)).or_insert(0usize) += 1;
+        }
+    }
+
+    let mut colors = counts.into_iter().collect::<Vec<_>>();
+    colors
+        .sort_unstable_by_key(|((rgb, material), count)| (Reverse(*count), *rgb, *material as u8));
+
+    let mut palette = [[0; 4]; 256];
+    let mut materials = [VoxMaterial::Diffuse; 256];
+    let mut indices = HashMap::new();
+    for (index, ((rgb, material), _)) in colors.iter().take(255).enumerate() {
+        palette[index + 1] = [rgb[0], rgb[1], rgb[2], 255];
+        materials[index + 1] = *material;
+        indices.insert((*rgb, *material), index as u8 + 1);
+    }
+    for ((rgb, material), _) in colors.iter().skip(255) {
+        let closest = (1..256)
+            .min_by_key(|index| {
+                let distance = (0..3)
+                    .map(|channel| (palette[*index][channel] as i32 - rgb[channel] as i32).pow(2))
+                    .sum::<i32>();
+                (materials[*index] != *material, distance)
+            })
+            .unwrap_or(1);
+        indices.insert((*rgb, *material), closest as u8);
+    }
+
+    // The world is y up, .vox files are z up.
+    let vox_size = IVec3::new(region.size.x, region.size.z, region.size.y);
+    let mut models = vec![];
+    for model_x in (0..vox_size.x).step_by(VOX_MODEL_SIZE as usize) {
+        for model_y in (0..vox_size.y).step_by(VOX_MODEL_SIZE as usize) {
+            for model_z in (0..vox_size.z).step_by(VOX_MODEL_SIZE as usize) {
+                let start = IVec3::new(model_x, model_y, model_z);
+                let size = (vox_size - start).min(IVec3::splat(VOX_MODEL_SIZE));
+
+                let mut voxels = vec![];
+                for x in 0..size.x {
+                    for y in 0..size.y {
+                        for z in 0..size.z {
+                            let vox_position = start + IVec3::new(x, y, z);
+                            let block = region.get(
+                                region.min
+                                    + IVec3::new(vox_position.x, vox_position.z, vox_position.y),
+                            );
+                            if block != BlockType::Air {
+                                voxels.push((
+                                    [x as u8, y as u8, z as u8],
+                                    indices[&block_color(block)],
+                                ));
+                            }
+                        }
+                    }
+                }
+
+                if !voxels.is_empty() {
+                    models.push((
+                        start,
+                        VoxModel {
+                            size: size.to_array(),
+                            voxels,
+                        },
+                    ));
+                }
+            }
+        }
+    }
+
+    VoxScene::from_models(models, palette, materials)
+}
+
+fn block_color(block: BlockType) -> ([u8; 3], VoxMaterial) {
+    let color = block.get_color();
+    let material = if block.is_emissive() {
+        VoxMaterial::Emit
+    } else if block.is_transparent() {
+        VoxMaterial::Glass
+    } else {
+        VoxMaterial::Diffuse
+    };
+
+    (
+        [0, 1, 2].map(|channel| (color[channel].clamp(0., 1.) * 255.).round() as u8),
+        material,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_generation::vox::parse_vox;
+
+    #[test]
+    fn large_regions_are_split_into_models() {
+        let mut region = RegionBlocks::new(IVec3::new(-10, 5, 3), IVec3::new(289, 6, 3)).unwrap();
+        region.set(IVec3::new(-10, 5, 3), BlockType::Stone);
+        region.set(IVec3::new(289, 6, 3), BlockType::Glass(10, 20, 30));
+
+        let scene = region_to_vox(&region);
+        assert_eq!(scene.models.len(), 2);
+
+        let blocks = parse_vox(&scene.to_bytes().unwrap())
+            .unwrap()
+            .to_blocks()
+            .unwrap();
+        assert_eq!(blocks.size, [300, 2, 1]);
+        assert!(blocks.blocks[0][0][0] == BlockType::Custom(150, 160, 155));
+        assert!(blocks.blocks[299][1][0] == BlockType::Glass(10, 20, 30));
+        assert!(blocks.blocks[299][0][0] == BlockType::Air);
+    }
+
+    #[test]
+    fn rare_colors_get_the_closest_palette_entry() {
+        let mut region = RegionBlocks::new(IVec3::ZERO, IVec3::new(299, 1, 0)).unwrap();
+        for x in 0..255 {
+            region.set(IVec3::new(x, 0, 0), BlockType::Custom(x as u8, 0, 0));
+            region.set(IVec3::new(x, 1, 0), BlockType::Custom(x as u8, 0, 0));
+        }
+        for x in 255..300 {
+            region.set(IVec3::new(x, 0, 0), BlockType::Custom(0, x as u8, 0));
+        }
+
+        let scene = region_to_vox(&region);
+        let blocks = parse_vox(&scene.to_bytes().unwrap())
+            .unwrap()
+            .to_blocks()
+            .unwrap();
+
+        assert!(blocks.blocks[10][0][0] == BlockType::Custom(10, 0, 0));
+        assert!(blocks.blocks[299][0][0] == BlockType::Custom(0, 0, 0));
+    }
+
+    #[test]
+    fn oversized_regions_are_errors() {
+        assert!(matches!(
+            RegionBlocks::new(IVec3::splat(i32::MIN), IVec3::splat(i32::MAX)),
+            Err(RegionError::TooLarge { .. })
+        ));
+        assert!(matches!(
+            RegionBlocks::new(IVec3::ZERO, IVec3::splat(2047)),
+            Err(RegionError::TooLarge { .. })
+        ));
+
+        let region =
+            RegionBlocks::new(IVec3::new(0, 0, 0), IVec3::new(-1, 5, i32::MAX - 1)).unwrap();
+        assert_eq!(region.size, IVec3::new(0, 6, i32::MAX));
+        assert!(region.get(IVec3::ZERO) == BlockType::Air);
+    }
+}
@@ -0,0 +1,1061 @@
+use crate::world_generation::chunk_generation::BlockType;
+use bevy::math::IVec3;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::{fs, io};
+use vox_format::types::{Color, ColorIndex, Model, Point, Size, Voxel};
+use vox_format::VoxData;
+
+/// Everything read from a MagicaVoxel `.vox` file. Coordinates are in the file's space, with z
+/// pointing up. Models and the palette are read by `vox_format`, the scene graph and materials it
+/// skips are read here.
+pub struct VoxScene {
+    pub models: Vec<VoxModel>,
+    /// Colors by the index voxels refer to, index 0 is unused.
+    pub palette: [[u8; 4]; 256],
+    /// Materials by palette index.
+    pub materials: [VoxMaterial; 256],
+    nodes: HashMap<i32, SceneNode>,
+    hidden_layers: Vec<i32>,
+}
+
+pub struct VoxModel {
+    pub size: [i32; 3],
+    /// Position and palette index of every voxel.
+    pub voxels: Vec<([u8; 3], u8)>,
+}
+
+/// How the voxels of a palette index are rendered. Metal and the other surface settings have no
+/// block equivalent and stay diffuse.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum VoxMaterial {
+    #[default]
+    Diffuse,
+    /// Glass and blend materials become see-through blocks.
+    Glass,
+    Emit,
+}
+
+/// Blocks of a scene, indexed `[x][up][z]` like every other structure model.
+pub struct VoxBlocks {
+    pub blocks: Vec<Vec<Vec<BlockType>>>,
+    pub size: [i32; 3],
+}
+
+#[derive(Debug)]
+pub enum VoxError {
+    Io(io::Error),
+    NotAVoxFile,
+    UnexpectedEnd,
+    /// Models or the palette couldn't be read or written by `vox_format`.
+    Format(String),
+    /// The file contains no voxels at all.
+    Empty,
+    InvalidRotation(u8),
+    MissingNode(i32),
+    MissingModel(i32),
+    /// A node is its own ancestor.
+    NodeCycle(i32),
+    VoxelOutOfBounds {
+        model: usize,
+    },
+    Unsupported(String),
+}
+
+impl fmt::Display for VoxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VoxError::Io(error) => write!(f, "{}", error),
+            VoxError::NotAVoxFile => write!(f, "not a .vox file"),
+            VoxError::UnexpectedEnd => write!(f, "unexpected end of file"),
+            VoxError::Format(error) => write!(f, "{}", error),
+            VoxError::Empty => write!(f, "scene contains no voxels"),
+            VoxError::InvalidRotation(rotation) => write!(f, "invalid rotation {}", rotation),
+            VoxError::MissingNode(node) => write!(f, "missing scene node {}", node),
+            VoxError::MissingModel(model) => write!(f, "missing model {}", model),
+            VoxError::NodeCycle(node) => write!(f, "scene node {} contains itself", node),
+            VoxError::VoxelOutOfBounds { model } => {
+                write!(f, "model {} has voxels outside of its size", model)
+            }
+            VoxError::Unsupported(feature) => write!(f, "unsupported feature: {}", feature),
+        }
+    }
+}
+
+impl std::error::Error for VoxError {}
+
+impl From<io::Error> for VoxError {
+    fn from(error: io::Error) -> Self {
+        VoxError::Io(error)
+    }
+}
+
+enum SceneNode {
+    Transform {
+        name: Option<String>,
+        hidden: bool,
+        layer: i32,
+        transform: VoxTransform,
+        child: i32,
+    },
+    Group(Vec<i32>),
+    Shape(i32),
+}
+
+/// Rotation and translation of a transform node, or of a model within the whole scene.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct VoxTransform {
+    rows: [IVec3; 3],
+    translation: IVec3,
+}
+
+impl VoxTransform {
+    const IDENTITY: Self = Self {
+        rows: [IVec3::X, IVec3::Y, IVec3::Z],
+        translation: IVec3::ZERO,
+    };
+
+    fn parse(rotation: Option<&str>, translation: Option<&str>) -> Result<Self, VoxError> {
+        let mut transform = Self::IDENTITY;
+
+        if let Some(rotation) = rotation {
+            let rotation = rotation
+                .trim()
+                .parse::<u8>()
+                .map_err(|_| VoxError::Unsupported(format!("rotation \"{}\"", rotation)))?;
+            let first = (rotation & 3) as usize;
+            let second = ((rotation >> 2) & 3) as usize;
+            if first > 2 || second > 2 || first == second {
+                return Err(VoxError::InvalidRotation(rotation));
+            }
+            let columns = [first, second, 3 - first - second];
+
+            for (row, column) in columns.into_iter().enumerate() {
+                let sign = if rotation & (16 << row) != 0 { -1 } else { 1 };
+                let mut value = IVec3::ZERO;
+                value[column] = sign;
+                transform.rows[row] = value;
+            }
+        }
+
+        if let Some(translation) = translation {
+            let values = translation
+                .split_whitespace()
+                .map(|value| value.parse::<i32>())
+                .collect::<Result<Vec<_>, _>>()
+                .ok()
+                .filter(|values| values.len() == 3)
+                .ok_or_else(|| VoxError::Unsupported(format!("translation \"{}\"", translation)))?;
+            transform.translation = IVec3::new(values[0], values[1], values[2]);
+        }
+
+        Ok(transform)
+    }
+
+    fn rotate(&self, vector: IVec3) -> IVec3 {
+        IVec3::new(
+            self.rows[0].dot(vector),
+            self.rows[1].dot(vector),
+            self.rows[2].dot(vector),
+        )
+    }
+
+    /// `child` placed inside of `self`.
+    fn then(&self, child: &Self) -> Self {
+        let columns = [IVec3::X, IVec3::Y, IVec3::Z].map(|axis| self.rotate(child.rotate(axis)));
+
+        Self {
+            rows: [0, 1, 2]
+                .map(|row| IVec3::new(columns[0][row], columns[1][row], columns[2][row])),
+            translation: self.translation + self.rotate(child.translation),
+        }
+    }
+
+    /// The packed rotation byte, the inverse of [`VoxTransform::parse`].
+    fn rotation_byte(&self) -> u8 {
+        let mut rotation = 0;
+        for (row, value) in self.rows.iter().enumerate() {
+            let column = (0..3).find(|column| value[*column] != 0).unwrap_or(row);
+            if row < 2 {
+                rotation |= (column as u8) << (row * 2);
+            }
+            if value[column] < 0 {
+                rotation |= 16 << row;
+            }
+        }
+        rotation
+    }
+
+    /// Scene position of voxel `point` of a model of `size`. The model is turned around its center
+    /// and keeps covering the same voxels, with voxel `size / 2` of the turned model at the
+    /// translation.
+    fn place(&self, point: [u8; 3], size: [i32; 3]) -> IVec3 {
+        // In doubled coordinates, so voxel centers of even and odd sized models stay exact.
+        let centered = self.rotate(
+            IVec3::from_array(point.map(|value| value as i32)) * 2 + 1 - IVec3::from_array(size),
+        );
+        let size = self.rotate(IVec3::from_array(size)).abs();
+        self.translation - size / 2 + (centered + size - 1) / 2
+    }
+}
+
+pub fn read_vox_file(path: impl AsRef<Path>) -> Result<VoxScene, VoxError> {
+    parse_vox(&fs::read(path)?)
+}
+
+pub fn parse_vox(bytes: &[u8]) -> Result<VoxScene, VoxError> {
+    let mut reader = Reader(bytes);
+    if reader.take(4)? != b"VOX " {
+        return Err(VoxError::NotAVoxFile);
+    }
+    reader.i32()?;
+
+    let (id, mut children) = reader.chunk()?;
+    if id != b"MAIN" {
+        return Err(VoxError::NotAVoxFile);
+    }
+
+    let mut scene = VoxScene {
+        models: vec![],
+        palette: default_palette(),
+        materials: [VoxMaterial::Diffuse; 256],
+        nodes: HashMap::new(),
+        hidden_layers: vec![],
+    };
+    let mut has_palette = false;
+
+    while !children.0.is_empty() {
+        let (id, mut content) = children.chunk()?;
+        match id {
+            b"RGBA" => has_palette = true,
+            b"nTRN" => {
+                let node = content.i32()?;
+                let attributes = content.dict()?;
+                let child = content.i32()?;
+                content.i32()?;
+                let layer = content.i32()?;
+                let frame_count = content.i32()?;
+                if frame_count != 1 {
+                    return Err(VoxError::Unsupported(format!(
+                        "transform node {} with {} animation frames",
+                        node, frame_count
+                    )));
+                }
+                let frame = content.dict()?;
+
+                scene.nodes.insert(
+                    node,
+                    SceneNode::Transform {
+                        name: attributes.get("_name").cloned(),
+                        hidden: attributes.get("_hidden").is_some_and(|value| value == "1"),
+                        layer,
+                        transform: VoxTransform::parse(
+                            frame.get("_r").map(String::as_str),
+                            frame.get("_t").map(String::as_str),
+                        )?,
+                        child,
+                    },
+                );
+            }
+            b"nGRP" => {
+                let node = content.i32()?;
+                content.dict()?;
+                let count = content.i32()?.max(0);
+                let children = (0..count)
+                    .map(|_| content.i32())
+                    .collect::<Result<Vec<_>, _>>()?;
+                scene.nodes.insert(node, SceneNode::Group(children));
+            }
+            b"nSHP" => {
+                let node = content.i32()?;
+                content.dict()?;
+                let model_count = content.i32()?;
+                if model_count != 1 {
+                    return Err(VoxError::Unsupported(format!(
+                        "shape node {} with {} animation frames",
+                        node, model_count
+                    )));
+                }
+                let model = content.i32()?;
+                scene.nodes.insert(node, SceneNode::Shape(model));
+            }
+            b"LAYR" => {
+                let layer = content.i32()?;
+                let attributes = content.dict()?;
+                if attributes.get("_hidden").is_some_and(|value| value == "1") {
+                    scene.hidden_layers.push(layer);
+                }
+            }
+            b"MATL" => {
+                let index = content.i32()?;
+                let attributes = content.dict()?;
+                let material = match attributes.get("_type").map(String::as_str) {
+                    Some("_glass" | "_blend") => VoxMaterial::Glass,
+                    Some("_emit") => VoxMaterial::Emit,
+                    _ => VoxMaterial::Diffuse,
+                };
+                if let Some(slot) = usize::try_from(index)
+                    .ok()
+                    .and_then(|index| scene.materials.get_mut(index))
+                {
+                    *slot = material;
+                }
+            }
+            // Models are read by vox_format, cameras, render settings and notes don't change the
+            // blocks.
+            _ => {}
+        }
+    }
+
+    let vox_data =
+        vox_format::from_slice(bytes).map_err(|error| VoxError::Format(error.to_string()))?;
+    for (index, model) in vox_data.models.iter().enumerate() {
+        let size = [model.size.x, model.size.y, model.size.z].map(|value| value as i32);
+        let voxels = model
+            .voxels
+            .iter()
+            .map(|voxel| {
+                (
+                    [voxel.point.x, voxel.point.y, voxel.point.z].map(|value| value as u8),
+                    voxel.color_index.0,
+                )
+            })
+            .collect::<Vec<_>>();
+        if voxels
+            .iter()
+            .any(|(point, _)| (0..3).any(|axis| point[axis] as i32 >= size[axis]))
+        {
+            return Err(VoxError::VoxelOutOfBounds { model: index });
+        }
+        scene.models.push(VoxModel { size, voxels });
+    }
+    if has_palette {
+        for (color, vox_color) in scene.palette.iter_mut().zip(vox_data.palette.colors.iter()) {
+            *color = [vox_color.r, vox_color.g, vox_color.b, vox_color.a];
+        }
+    }
+
+    Ok(scene)
+}
+
+/// The palette MagicaVoxel uses for files that don't contain one: a 6x6x6 color cube without
+/// black, followed by ramps of red, green, blue and gray.
+fn default_palette() -> [[u8; 4]; 256] {
+    let cube =
+        (0..215u8).map(|index| [index / 36, index / 6 % 6, index % 6].map(|step| (5 - step) * 51));
+    let ramps = [[1, 0, 0], [0, 1, 0], [0, 0, 1], [1, 1, 1]]
+        .into_iter()
+        .flat_map(|channels: [u8; 3]| {
+            [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11]
+                .map(|value| channels.map(|channel| channel * value))
+        });
+
+    let mut palette = [[0; 4]; 256];
+    for (color, [r, g, b]) in palette[1..].iter_mut().zip(cube.chain(ramps)) {
+        *color = [r, g, b, 255];
+    }
+    palette
+}
+
+impl VoxScene {
+    /// Scene of `models`, each one with its first voxel at the given scene position.
+    pub fn from_models(
+        models: Vec<(IVec3, VoxModel)>,
+        palette: [[u8; 4]; 256],
+        materials: [VoxMaterial; 256],
+    ) -> Self {
+        let mut nodes = HashMap::new();
+        let mut children = vec![];
+        let mut vox_models = vec![];
+
+        for (index, (position, model)) in models.into_iter().enumerate() {
+            let transform_node = 2 + index as i32 * 2;
+            nodes.insert(
+                transform_node,
+                SceneNode::Transform {
+                    name: None,
+                    hidden: false,
+                    layer: 0,
+                    transform: VoxTransform {
+                        translation: position + IVec3::from_array(model.size) / 2,
+                        ..VoxTransform::IDENTITY
+                    },
+                    child: transform_node + 1,
+                },
+            );
+            nodes.insert(transform_node + 1, SceneNode::Shape(index as i32));
+            children.push(transform_node);
+            vox_models.push(model);
+        }
+
+        nodes.insert(
+            0,
+            SceneNode::Transform {
+                name: None,
+                hidden: false,
+                layer: -1,
+                transform: VoxTransform::IDENTITY,
+                child: 1,
+            },
+        );
+        nodes.insert(1, SceneNode::Group(children));
+
+        Self {
+            models: vox_models,
+            palette,
+            materials,
+            nodes,
+            hidden_layers: vec![],
+        }
+    }
+
+    pub fn write_file(&self, path: impl AsRef<Path>) -> Result<(), VoxError> {
+        fs::write(path, self.to_bytes()?)?;
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, VoxError> {
+        let mut vox_data = VoxData::default();
+        vox_data.models.extend(self.models.iter().map(|model| {
+            Model {
+                size: Size {
+                    x: model.size[0] as u32,
+                    y: model.size[1] as u32,
+                    z: model.size[2] as u32,
+                },
+                voxels: model
+                    .voxels
+                    .iter()
+                    .map(|(point, color)| Voxel {
+                        point: Point {
+                            x: point[0] as _,
+                            y: point[1] as _,
+                            z: point[2] as _,
+                        },
+                        color_index: ColorIndex(*color),
+                    })
+                    .collect(),
+            }
+        }));
+        for (vox_color, [r, g, b, a]) in vox_data.palette.colors.iter_mut().zip(self.palette) {
+            *vox_color = Color { r, g, b, a };
+        }
+        let mut bytes =
+            vox_format::to_vec(&vox_data).map_err(|error| VoxError::Format(error.to_string()))?;
+
+        // vox_format only writes models and the palette, the rest is added to the end of MAIN.
+        let mut children = Writer::default();
+        let mut node_ids = self.nodes.keys().copied().collect::<Vec<_>>();
+        node_ids.sort_unstable();
+        for node in node_ids {
+            let mut content = Writer::default();
+            content.i32(node);
+            match &self.nodes[&node] {
+                SceneNode::Transform {
+                    name,
+                    hidden,
+                    layer,
+                    transform,
+                    child,
+                } => {
+                    let mut attributes = vec![];
+                    if let Some(name) = name {
+                        attributes.push(("_name", name.clone()));
+                    }
+                    if *hidden {
+                        attributes.push(("_hidden", "1".to_string()));
+                    }
+                    content.dict(&attributes);
+                    content.i32(*child);
+                    content.i32(-1);
+                    content.i32(*layer);
+                    content.i32(1);
+
+                    let translation = transform.translation;
+                    let mut frame = vec![(
+                        "_t",
+                        format!("{} {} {}", translation.x, translation.y, translation.z),
+                    )];
+                    if transform.rows != VoxTransform::IDENTITY.rows {
+                        frame.push(("_r", transform.rotation_byte().to_string()));
+                    }
+                    content.dict(&frame);
+                    children.chunk(b"nTRN", content);
+                }
+                SceneNode::Group(group_children) => {
+                    content.dict(&[]);
+                    content.i32(group_children.len() as i32);
+                    for child in group_children {
+                        content.i32(*child);
+                    }
+                    children.chunk(b"nGRP", content);
+                }
+                SceneNode::Shape(model) => {
+                    content.dict(&[]);
+                    content.i32(1);
+                    content.i32(*model);
+                    content.dict(&[]);
+                    children.chunk(b"nSHP", content);
+                }
+            }
+        }
+
+        for layer in &self.hidden_layers {
+            let mut content = Writer::default();
+            content.i32(*layer);
+            content.dict(&[("_hidden", "1".to_string())]);
+            content.i32(-1);
+            children.chunk(b"LAYR", content);
+        }
+
+        for (index, material) in self.materials.iter().enumerate() {
+            let kind = match material {
+                VoxMaterial::Diffuse => continue,
+                VoxMaterial::Glass => "_glass",
+                VoxMaterial::Emit => "_emit",
+            };
+            let mut content = Writer::default();
+            content.i32(index as i32);
+            content.dict(&[("_type", kind.to_string())]);
+            children.chunk(b"MATL", content);
+        }
+
+        let Some(children_length) = bytes.get_mut(16..20) else {
+            return Err(VoxError::UnexpectedEnd);
+        };
+        let length = i32::from_le_bytes([
+            children_length[0],
+            children_length[1],
+            children_length[2],
+            children_length[3],
+        ]) + children.0.len() as i32;
+        children_length.copy_from_slice(&length.to_le_bytes());
+        bytes.extend(children.0);
+        Ok(bytes)
+    }
+
+    /// All visible models of the scene, placed by their transforms and combined into one
+    /// structure.
+    pub fn to_blocks(&self) -> Result<VoxBlocks, VoxError> {
+        let mut voxels = PlacedVoxels::default();
+        if self.nodes.is_empty() {
+            // Files without a scene graph have their models on top of each other.
+            for model in &self.models {
+                voxels.add_model(model, |point| {
+                    IVec3::from_array(point.map(|value| value as i32))
+                });
+            }
+        } else {
+            self.collect_voxels(0, VoxTransform::IDENTITY, &mut vec![], &mut voxels)?;
+        }
+
+        self.voxels_to_blocks(&voxels)
+    }
+
+    /// Every visible transform node with a name as its own structure, for files that keep the
+    /// variants of a structure next to each other. Named nodes inside of named nodes belong to
+    /// the outer one.
+    pub fn named_parts(&self) -> Result<Vec<(String, VoxBlocks)>, VoxError> {
+        let mut parts = vec![];
+        if !self.nodes.is_empty() {
+            self.collect_named_parts(0, &mut vec![], &mut parts)?;
+        }
+        Ok(parts)
+    }
+
+    fn collect_named_parts(
+        &self,
+        node: i32,
+        ancestors: &mut Vec<i32>,
+        parts: &mut Vec<(String, VoxBlocks)>,
+    ) -> Result<(), VoxError> {
+        if ancestors.contains(&node) {
+            return Err(VoxError::NodeCycle(node));
+        }
+
+        match self.nodes.get(&node).ok_or(VoxError::MissingNode(node))? {
+            SceneNode::Transform {
+                name: Some(name), ..
+            } if self.is_visible(node) => {
+                let mut voxels = PlacedVoxels::default();
+                self.collect_voxels(node, VoxTransform::IDENTITY, ancestors, &mut voxels)?;
+                parts.push((name.clone(), self.voxels_to_blocks(&voxels)?));
+            }
+            SceneNode::Transform { child, .. } if self.is_visible(node) => {
+                ancestors.push(node);
+                self.collect_named_parts(*child, ancestors, parts)?;
+                ancestors.pop();
+            }
+            SceneNode::Group(children) => {
+                ancestors.push(node);
+                for child in children {
+                    self.collect_named_parts(*child, ancestors, parts)?;
+                }
+                ancestors.pop();
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn is_visible(&self, node: i32) -> bool {
+        match self.nodes.get(&node) {
+            Some(SceneNode::Transform { hidden, layer, .. }) => {
+                !hidden && !self.hidden_layers.contains(layer)
+            }
+            _ => true,
+        }
+    }
+
+    fn collect_voxels(
+        &self,
+        node: i32,
+        parent: VoxTransform,
+        ancestors: &mut Vec<i32>,
+        voxels: &mut PlacedVoxels,
+    ) -> Result<(), VoxError> {
+        if ancestors.contains(&node) {
+            return Err(VoxError::NodeCycle(node));
+        }
+        if !self.is_visible(node) {
+            return Ok(());
+        }
+
+        ancestors.push(node);
+        match self.nodes.get(&node).ok_or(VoxError::MissingNode(node))? {
+            SceneNode::Transform {
+                transform, child, ..
+            } => {
+                self.collect_voxels(*child, parent.then(transform), ancestors, voxels)?;
+            }
+            SceneNode::Group(children) => {
+                for child in children {
+                    self.collect_voxels(*child, parent, ancestors, voxels)?;
+                }
+            }
+            SceneNode::Shape(model) => {
+                let vox_model = usize::try_from(*model)
+                    .ok()
+                    .and_then(|index| self.models.get(index))
+                    .ok_or(VoxError::MissingModel(*model))?;
+                voxels.add_model(vox_model, |point| parent.place(point, vox_model.size));
+            }
+        }
+        ancestors.pop();
+
+        Ok(())
+    }
+
+    fn voxels_to_blocks(&self, voxels: &PlacedVoxels) -> Result<VoxBlocks, VoxError> {
+        let (Some((min, max)), false) = (voxels.bounds, voxels.voxels.is_empty()) else {
+            return Err(VoxError::Empty);
+        };
+
+        let vox_size = max - min + 1;
+        let size = [vox_size.x, vox_size.z, vox_size.y];
+        let mut blocks =
+            vec![vec![vec![BlockType::Air; size[2] as usize]; size[1] as usize]; size[0] as usize];
+
+        for (point, color) in &voxels.voxels {
+            let [r, g, b, _] = self.palette[*color as usize];
+            let point = *point - min;
+            blocks[point.x as usize][point.z as usize][point.y as usize] =
+                match self.materials[*color as usize] {
+                    VoxMaterial::Diffuse => BlockType::Custom(r, g, b),
+                    VoxMaterial::Glass => BlockType::Glass(r, g, b),
+                    VoxMaterial::Emit => BlockType::Light(r, g, b),
+                };
+        }
+
+        Ok(VoxBlocks { blocks, size })
+    }
+}
+
+/// Voxels in scene space, with the bounds of the models they came from. Empty space in the models
+/// is kept, so a scene with a single model has the same size as the model.
+#[derive(Default)]
+struct PlacedVoxels {
+    voxels: Vec<(IVec3, u8)>,
+    bounds: Option<(IVec3, IVec3)>,
+}
+
+impl PlacedVoxels {
+    fn add_model(&mut self, model: &VoxModel, place: impl Fn([u8; 3]) -> IVec3) {
+        for corner in [[0; 3], model.size.map(|value| (value - 1) as u8)] {
+            let corner = place(corner);
+            self.bounds = Some(match self.bounds {
+                Some((min, max)) => (min.min(corner), max.max(corner)),
+                None => (corner, corner),
+            });
+        }
+
+        for (point, color) in &model.voxels {
+            self.voxels.push((place(*point), *color));
+        }
+    }
+}
+
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn i32(&mut self, value: i32) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.i32(value.len() as i32);
+        self.0.extend(value.as_bytes());
+    }
+
+    fn dict(&mut self, pairs: &[(&str, String)]) {
+        self.i32(pairs.len() as i32);
+        for (key, value) in pairs {
+            self.string(key);
+            self.string(value);
+        }
+    }
+
+    fn chunk(&mut self, id: &[u8; 4], content: Writer) {
+        self.0.extend(id);
+        self.i32(content.0.len() as i32);
+        self.i32(0);
+        self.0.extend(content.0);
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], VoxError> {
+        if self.0.len() < length {
+            return Err(VoxError::UnexpectedEnd);
+        }
+        let (taken, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn i32(&mut self) -> Result<i32, VoxError> {
+        let bytes = self.take(4)?;
+        Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn string(&mut self) -> Result<String, VoxError> {
+        let length = self.i32()?.max(0) as usize;
+        Ok(String::from_utf8_lossy(self.take(length)?).into_owned())
+    }
+
+    fn dict(&mut self) -> Result<HashMap<String, String>, VoxError> {
+        let count = self.i32()?.max(0);
+        let mut dict = HashMap::new();
+        for _ in 0..count {
+            let key = self.string()?;
+            dict.insert(key, self.string()?);
+        }
+        Ok(dict)
+    }
+
+    /// Id of the next chunk, with readers for its content and its children. The children follow
+    /// the content, so for chunks without children the rest is just skipped.
+    fn chunk(&mut self) -> Result<(&'a [u8], Reader<'a>), VoxError> {
+        let id = self.take(4)?;
+        let content_length = self.i32()?.max(0) as usize;
+        let children_length = self.i32()?.max(0) as usize;
+        let content = self.take(content_length)?;
+        let children = self.take(children_length)?;
+
+        Ok(match id {
+            b"MAIN" => (id, Reader(children)),
+            _ => (id, Reader(content)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], content: &[u8], children: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend((content.len() as i32).to_le_bytes());
+        bytes.extend((children.len() as i32).to_le_bytes());
+        bytes.extend(content);
+        bytes.extend(children);
+        bytes
+    }
+
+    fn dict(pairs: &[(&str, &str)]) -> Vec<u8> {
+        let mut bytes = (pairs.len() as i32).to_le_bytes().to_vec();
+        for value in pairs.iter().flat_map(|(key, value)| [key, value]) {
+            bytes.extend((value.len() as i32).to_le_bytes());
+            bytes.extend(value.as_bytes());
+        }
+        bytes
+    }
+
+    fn ints(values: &[i32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    fn model(size: [i32; 3], voxels: &[[u8; 4]]) -> Vec<u8> {
+        let mut xyzi = ints(&[voxels.len() as i32]);
+        xyzi.extend(voxels.iter().flatten());
+        [
+            chunk(b"SIZE", &ints(&size), &[]),
+            chunk(b"XYZI", &xyzi, &[]),
+        ]
+        .concat()
+    }
+
+    fn transform(node: i32, child: i32, name: &str, frame: &[(&str, &str)]) -> Vec<u8> {
+        let attributes = if name.is_empty() {
+            dict(&[])
+        } else {
+            dict(&[("_name", name)])
+        };
+        let content = [
+            ints(&[node]),
+            attributes,
+            ints(&[child, -1, 0, 1]),
+            dict(frame),
+        ]
+        .concat();
+        chunk(b"nTRN", &content, &[])
+    }
+
+    fn group(node: i32, children: &[i32]) -> Vec<u8> {
+        let content = [
+            ints(&[node]),
+            dict(&[]),
+            ints(&[children.len() as i32]),
+            ints(children),
+        ]
+        .concat();
+        chunk(b"nGRP", &content, &[])
+    }
+
+    fn shape(node: i32, model: i32) -> Vec<u8> {
+        let content = [ints(&[node]), dict(&[]), ints(&[1, model]), dict(&[])].concat();
+        chunk(b"nSHP", &content, &[])
+    }
+
+    fn file(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let palette = (0..256u32)
+            .flat_map(|index| [index as u8, index as u8, index as u8, 255])
+            .collect::<Vec<_>>();
+        file_without_palette(&[chunks.concat(), chunk(b"RGBA", &palette, &[])])
+    }
+
+    fn file_without_palette(chunks: &[Vec<u8>]) -> Vec<u8> {
+        [
+            b"VOX ".to_vec(),
+            ints(&[150]),
+            chunk(b"MAIN", &[], &chunks.concat()),
+        ]
+        .concat()
+    }
+
+    /// Two single voxel models, the second one 10 voxels along x and rotated.
+    fn two_part_scene() -> Vec<u8> {
+        file(&[
+            model([1, 1, 1], &[[0, 0, 0, 1]]),
+            model([1, 2, 1], &[[0, 0, 0, 2], [0, 1, 0, 3]]),
+            transform(0, 1, "", &[]),
+            group(1, &[2, 4]),
+            transform(2, 3, "first", &[]),
+            shape(3, 0),
+            transform(4, 5, "second", &[("_t", "10 0 0"), ("_r", "17")]),
+            shape(5, 1),
+        ])
+    }
+
+    #[test]
+    fn model_without_scene_graph_keeps_its_layout() {
+        let scene = parse_vox(&file(&[model([2, 3, 4], &[[1, 2, 3, 5]])])).unwrap();
+        let blocks = scene.to_blocks().unwrap();
+
+        assert_eq!(blocks.size, [2, 4, 3]);
+        assert!(blocks.blocks[1][3][2] == BlockType::Custom(4, 4, 4));
+        assert!(blocks.blocks[0][0][0] == BlockType::Air);
+    }
+
+    #[test]
+    fn files_without_palette_use_the_default_one() {
+        let scene = parse_vox(&file_without_palette(&[model(
+            [3, 1, 1],
+            &[[0, 0, 0, 1], [1, 0, 0, 2], [2, 0, 0, 216]],
+        )]))
+        .unwrap();
+        let blocks = scene.to_blocks().unwrap();
+
+        assert!(blocks.blocks[0][0][0] == BlockType::Custom(255, 255, 255));
+        assert!(blocks.blocks[1][0][0] == BlockType::Custom(255, 255, 204));
+        assert!(blocks.blocks[2][0][0] == BlockType::Custom(238, 0, 0));
+        assert_eq!(default_palette()[255], [17, 17, 17, 255]);
+    }
+
+    #[test]
+    fn materials_become_block_properties() {
+        let material = |index: i32, kind: &str| {
+            let content = [ints(&[index]), dict(&[("_type", kind)])].concat();
+            chunk(b"MATL", &content, &[])
+        };
+        let scene = parse_vox(&file(&[
+            model([3, 1, 1], &[[0, 0, 0, 1], [1, 0, 0, 2], [2, 0, 0, 3]]),
+            material(1, "_glass"),
+            material(2, "_emit"),
+            material(3, "_metal"),
+        ]))
+        .unwrap();
+        let blocks = scene.to_blocks().unwrap();
+
+        assert!(blocks.blocks[0][0][0] == BlockType::Glass(0, 0, 0));
+        assert!(blocks.blocks[1][0][0] == BlockType::Light(1, 1, 1));
+        assert!(blocks.blocks[2][0][0] == BlockType::Custom(2, 2, 2));
+    }
+
+    #[test]
+    fn scene_combines_transformed_models() {
+        let blocks = parse_vox(&two_part_scene()).unwrap().to_blocks().unwrap();
+
+        // The rotated model lies along x, covering the voxels around the translation.
+        assert_eq!(blocks.size, [11, 1, 1]);
+        assert!(blocks.blocks[0][0][0] == BlockType::Custom(0, 0, 0));
+        assert!(blocks.blocks[9][0][0] == BlockType::Custom(2, 2, 2));
+        assert!(blocks.blocks[10][0][0] == BlockType::Custom(1, 1, 1));
+        assert!(blocks.blocks[5][0][0] == BlockType::Air);
+    }
+
+    #[test]
+    fn models_are_centered_on_their_translation() {
+        let flipped = VoxTransform {
+            rows: [IVec3::NEG_X, IVec3::Y, IVec3::Z],
+            translation: IVec3::new(10, 0, 0),
+        };
+        let identity = VoxTransform {
+            translation: IVec3::new(10, 0, 0),
+            ..VoxTransform::IDENTITY
+        };
+
+        // The middle voxel of an odd sized model is at the translation, also when flipped.
+        let odd = [3, 1, 1];
+        assert_eq!(identity.place([0, 0, 0], odd), IVec3::new(9, 0, 0));
+        assert_eq!(identity.place([1, 0, 0], odd), IVec3::new(10, 0, 0));
+        assert_eq!(flipped.place([0, 0, 0], odd), IVec3::new(11, 0, 0));
+        assert_eq!(flipped.place([1, 0, 0], odd), IVec3::new(10, 0, 0));
+
+        // Even sized models cover the same voxels when flipped.
+        let even = [4, 1, 1];
+        assert_eq!(identity.place([0, 0, 0], even), IVec3::new(8, 0, 0));
+        assert_eq!(identity.place([3, 0, 0], even), IVec3::new(11, 0, 0));
+        assert_eq!(flipped.place([0, 0, 0], even), IVec3::new(11, 0, 0));
+        assert_eq!(flipped.place([3, 0, 0], even), IVec3::new(8, 0, 0));
+
+        let blocks = parse_vox(&file(&[
+            model([3, 1, 1], &[[0, 0, 0, 1], [2, 0, 0, 3]]),
+            transform(0, 1, "", &[("_t", "10 0 0")]),
+            shape(1, 0),
+        ]))
+        .unwrap()
+        .to_blocks()
+        .unwrap();
+        assert_eq!(blocks.size, [3, 1, 1]);
+        assert!(blocks.blocks[2][0][0] == BlockType::Custom(2, 2, 2));
+    }
+
+    #[test]
+    fn named_nodes_become_parts() {
+        let parts = parse_vox(&two_part_scene()).unwrap().named_parts().unwrap();
+
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].0, "first");
+        assert_eq!(parts[0].1.size, [1, 1, 1]);
+        assert_eq!(parts[1].0, "second");
+        assert_eq!(parts[1].1.size, [2, 1, 1]);
+    }
+
+    #[test]
+    fn written_scenes_read_back_the_same() {
+        let models = vec![
+            (
+                IVec3::new(-3, 0, 0),
+                VoxModel {
+                    size: [2, 1, 3],
+                    voxels: vec![([0, 0, 0], 1), ([1, 0, 2], 2)],
+                },
+            ),
+            (
+                IVec3::new(0, 4, 1),
+                VoxModel {
+                    size: [1, 1, 1],
+                    voxels: vec![([0, 0, 0], 3)],
+                },
+            ),
+        ];
+        let mut palette = [[0; 4]; 256];
+        palette[3] = [1, 2, 3, 255];
+        let mut materials = [VoxMaterial::Diffuse; 256];
+        materials[3] = VoxMaterial::Emit;
+
+        let scene = VoxScene::from_models(models, palette, materials);
+        let blocks = parse_vox(&scene.to_bytes().unwrap())
+            .unwrap()
+            .to_blocks()
+            .unwrap();
+
+        assert_eq!(blocks.size, [4, 3, 5]);
+        assert!(blocks.blocks[0][0][0] == BlockType::Custom(0, 0, 0));
+        assert!(blocks.blocks[1][2][0] == BlockType::Custom(0, 0, 0));
+        assert!(blocks.blocks[3][1][4] == BlockType::Light(1, 2, 3));
+    }
+
+    #[test]
+    fn rotations_are_written_like_they_are_read() {
+        for rotation in 0..128 {
+            if let Ok(transform) = VoxTransform::parse(Some(&rotation.to_string()), None) {
+                assert_eq!(transform.rotation_byte(), rotation);
+            }
+        }
+    }
+
+    #[test]
+    fn rotation_rows_follow_the_packed_format() {
+        let transform = VoxTransform::parse(Some("17"), None).unwrap();
+
+        assert_eq!(transform.rotate(IVec3::X), IVec3::Y);
+        assert_eq!(transform.rotate(IVec3::Y), -IVec3::X);
+        assert_eq!(transform.rotate(IVec3::Z), IVec3::Z);
+        assert!(matches!(
+            VoxTransform::parse(Some("5"), None),
+            Err(VoxError::InvalidRotation(5))
+        ));
+    }
+
+    #[test]
+    fn broken_files_are_errors() {
+        let scene = two_part_scene();
+        assert!(matches!(
+            parse_vox(&scene[..scene.len() - 10]),
+            Err(VoxError::UnexpectedEnd)
+        ));
+        assert!(matches!(parse_vox(b"PNG"), Err(VoxError::UnexpectedEnd)));
+        assert!(matches!(
+            parse_vox(b"RIFF\0\0\0\0"),
+            Err(VoxError::NotAVoxFile)
+        ));
+
+        let cycle = parse_vox(&file(&[
+            model([1, 1, 1], &[[0, 0, 0, 1]]),
+            transform(0, 1, "", &[]),
+            group(1, &[0]),
+        ]))
+        .unwrap();
+        assert!(matches!(cycle.to_blocks(), Err(VoxError::NodeCycle(0))));
+    }
+}
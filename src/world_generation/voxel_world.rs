@@ -1,3 +1,4 @@
+use crate::world_generation::chunk_events::ChunkBounds;
 use crate::world_generation::chunk_generation::mesh_generation::generate_mesh;
 use crate::world_generation::chunk_generation::voxel_generation::generate_voxels;
 use crate::world_generation::chunk_generation::{ChunkTaskData, CHUNK_SIZE, VOXEL_SIZE};
@@ -11,7 +12,7 @@ use std::sync::Arc;
 
 pub const MAX_LOD: ChunkLod = ChunkLod::OneTwentyEight;
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum ChunkLod {
     Full = 1,
     Half = 2,
@@ -58,6 +59,16 @@ impl ChunkLod {
     pub fn previous(self) -> Self {
         ChunkLod::from_u8(self as u8 - 1).expect("Mapping doesn't exist!")
     }
+    pub fn from_tree_depth(depth: i32) -> Self {
+        ChunkLod::from_u8((MAX_LOD.i32() - depth) as u8).expect("Mapping doesn't exist!")
+    }
+
+    /// The LOD whose voxels are `multiplier` full resolution voxels wide.
+    pub fn from_multiplier(multiplier: i32) -> Option<Self> {
+        (1..)
+            .map_while(ChunkLod::from_u8)
+            .find(|lod| lod.multiplier_i32() == multiplier)
+    }
 
     fn from_u8(number: u8) -> Option<Self> {
         match number {
@@ -134,15 +145,14 @@ impl VoxelWorld for QuadTreeVoxelWorld {
             chunk_height,
             parent_pos.y * MAX_LOD.multiplier_i32() + lod_position.y * chunk_lod.multiplier_i32(),
         ];
-        let mesh = generate_mesh(
-            generate_voxels(
-                new_chunk_pos,
-                &generation_options,
-                chunk_lod,
-                &country_cache,
-            ),
+        let voxels = generate_voxels(
+            new_chunk_pos,
+            &generation_options,
             chunk_lod,
+            &country_cache,
         );
+        let min_height = voxels.1;
+        let mesh = generate_mesh(voxels, chunk_lod);
 
         return ChunkGenerationResult {
             task_data: match mesh.0 {
@@ -158,7 +168,8 @@ impl VoxelWorld for QuadTreeVoxelWorld {
                     } else {
                         None
                     },
-                    mesh: mesh.0,
+                    meshes: mesh.0,
+                    bounds: ChunkBounds::chunk(parent_pos, chunk_lod, lod_position, min_height),
                 }),
             },
             generate_above: mesh.1,
@@ -0,0 +1,386 @@
+use crate::world_generation::chunk_generation::voxel_generation::get_terrain_noise;
+use crate::world_generation::chunk_generation::{BlockType, CacheTaskPool, VOXEL_SIZE};
+use crate::world_generation::chunk_loading::country_cache::{CountryCache, COUNTRY_SIZE};
+use crate::world_generation::chunk_loading::country_layout::CountryPalette;
+use crate::world_generation::generation_options::{GenerationOptions, GenerationOptionsResource};
+use crate::world_generation::voxel_world::ChunkLod;
+use bevy::app::{App, Plugin, Update};
+use bevy::prelude::{
+    Assets, Commands, Component, Entity, Handle, IVec2, Image, Query, Res, ResMut, Resource,
+};
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::tasks::Task;
+use futures_lite::future;
+use image::RgbaImage;
+use noise::NoiseFn;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Every map pixel is one terrain sample at this LOD.
+pub const MAP_LOD: ChunkLod = ChunkLod::TwoFiftySix;
+/// Map tiles cover the grid cell of one country.
+pub const MAP_TILE_SIZE: usize = COUNTRY_SIZE / MAP_LOD.multiplier_i32() as usize;
+
+const MAX_MAP_TASKS: usize = 4;
+
+const BORDER_COLOR: [u8; 4] = [40, 30, 30, 255];
+const CITY_COLOR: [u8; 4] = [200, 40, 40, 255];
+const CITY_RADIUS: i32 = 2;
+
+pub struct WorldMapPlugin;
+
+impl Plugin for WorldMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WorldMap>()
+            .add_systems(Update, (start_map_tile_tasks, set_generated_map_tiles));
+    }
+}
+
+/// Top-down map images of the current seed, one per country grid cell. Tiles are only generated
+/// once they are requested.
+#[derive(Resource, Default)]
+pub struct WorldMap {
+    seed: u64,
+    tiles: HashMap<IVec2, Option<Handle<Image>>>,
+    requested: HashSet<IVec2>,
+}
+
+impl WorldMap {
+    /// Queues the tile of `country` for generation, unless it is already generated or generating.
+    pub fn request(&mut self, country: IVec2) {
+        if !self.tiles.contains_key(&country) {
+            self.requested.insert(country);
+        }
+    }
+
+    pub fn tiles(&self) -> impl Iterator<Item = (IVec2, &Handle<Image>)> {
+        self.tiles
+            .iter()
+            .filter_map(|(country, tile)| Some((*country, tile.as_ref()?)))
+    }
+}
+
+#[derive(Component)]
+pub struct MapTileTask(pub Task<MapTile>, pub u64);
+
+/// RGBA pixels of one map tile, rows go from north to south.
+pub struct MapTile {
+    pub country: IVec2,
+    pub pixels: Vec<u8>,
+}
+
+impl MapTile {
+    pub fn generate(country: IVec2, generation_options: &GenerationOptions) -> Self {
+        Self {
+            country,
+            pixels: render_area(
+                generation_options,
+                &Self::country_cache(country, generation_options),
+                country * COUNTRY_SIZE as i32,
+                MAP_LOD,
+                MAP_TILE_SIZE,
+            ),
+        }
+    }
+
+    /// Roads crossing a grid cell link capitals of the cell or its direct neighbours.
+    fn country_cache(country: IVec2, generation_options: &GenerationOptions) -> CountryCache {
+        let country_caches = (-1..=1)
+            .flat_map(|x| (-1..=1).map(move |z| country + IVec2::new(x, z)))
+            .map(|key| {
+                generation_options
+                    .country_cache
+                    .try_get_cache_entry(key, generation_options)
+                    .unwrap_or_else(|_| {
+                        Arc::new(CountryCache::without_paths(key, generation_options))
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        country_caches[0].merged(country_caches[1..].iter().map(Arc::as_ref))
+    }
+
+    pub fn to_image(&self) -> Image {
+        map_image(self.pixels.clone(), MAP_TILE_SIZE)
+    }
+}
+
+/// Renders `size` by `size` RGBA pixels starting at the voxel position `origin`, one terrain sample
+/// at `lod` per pixel. Roads are taken from `country_cache`.
+pub fn render_area(
+    generation_options: &GenerationOptions,
+    country_cache: &CountryCache,
+    origin: IVec2,
+    lod: ChunkLod,
+    size: usize,
+) -> Vec<u8> {
+    let layout = &generation_options.country_layout;
+    let terrain_noise = get_terrain_noise(lod, generation_options);
+    let step = lod.multiplier_i32();
+
+    let voxel_position = |x: usize, z: usize| origin + IVec2::new(x as i32, z as i32) * step;
+
+    // One extra row and column, so borders and slopes can be found at the edges.
+    let samples = size + 1;
+    let mut heights = vec![0f32; samples * samples];
+    let mut countries = vec![IVec2::ZERO; samples * samples];
+    for x in 0..samples {
+        for z in 0..samples {
+            let position = voxel_position(x, z);
+            heights[x * samples + z] =
+                terrain_noise.get(position.as_dvec2().to_array()) as f32 * lod.multiplier_f32();
+            countries[x * samples + z] = layout.country_at(position);
+        }
+    }
+
+    let mut palettes = HashMap::new();
+    let mut palette = |country: IVec2| -> CountryPalette {
+        *palettes
+            .entry(country)
+            .or_insert_with(|| layout.palette(country))
+    };
+
+    let mut pixels = vec![0u8; size * size * 4];
+    for x in 0..size {
+        for z in 0..size {
+            let index = x * samples + z;
+            let height = heights[index];
+            let slope_x = heights[index + samples] - height;
+            let slope_z = heights[index + 1] - height;
+            let steepness = slope_x.abs().max(slope_z.abs()) / step as f32;
+
+            let country = countries[index];
+            let color = if country != countries[index + samples] || country != countries[index + 1]
+            {
+                BORDER_COLOR
+            } else {
+                let block = if steepness > 0.8 {
+                    palette(country).stone
+                } else if height > 3500. / VOXEL_SIZE {
+                    BlockType::Snow
+                } else {
+                    palette(country).grass
+                };
+
+                // Lit from the north west.
+                let light = (1. - (slope_x + slope_z) / step as f32 * 0.5).clamp(0.5, 1.3);
+                shade(block, light)
+            };
+
+            set_pixel(&mut pixels, size, IVec2::new(x as i32, z as i32), color);
+        }
+    }
+
+    let to_pixel = |position: IVec2| (position - origin).div_euclid(IVec2::splat(step));
+
+    for path in country_cache.paths() {
+        for line in &path.lines {
+            let color = shade(palette(layout.country_at(line.start)).path, 0.8);
+            let segments = (line.estimated_length / step as f32 * 2.).ceil().max(1.) as usize;
+            for segment in 0..=segments {
+                let point = line.lerp_on_spline(segment as f32 / segments as f32);
+                set_pixel(&mut pixels, size, to_pixel(point.as_ivec2()), color);
+            }
+        }
+    }
+
+    let min_country = origin.div_euclid(IVec2::splat(COUNTRY_SIZE as i32)) - 1;
+    let max_country = voxel_position(size, size).div_euclid(IVec2::splat(COUNTRY_SIZE as i32)) + 1;
+    for x in min_country.x..=max_country.x {
+        for z in min_country.y..=max_country.y {
+            let capital = to_pixel(layout.capital(IVec2::new(x, z)));
+            for offset_x in -CITY_RADIUS..=CITY_RADIUS {
+                for offset_z in -CITY_RADIUS..=CITY_RADIUS {
+                    let pixel = capital + IVec2::new(offset_x, offset_z);
+                    set_pixel(&mut pixels, size, pixel, CITY_COLOR);
+                }
+            }
+        }
+    }
+
+    pixels
+}
+
+pub fn map_image(pixels: Vec<u8>, size: usize) -> Image {
+    Image::new(
+        Extent3d {
+            width: size as u32,
+            height: size as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        pixels,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+}
+
+fn shade(block: BlockType, light: f32) -> [u8; 4] {
+    let color = block.get_color();
+    [
+        (color[0].min(1.) * light * 255.).clamp(0., 255.) as u8,
+        (color[1].min(1.) * light * 255.).clamp(0., 255.) as u8,
+        (color[2].min(1.) * light * 255.).clamp(0., 255.) as u8,
+        255,
+    ]
+}
+
+fn set_pixel(pixels: &mut [u8], size: usize, pixel: IVec2, color: [u8; 4]) {
+    if pixel.x < 0 || pixel.y < 0 || pixel.x >= size as i32 || pixel.y >= size as i32 {
+        return;
+    }
+
+    let index = (pixel.y as usize * size + pixel.x as usize) * 4;
+    pixels[index..index + 4].copy_from_slice(&color);
+}
+
+/// Renders the countries from `min` to `max`, inclusive on both ends, into one image.
+/// Runs on the CPU only, so it can be used without a window.
+pub fn render_map(generation_options: &GenerationOptions, min: IVec2, max: IVec2) -> RgbaImage {
+    let tiles = (min.x..=max.x)
+        .flat_map(|x| (min.y..=max.y).map(move |z| IVec2::new(x, z)))
+        .map(|country| MapTile::generate(country, generation_options))
+        .collect::<Vec<_>>();
+
+    stitch_tiles(&tiles, min, max)
+}
+
+pub fn export_map_png(
+    generation_options: &GenerationOptions,
+    min: IVec2,
+    max: IVec2,
+    path: impl AsRef<Path>,
+) -> image::ImageResult<()> {
+    render_map(generation_options, min, max).save(path)
+}
+
+fn stitch_tiles(tiles: &[MapTile], min: IVec2, max: IVec2) -> RgbaImage {
+    let size = (max - min + 1) * MAP_TILE_SIZE as i32;
+    let mut image = RgbaImage::new(size.x as u32, size.y as u32);
+
+    for tile in tiles {
+        let offset = (tile.country - min) * MAP_TILE_SIZE as i32;
+        for (index, pixel) in tile.pixels.chunks_exact(4).enumerate() {
+            let x = offset.x + (index % MAP_TILE_SIZE) as i32;
+            let y = offset.y + (index / MAP_TILE_SIZE) as i32;
+            image.put_pixel(
+                x as u32,
+                y as u32,
+                image::Rgba([pixel[0], pixel[1], pixel[2], pixel[3]]),
+            );
+        }
+    }
+
+    image
+}
+
+fn start_map_tile_tasks(
+    mut commands: Commands,
+    mut world_map: ResMut<WorldMap>,
+    tasks: Query<&MapTileTask>,
+    cache_task_pool: Res<CacheTaskPool>,
+    generation_options: Res<GenerationOptionsResource>,
+) {
+    if world_map.seed != generation_options.0.seed {
+        *world_map = WorldMap {
+            seed: generation_options.0.seed,
+            ..Default::default()
+        };
+    }
+
+    // Requests that don't fit are dropped, the map screen requests them again next frame.
+    let free_tasks = MAX_MAP_TASKS.saturating_sub(tasks.iter().len());
+    let requested = world_map
+        .requested
+        .drain()
+        .take(free_tasks)
+        .collect::<Vec<_>>();
+
+    for country in requested {
+        let generation_options = Arc::clone(&generation_options.0);
+        let seed = generation_options.seed;
+        commands.spawn(MapTileTask(
+            cache_task_pool
+                .0
+                .spawn(async move { MapTile::generate(country, &generation_options) }),
+            seed,
+        ));
+        world_map.tiles.insert(country, None);
+    }
+}
+
+fn set_generated_map_tiles(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &mut MapTileTask)>,
+    mut world_map: ResMut<WorldMap>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    for (entity, mut task) in &mut tasks {
+        let Some(tile) = future::block_on(future::poll_once(&mut task.0)) else {
+            continue;
+        };
+
+        if task.1 == world_map.seed {
+            let image = images.add(tile.to_image());
+            world_map.tiles.insert(tile.country, Some(image));
+        }
+        commands.entity(entity).despawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageFormat;
+    use std::io::Cursor;
+
+    fn filled_tile(country: IVec2, color: [u8; 4]) -> MapTile {
+        MapTile {
+            country,
+            pixels: color.repeat(MAP_TILE_SIZE * MAP_TILE_SIZE),
+        }
+    }
+
+    #[test]
+    fn tiles_are_stitched_at_their_country() {
+        let red = [255, 0, 0, 255];
+        let blue = [0, 0, 255, 255];
+        let tiles = [
+            filled_tile(IVec2::new(-1, 3), red),
+            filled_tile(IVec2::new(0, 3), blue),
+        ];
+
+        let image = stitch_tiles(&tiles, IVec2::new(-1, 3), IVec2::new(0, 3));
+
+        assert_eq!(image.width() as usize, MAP_TILE_SIZE * 2);
+        assert_eq!(image.height() as usize, MAP_TILE_SIZE);
+        assert_eq!(image.get_pixel(0, 0).0, red);
+        assert_eq!(image.get_pixel(MAP_TILE_SIZE as u32 - 1, 10).0, red);
+        assert_eq!(image.get_pixel(MAP_TILE_SIZE as u32, 10).0, blue);
+    }
+
+    #[test]
+    fn png_export_round_trips() {
+        let mut tile = filled_tile(IVec2::ZERO, [10, 20, 30, 255]);
+        set_pixel(
+            &mut tile.pixels,
+            MAP_TILE_SIZE,
+            IVec2::new(5, 7),
+            CITY_COLOR,
+        );
+        let image = stitch_tiles(&[tile], IVec2::ZERO, IVec2::ZERO);
+
+        let mut png = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let decoded = image::load_from_memory_with_format(&png, ImageFormat::Png)
+            .unwrap()
+            .to_rgba8();
+
+        assert_eq!(decoded, image);
+        assert_eq!(decoded.get_pixel(5, 7).0, CITY_COLOR);
+    }
+}